@@ -23,6 +23,8 @@ __all__ = [
     "validate_pii_config",
     "convert_datascrubbing_config",
     "pii_strip_event",
+    "pii_explain",
+    "compute_grouping_hashes",
     "VALID_PLATFORMS",
 ]
 
@@ -202,6 +204,32 @@ def pii_strip_event(config, event):
     return json.loads(decode_str(raw_rv, free=True))
 
 
+def pii_explain(config, event):
+    """
+    Explain which rules of a PII config apply to an event, without modifying it.
+
+    Returns a list of rule applications with the path, the matching selector,
+    the rule id, the redaction method and the original and redacted values.
+    """
+    raw_config = encode_str(json.dumps(config))
+    raw_event = encode_str(json.dumps(event))
+    raw_rv = rustcall(lib.relay_pii_explain, raw_config, raw_event)
+    return json.loads(decode_str(raw_rv, free=True))
+
+
+def compute_grouping_hashes(config_id, event):
+    """
+    Compute the grouping hashes of an event for a grouping config id.
+
+    Returns the strategy, the hashed components and the hashes. Raises
+    `GroupingErrorUnknownConfig` if the grouping config is not supported.
+    """
+    raw_config_id = encode_str(config_id)
+    raw_event = encode_str(json.dumps(event))
+    raw_rv = rustcall(lib.relay_compute_grouping_hashes, raw_config_id, raw_event)
+    return json.loads(decode_str(raw_rv, free=True))
+
+
 def parse_release(release):
     return json.loads(
         decode_str(rustcall(lib.relay_parse_release, encode_str(release)), free=True)
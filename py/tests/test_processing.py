@@ -198,6 +198,35 @@ def test_pii_strip_event():
     assert sentry_relay.pii_strip_event({}, event) == event
 
 
+def test_pii_explain():
+    config = {"applications": {"$string": ["@email:replace"]}}
+    event = {"user": {"email": "mail@example.org"}}
+    assert sentry_relay.pii_explain(config, event) == [
+        {
+            "path": "user.email",
+            "selector": "$string",
+            "rule_id": "@email:replace",
+            "redaction": {"method": "replace", "text": "[email]"},
+            "original": "mail@example.org",
+            "redacted": "[email]",
+        }
+    ]
+
+
+def test_compute_grouping_hashes():
+    event = {"logentry": {"message": "Hello %s"}, "fingerprint": ["{{ default }}", "x"]}
+    result = sentry_relay.compute_grouping_hashes("relay:v1", event)
+    assert result["config"] == "relay:v1"
+    assert result["strategy"] == "message"
+    assert result["components"] == ["message:Hello %s", "fingerprint:x"]
+    assert len(result["hashes"]) == 1
+
+
+def test_compute_grouping_hashes_unknown_config():
+    with pytest.raises(sentry_relay.GroupingErrorUnknownConfig):
+        sentry_relay.compute_grouping_hashes("newstyle:2019-10-29", {})
+
+
 def test_parse_release():
     parsed = sentry_relay.parse_release("org.example.FooApp@1.0rc1+20200101100")
     assert parsed == {
@@ -41,6 +41,7 @@ enum RelayErrorCode {
   RELAY_ERROR_CODE_INVALID_RELEASE_ERROR_TOO_LONG = 3001,
   RELAY_ERROR_CODE_INVALID_RELEASE_ERROR_RESTRICTED_NAME = 3002,
   RELAY_ERROR_CODE_INVALID_RELEASE_ERROR_BAD_CHARACTERS = 3003,
+  RELAY_ERROR_CODE_GROUPING_ERROR_UNKNOWN_CONFIG = 4001,
 };
 typedef uint32_t RelayErrorCode;
 
@@ -132,6 +133,11 @@ typedef struct {
  */
 void relay_buf_free(RelayBuf *b);
 
+/**
+ * Compute the grouping hashes of an event for a grouping config id.
+ */
+RelayStr relay_compute_grouping_hashes(const RelayStr *config_id, const RelayStr *event);
+
 /**
  * Convert an old datascrubbing config to the new PII config format.
  */
@@ -200,6 +206,11 @@ bool relay_is_glob_match(const RelayBuf *value,
 
 RelayStr relay_parse_release(const RelayStr *value);
 
+/**
+ * Explain which rules of a PII config apply to an event, without modifying it.
+ */
+RelayStr relay_pii_explain(const RelayStr *config, const RelayStr *event);
+
 /**
  * Scrub an event using new PII stripping config.
  */
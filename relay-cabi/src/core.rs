@@ -10,6 +10,7 @@ use sentry_release_parser::InvalidRelease;
 
 use relay_auth::{KeyParseError, UnpackError};
 use relay_common::Uuid;
+use relay_general::grouping::GroupingError;
 use relay_general::store::GeoIpError;
 use relay_general::types::ProcessingAction;
 
@@ -41,6 +42,9 @@ pub enum RelayErrorCode {
     InvalidReleaseErrorTooLong = 3001,
     InvalidReleaseErrorRestrictedName = 3002,
     InvalidReleaseErrorBadCharacters = 3003,
+
+    // relay_general::grouping::GroupingError
+    GroupingErrorUnknownConfig = 4001,
 }
 
 impl RelayErrorCode {
@@ -77,6 +81,11 @@ impl RelayErrorCode {
                     _ => RelayErrorCode::Unknown,
                 };
             }
+            if let Some(err) = cause.downcast_ref::<GroupingError>() {
+                return match err {
+                    GroupingError::UnknownConfig(_) => RelayErrorCode::GroupingErrorUnknownConfig,
+                };
+            }
             if let Some(err) = cause.downcast_ref::<InvalidRelease>() {
                 return match err {
                     InvalidRelease::TooLong => RelayErrorCode::InvalidReleaseErrorTooLong,
@@ -8,7 +8,8 @@ use std::slice;
 
 use json_forensics;
 use relay_common::{glob_match_bytes, GlobOptions};
-use relay_general::pii::{DataScrubbingConfig, PiiConfig, PiiProcessor};
+use relay_general::grouping::{compute_hashes, GroupingVersion};
+use relay_general::pii::{explain_pii, DataScrubbingConfig, PiiConfig, PiiProcessor};
 use relay_general::processor::{process_value, split_chunks, ProcessingState};
 use relay_general::protocol::{Event, VALID_PLATFORMS};
 use relay_general::store::{GeoIpLookup, StoreConfig, StoreProcessor};
@@ -179,6 +180,35 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Explain which rules of a PII config apply to an event, without modifying it.
+    unsafe fn relay_pii_explain(
+        config: *const RelayStr,
+        event: *const RelayStr
+    ) -> Result<RelayStr> {
+        let config = serde_json::from_str::<PiiConfig>((*config).as_str())?;
+        let compiled = config.compiled();
+
+        let event = Annotated::<Event>::from_json((*event).as_str())?;
+        let explanations = explain_pii(&compiled, &event)?;
+
+        Ok(RelayStr::from_string(serde_json::to_string(&explanations)?))
+    }
+}
+
+ffi_fn! {
+    /// Compute the grouping hashes of an event for a grouping config id.
+    unsafe fn relay_compute_grouping_hashes(
+        config_id: *const RelayStr,
+        event: *const RelayStr
+    ) -> Result<RelayStr> {
+        let version = GroupingVersion::from_config_id((*config_id).as_str())?;
+        let event = Annotated::<Event>::from_json((*event).as_str())?;
+        let result = compute_hashes(event.value().unwrap_or(&Event::default()), version);
+        Ok(RelayStr::from_string(serde_json::to_string(&result)?))
+    }
+}
+
 ffi_fn! {
     unsafe fn relay_test_panic() -> Result<()> {
         panic!("this is a test panic")
@@ -99,6 +99,14 @@ impl From<String> for Glob {
 
 impl_str_serialization!(Glob, "a glob pattern");
 
+/// Returns `true` if the value is `false`.
+///
+/// Use this with `#[serde(skip_serializing_if = "is_false")]` to omit flags that are not set.
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn is_false(val: &bool) -> bool {
+    !val
+}
+
 /// Helper for glob matching
 #[derive(Debug)]
 pub struct GlobMatcher<T> {
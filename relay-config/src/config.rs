@@ -301,6 +301,12 @@ struct Limits {
     max_pending_connections: i32,
     /// The maximum number of open connections to Relay.
     max_connections: usize,
+    /// Enforces project quotas in memory on Relays without processing.
+    ///
+    /// Quotas are counted separately by every Relay instance and are not reported to the upstream,
+    /// so this is intended for static Relays that cannot rely on the upstream to enforce quotas.
+    /// Defaults to `false`.
+    local_quotas: bool,
 }
 
 impl Default for Limits {
@@ -318,6 +324,7 @@ impl Default for Limits {
             max_connection_rate: 256,
             max_pending_connections: 2048,
             max_connections: 25_000,
+            local_quotas: false,
         }
     }
 }
@@ -489,6 +496,12 @@ pub struct Processing {
     /// GeoIp DB file location.
     #[serde(default)]
     pub geoip_path: Option<PathBuf>,
+    /// Directory with source maps of JavaScript releases.
+    ///
+    /// Source maps are looked up at `<release>/<path>.map`, where `path` is the path of the
+    /// minified file's URL. The host of the URL is ignored.
+    #[serde(default)]
+    pub sourcemaps_path: Option<PathBuf>,
     /// Maximum future timestamp of ingested events.
     #[serde(default = "default_max_secs_in_future")]
     pub max_secs_in_future: u32,
@@ -520,6 +533,7 @@ impl Default for Processing {
         Self {
             enabled: false,
             geoip_path: None,
+            sourcemaps_path: None,
             max_secs_in_future: 0,
             max_secs_in_past: 0,
             kafka_config: Vec::new(),
@@ -915,6 +929,11 @@ impl Config {
         self.values.limits.max_pending_connections
     }
 
+    /// Returns `true` if Relays without processing enforce project quotas in memory.
+    pub fn local_quotas(&self) -> bool {
+        self.values.limits.local_quotas
+    }
+
     /// Returns the number of cores to use for thread pools.
     pub fn cpu_concurrency(&self) -> usize {
         self.values.limits.max_thread_count
@@ -949,6 +968,11 @@ impl Config {
         self.values.processing.geoip_path.as_deref()
     }
 
+    /// The directory to look up source maps of JavaScript events, if configured.
+    pub fn sourcemaps_path(&self) -> Option<&Path> {
+        self.values.processing.sourcemaps_path.as_deref()
+    }
+
     /// Maximum future timestamp of ingested events.
     pub fn max_secs_in_future(&self) -> i64 {
         self.values.processing.max_secs_in_future.into()
@@ -126,6 +126,9 @@ pub enum FilterStatKey {
 
     /// Filtered due to invalid CSP policy.
     InvalidCsp,
+
+    /// Filtered due to disallowed NEL report.
+    InvalidNel,
 }
 
 // An event grouped to a removed group.
@@ -152,6 +155,7 @@ impl FilterStatKey {
             FilterStatKey::Localhost => "localhost",
             FilterStatKey::WebCrawlers => "web-crawlers",
             FilterStatKey::InvalidCsp => "invalid-csp",
+            FilterStatKey::InvalidNel => "invalid-nel",
         }
     }
 }
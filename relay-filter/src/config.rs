@@ -115,6 +115,25 @@ impl CspFilterConfig {
     }
 }
 
+/// Configuration for the NEL filter.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NelFilterConfig {
+    /// Disallowed sources for the URLs of failed requests in NEL reports.
+    #[serde(default)]
+    pub disallowed_sources: Vec<String>,
+    /// List of network error type patterns that will be filtered.
+    #[serde(default)]
+    pub error_types: GlobPatterns,
+}
+
+impl NelFilterConfig {
+    /// Returns true if no configuration for this filter is given.
+    pub fn is_empty(&self) -> bool {
+        self.disallowed_sources.is_empty() && self.error_types.is_empty()
+    }
+}
+
 /// Configuration for the error messages filter.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ErrorMessagesFilterConfig {
@@ -181,6 +200,10 @@ pub struct FiltersConfig {
     #[serde(default, skip_serializing_if = "CspFilterConfig::is_empty")]
     pub csp: CspFilterConfig,
 
+    /// Configuration for the NEL filter.
+    #[serde(default, skip_serializing_if = "NelFilterConfig::is_empty")]
+    pub nel: NelFilterConfig,
+
     /// Configuration for the Error Messages filter.
     #[serde(default, skip_serializing_if = "ErrorMessagesFilterConfig::is_empty")]
     pub error_messages: ErrorMessagesFilterConfig,
@@ -205,6 +228,7 @@ impl FiltersConfig {
             && self.client_ips.is_empty()
             && self.web_crawlers.is_empty()
             && self.csp.is_empty()
+            && self.nel.is_empty()
             && self.error_messages.is_empty()
             && self.legacy_browsers.is_empty()
             && self.localhost.is_empty()
@@ -233,6 +257,10 @@ mod tests {
             csp: CspFilterConfig {
                 disallowed_sources: [],
             },
+            nel: NelFilterConfig {
+                disallowed_sources: [],
+                error_types: [],
+            },
             error_messages: ErrorMessagesFilterConfig {
                 patterns: [],
             },
@@ -268,6 +296,10 @@ mod tests {
             csp: CspFilterConfig {
                 disallowed_sources: vec!["https://*".to_string()],
             },
+            nel: NelFilterConfig {
+                disallowed_sources: vec!["https://*.example.com".to_string()],
+                error_types: GlobPatterns::new(vec!["abandoned".to_string()]),
+            },
             error_messages: ErrorMessagesFilterConfig {
                 patterns: GlobPatterns::new(vec!["Panic".to_string()]),
             },
@@ -299,6 +331,14 @@ mod tests {
               "https://*"
             ]
           },
+          "nel": {
+            "disallowedSources": [
+              "https://*.example.com"
+            ],
+            "errorTypes": [
+              "abandoned"
+            ]
+          },
           "errorMessages": {
             "patterns": [
               "Panic"
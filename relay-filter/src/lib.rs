@@ -20,6 +20,7 @@ mod csp;
 mod error_messages;
 mod legacy_browsers;
 mod localhost;
+mod nel;
 mod releases;
 mod web_crawlers;
 
@@ -43,6 +44,7 @@ pub fn should_filter(
     // when making changes to this order.
 
     csp::should_filter(event, &config.csp)?;
+    nel::should_filter(event, &config.nel)?;
     client_ips::should_filter(client_ip, &config.client_ips)?;
     releases::should_filter(event, &config.releases)?;
     error_messages::should_filter(event, &config.error_messages)?;
@@ -0,0 +1,105 @@
+//! Implements event filtering for events originating from Network Error Logging (NEL) reports.
+//!
+//! Events originating from a NEL report can be filtered based on the URL of the failed request
+//! and the type of the network error.
+
+use relay_general::protocol::{Event, EventType};
+
+use crate::csp::SchemeDomainPort;
+use crate::{matches_any_origin, FilterStatKey, NelFilterConfig};
+
+/// Filters NEL events based on disallowed sources and error types.
+pub fn should_filter(event: &Event, config: &NelFilterConfig) -> Result<(), FilterStatKey> {
+    if config.is_empty() || event.ty.value() != Some(&EventType::Nel) {
+        return Ok(());
+    }
+
+    let nel = match event.nel.value() {
+        Some(nel) => nel,
+        None => return Ok(()),
+    };
+
+    if let Some(ty) = nel.ty.as_str() {
+        if config.error_types.is_match(ty) {
+            return Err(FilterStatKey::InvalidNel);
+        }
+    }
+
+    let disallowed_sources: Vec<SchemeDomainPort> = config
+        .disallowed_sources
+        .iter()
+        .map(|origin| SchemeDomainPort::from(origin.as_str()))
+        .collect();
+
+    if matches_any_origin(nel.url.as_str(), &disallowed_sources) {
+        return Err(FilterStatKey::InvalidNel);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use relay_general::protocol::Nel;
+    use relay_general::types::Annotated;
+
+    use crate::GlobPatterns;
+
+    fn get_nel_event(url: &str, ty: &str) -> Event {
+        Event {
+            ty: Annotated::from(EventType::Nel),
+            nel: Annotated::from(Nel {
+                url: Annotated::from(url.to_string()),
+                ty: Annotated::from(ty.to_string()),
+                ..Nel::default()
+            }),
+            ..Event::default()
+        }
+    }
+
+    #[test]
+    fn test_filters_disallowed_sources() {
+        let event = get_nel_event("https://known.bad.com/api", "tcp.timed_out");
+        let config = NelFilterConfig {
+            disallowed_sources: vec!["https://known.bad.com".to_string()],
+            error_types: GlobPatterns::default(),
+        };
+
+        assert_eq!(
+            should_filter(&event, &config),
+            Err(FilterStatKey::InvalidNel)
+        );
+    }
+
+    #[test]
+    fn test_filters_error_types() {
+        let event = get_nel_event("https://example.com/", "abandoned");
+        let config = NelFilterConfig {
+            disallowed_sources: vec![],
+            error_types: GlobPatterns::new(vec!["abandoned".to_string(), "dns.*".to_string()]),
+        };
+
+        assert_eq!(
+            should_filter(&event, &config),
+            Err(FilterStatKey::InvalidNel)
+        );
+
+        let event = get_nel_event("https://example.com/", "tcp.reset");
+        assert_eq!(should_filter(&event, &config), Ok(()));
+    }
+
+    #[test]
+    fn test_does_not_filter_other_events() {
+        let mut event = get_nel_event("https://known.bad.com/api", "abandoned");
+        event.ty = Annotated::from(EventType::Default);
+
+        let config = NelFilterConfig {
+            disallowed_sources: vec!["https://known.bad.com".to_string()],
+            error_types: GlobPatterns::new(vec!["abandoned".to_string()]),
+        };
+
+        assert_eq!(should_filter(&event, &config), Ok(()));
+    }
+}
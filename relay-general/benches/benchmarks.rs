@@ -89,6 +89,10 @@ fn bench_store_processor(c: &mut Criterion) {
         remove_other: Some(true),
         user_agent: None,
         sent_at: None,
+        transaction_name_rules: Vec::new(),
+        stacktrace_rules: Vec::new(),
+        write_grouping_hashes: Some(false),
+        collapse_breadcrumbs: Some(false),
     };
 
     let mut processor = StoreProcessor::new(config, None);
@@ -0,0 +1,420 @@
+//! Computation of grouping hashes for events.
+//!
+//! Events with equal grouping hashes belong to the same issue. Hashes are computed from the first
+//! applicable strategy in this order:
+//!
+//!  1. The stack traces of all exceptions, or the stack trace of the event or its only thread.
+//!  2. The type and value of all exceptions.
+//!  3. The log message of the event.
+//!
+//! A custom `fingerprint` replaces these strategies. The special value `{{ default }}` in a
+//! fingerprint is expanded to the components of the default strategy.
+
+use failure::Fail;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::protocol::{Event, Exception, Frame, Stacktrace};
+use crate::types::Annotated;
+
+/// The length of grouping hashes in hex characters.
+const HASH_LENGTH: usize = 32;
+
+/// An error returned when computing grouping hashes.
+#[derive(Debug, Fail, PartialEq)]
+pub enum GroupingError {
+    /// The grouping config is not supported by this version of Relay.
+    #[fail(display = "unknown grouping config: {}", _0)]
+    UnknownConfig(String),
+}
+
+/// A version of the grouping algorithm.
+///
+/// Each version corresponds to a grouping config id. Hashes must remain stable within a version.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GroupingVersion {
+    /// Stack trace, exception and message strategies.
+    V1,
+}
+
+impl GroupingVersion {
+    /// Returns the grouping version for a grouping config id.
+    pub fn from_config_id(id: &str) -> Result<Self, GroupingError> {
+        match id {
+            "relay:v1" => Ok(GroupingVersion::V1),
+            _ => Err(GroupingError::UnknownConfig(id.to_owned())),
+        }
+    }
+
+    /// Returns the grouping config id of this version.
+    pub fn config_id(self) -> &'static str {
+        match self {
+            GroupingVersion::V1 => "relay:v1",
+        }
+    }
+}
+
+/// The strategy that was used to compute grouping hashes.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupingStrategy {
+    /// A custom fingerprint without the default components.
+    Fingerprint,
+    /// Stack frames of exceptions or threads.
+    Stacktrace,
+    /// Types and values of exceptions.
+    Exception,
+    /// The log message.
+    Message,
+    /// None of the strategies applied.
+    Fallback,
+}
+
+/// The result of computing grouping hashes for an event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GroupingResult {
+    /// The grouping config id that was used.
+    pub config: &'static str,
+    /// The strategy that determined the components.
+    pub strategy: GroupingStrategy,
+    /// The values that were hashed.
+    pub components: Vec<String>,
+    /// The grouping hashes of the event.
+    pub hashes: Vec<String>,
+}
+
+/// Returns whether a fingerprint value is the `{{ default }}` placeholder.
+fn is_default_placeholder(value: &str) -> bool {
+    value.starts_with("{{")
+        && value.ends_with("}}")
+        && value[2..value.len() - 2].trim() == "default"
+}
+
+/// Returns the frames of a stack trace that contribute to grouping.
+///
+/// If any frame is in-app, only in-app frames contribute.
+fn get_grouping_frames(stacktrace: &Stacktrace) -> Vec<&Frame> {
+    let frames: Vec<&Frame> = match stacktrace.frames.value() {
+        Some(frames) => frames.iter().filter_map(|frame| frame.value()).collect(),
+        None => return Vec::new(),
+    };
+
+    if frames
+        .iter()
+        .any(|frame| frame.in_app.value() == Some(&true))
+    {
+        frames
+            .into_iter()
+            .filter(|frame| frame.in_app.value() == Some(&true))
+            .collect()
+    } else {
+        frames
+    }
+}
+
+/// Returns the grouping component of a frame.
+///
+/// The frame is identified by its module or file name and its function. Frames without function
+/// fall back to their context line or line number.
+fn frame_component(frame: &Frame) -> String {
+    let location = frame.module.as_str().or_else(|| {
+        let filename = frame.filename.value()?.as_str();
+        filename.rsplit(|c| c == '/' || c == '\\').next()
+    });
+
+    let function = match frame.function.as_str() {
+        Some(function) => function.to_owned(),
+        None => match frame.context_line.as_str() {
+            Some(line) => line.trim().to_owned(),
+            None => frame.lineno.value().map(u64::to_string).unwrap_or_default(),
+        },
+    };
+
+    format!("frame:{}:{}", location.unwrap_or_default(), function)
+}
+
+/// Returns the stack trace components of a stack trace, if it has any frames.
+fn stacktrace_components(stacktrace: Option<&Stacktrace>) -> Vec<String> {
+    stacktrace
+        .map(get_grouping_frames)
+        .unwrap_or_default()
+        .into_iter()
+        .map(frame_component)
+        .collect()
+}
+
+/// Returns the components of the stack trace strategy.
+fn get_stacktrace_components(event: &Event, exceptions: &[&Exception]) -> Vec<String> {
+    let mut components = Vec::new();
+
+    if !exceptions.is_empty() {
+        for exception in exceptions {
+            let frames = stacktrace_components(exception.stacktrace.value());
+            if !frames.is_empty() {
+                components.push(format!(
+                    "type:{}",
+                    exception.ty.as_str().unwrap_or_default()
+                ));
+                components.extend(frames);
+            }
+        }
+
+        return components;
+    }
+
+    let thread_stacktrace = event
+        .threads
+        .value()
+        .and_then(|threads| threads.values.value())
+        .filter(|threads| threads.len() == 1)
+        .and_then(|threads| threads.first()?.value()?.stacktrace.value());
+
+    stacktrace_components(event.stacktrace.value().or(thread_stacktrace))
+}
+
+/// Returns the components of the exception strategy.
+fn get_exception_components(exceptions: &[&Exception]) -> Vec<String> {
+    let mut components = Vec::new();
+
+    for exception in exceptions {
+        if let Some(ty) = exception.ty.as_str() {
+            components.push(format!("type:{}", ty));
+        }
+        if let Some(value) = exception.value.value() {
+            components.push(format!("value:{}", value.as_str()));
+        }
+    }
+
+    components
+}
+
+/// Returns the components of the message strategy.
+fn get_message_components(event: &Event) -> Vec<String> {
+    let logentry = match event.logentry.value() {
+        Some(logentry) => logentry,
+        None => return Vec::new(),
+    };
+
+    match logentry
+        .message
+        .as_str()
+        .or_else(|| logentry.formatted.as_str())
+    {
+        Some(message) => vec![format!("message:{}", message)],
+        None => Vec::new(),
+    }
+}
+
+/// Returns the components of the first applicable default strategy.
+fn get_default_components(event: &Event) -> (GroupingStrategy, Vec<String>) {
+    let exceptions: Vec<&Exception> = event
+        .exceptions
+        .value()
+        .and_then(|exceptions| exceptions.values.value())
+        .map(|values| values.iter().filter_map(|e| e.value()).collect())
+        .unwrap_or_default();
+
+    let components = get_stacktrace_components(event, &exceptions);
+    if !components.is_empty() {
+        return (GroupingStrategy::Stacktrace, components);
+    }
+
+    let components = get_exception_components(&exceptions);
+    if !components.is_empty() {
+        return (GroupingStrategy::Exception, components);
+    }
+
+    let components = get_message_components(event);
+    if !components.is_empty() {
+        return (GroupingStrategy::Message, components);
+    }
+
+    (GroupingStrategy::Fallback, Vec::new())
+}
+
+/// Computes the hash of grouping components.
+fn hash_components(components: &[String]) -> String {
+    let mut hasher = Sha1::new();
+    for component in components {
+        hasher.input(component.as_bytes());
+        hasher.input(b"\0");
+    }
+
+    let mut hash = format!("{:x}", hasher.result());
+    hash.truncate(HASH_LENGTH);
+    hash
+}
+
+/// Computes the grouping hashes of an event.
+pub fn compute_hashes(event: &Event, version: GroupingVersion) -> GroupingResult {
+    let fingerprint = event
+        .fingerprint
+        .value()
+        .filter(|fingerprint| !fingerprint.is_empty());
+
+    let (strategy, components) = match fingerprint {
+        Some(fingerprint) if !fingerprint.iter().all(|v| is_default_placeholder(v)) => {
+            let mut components = Vec::new();
+            let mut strategy = GroupingStrategy::Fingerprint;
+
+            for value in fingerprint.iter() {
+                if is_default_placeholder(value) {
+                    let (default_strategy, default_components) = get_default_components(event);
+                    strategy = default_strategy;
+                    components.extend(default_components);
+                } else {
+                    components.push(format!("fingerprint:{}", value));
+                }
+            }
+
+            (strategy, components)
+        }
+        _ => get_default_components(event),
+    };
+
+    GroupingResult {
+        config: version.config_id(),
+        strategy,
+        hashes: vec![hash_components(&components)],
+        components,
+    }
+}
+
+/// Computes grouping hashes and writes them into the event's `hashes`.
+pub fn write_hashes(event: &mut Event, version: GroupingVersion) -> GroupingResult {
+    let result = compute_hashes(event, version);
+    let hashes = result.hashes.iter().cloned().map(Annotated::new).collect();
+    event.hashes.set_value(Some(hashes));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compute(json: &str) -> GroupingResult {
+        let event = Annotated::<Event>::from_json(json).unwrap();
+        compute_hashes(event.value().unwrap(), GroupingVersion::V1)
+    }
+
+    #[test]
+    fn test_unknown_config() {
+        assert_eq_dbg!(
+            GroupingVersion::from_config_id("newstyle:2019-10-29"),
+            Err(GroupingError::UnknownConfig(
+                "newstyle:2019-10-29".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_stacktrace_in_app_frames() {
+        let result = compute(
+            r#"{
+                "exception": {"values": [{
+                    "type": "ValueError",
+                    "value": "invalid literal 42",
+                    "stacktrace": {"frames": [
+                        {"module": "django.core", "function": "dispatch", "in_app": false},
+                        {"module": "app.views", "function": "index", "in_app": true},
+                        {"filename": "/srv/app/utils.py", "lineno": 12, "in_app": true}
+                    ]}
+                }]}
+            }"#,
+        );
+
+        assert_eq_dbg!(result.strategy, GroupingStrategy::Stacktrace);
+        assert_eq_dbg!(
+            result.components,
+            vec![
+                "type:ValueError".to_owned(),
+                "frame:app.views:index".to_owned(),
+                "frame:utils.py:12".to_owned(),
+            ]
+        );
+        assert_eq_dbg!(result.hashes[0].len(), HASH_LENGTH);
+    }
+
+    #[test]
+    fn test_stacktrace_ignores_exception_value() {
+        let first = compute(
+            r#"{"exception": {"values": [{"type": "E", "value": "a", "stacktrace": {"frames": [{"function": "f"}]}}]}}"#,
+        );
+        let second = compute(
+            r#"{"exception": {"values": [{"type": "E", "value": "b", "stacktrace": {"frames": [{"function": "f"}]}}]}}"#,
+        );
+
+        assert_eq_dbg!(first.hashes, second.hashes);
+    }
+
+    #[test]
+    fn test_exception_type_and_value() {
+        let result = compute(r#"{"exception": {"values": [{"type": "E", "value": "boom"}]}}"#);
+
+        assert_eq_dbg!(result.strategy, GroupingStrategy::Exception);
+        assert_eq_dbg!(
+            result.components,
+            vec!["type:E".to_owned(), "value:boom".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_message() {
+        let result =
+            compute(r#"{"logentry": {"message": "Hello %s", "formatted": "Hello World"}}"#);
+
+        assert_eq_dbg!(result.strategy, GroupingStrategy::Message);
+        assert_eq_dbg!(result.components, vec!["message:Hello %s".to_owned()]);
+    }
+
+    #[test]
+    fn test_fallback() {
+        let result = compute("{}");
+        assert_eq_dbg!(result.strategy, GroupingStrategy::Fallback);
+        assert!(result.components.is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint_default() {
+        let default = compute(r#"{"logentry": {"message": "Hello"}}"#);
+        let result =
+            compute(r#"{"logentry": {"message": "Hello"}, "fingerprint": ["{{default}}"]}"#);
+        assert_eq_dbg!(result, default);
+
+        let result = compute(
+            r#"{"logentry": {"message": "Hello"}, "fingerprint": ["{{ default }}", "custom"]}"#,
+        );
+        assert_eq_dbg!(result.strategy, GroupingStrategy::Message);
+        assert_eq_dbg!(
+            result.components,
+            vec!["message:Hello".to_owned(), "fingerprint:custom".to_owned()]
+        );
+        assert_ne!(result.hashes, default.hashes);
+    }
+
+    #[test]
+    fn test_fingerprint_custom() {
+        let result = compute(r#"{"logentry": {"message": "Hello"}, "fingerprint": ["custom"]}"#);
+
+        assert_eq_dbg!(result.strategy, GroupingStrategy::Fingerprint);
+        assert_eq_dbg!(result.components, vec!["fingerprint:custom".to_owned()]);
+    }
+
+    #[test]
+    fn test_write_hashes() {
+        let mut event = Annotated::<Event>::from_json(r#"{"logentry": {"message": "Hello"}}"#)
+            .unwrap()
+            .0
+            .unwrap();
+
+        let result = write_hashes(&mut event, GroupingVersion::V1);
+        let hashes: Vec<_> = event
+            .hashes
+            .value()
+            .unwrap()
+            .iter()
+            .map(|h| h.as_str())
+            .collect();
+        assert_eq_dbg!(hashes, vec![Some(result.hashes[0].as_str())]);
+    }
+}
@@ -15,6 +15,7 @@ mod macros;
 #[macro_use]
 mod testutils;
 
+pub mod grouping;
 pub mod pii;
 pub mod processor;
 pub mod protocol;
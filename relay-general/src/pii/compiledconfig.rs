@@ -3,7 +3,10 @@ use std::collections::BTreeSet;
 
 use crate::pii::builtin::BUILTIN_RULES_MAP;
 use crate::pii::{PiiConfig, Redaction, RuleSpec, RuleType};
-use crate::processor::SelectorSpec;
+use crate::processor::{SelectorPathItem, SelectorSpec};
+
+/// The rule id reported for fields that are redacted because they are not allowlisted.
+const ALLOWLIST_RULE_ID: &str = "@allowlist";
 
 /// A representation of `PiiConfig` that is more (CPU-)efficient for use in `PiiProcessor`. It is
 /// lossy in the sense that it cannot be consumed by downstream relays, so both versions have to be
@@ -11,12 +14,32 @@ use crate::processor::SelectorSpec;
 #[derive(Debug, Clone)]
 pub struct CompiledPiiConfig {
     pub(super) applications: Vec<(SelectorSpec, BTreeSet<RuleRef>)>,
+    /// Path items whose states need field values to evaluate selector predicates.
+    pub(super) predicate_items: Vec<SelectorPathItem>,
+    /// Fields of selected values that are compared by selector predicates.
+    pub(super) predicate_fields: BTreeSet<String>,
+    /// Selector of fields outside the allowlist and the rule to redact them with.
+    pub(super) denied: Option<(SelectorSpec, RuleRef)>,
 }
 
 impl CompiledPiiConfig {
     pub fn new(config: &PiiConfig) -> Self {
         let mut applications = Vec::new();
+        let mut predicate_items = Vec::new();
+        let mut predicate_fields = BTreeSet::new();
+        let mut collect_predicate_items = |selector: &SelectorSpec| {
+            for item in selector.predicate_items() {
+                collect_predicate_fields(item, &mut predicate_fields);
+                let item = item.without_predicates();
+                if !predicate_items.contains(item) {
+                    predicate_items.push(item.clone());
+                }
+            }
+        };
+
         for (selector, rules) in &config.applications {
+            collect_predicate_items(selector);
+
             #[allow(clippy::mutable_key_type)]
             let mut rule_set = BTreeSet::default();
             for rule_id in rules {
@@ -25,7 +48,36 @@ impl CompiledPiiConfig {
             applications.push((selector.clone(), rule_set));
         }
 
-        CompiledPiiConfig { applications }
+        let denied = config.allowlist.as_ref().map(|allowlist| {
+            let allowed = SelectorSpec::Or(allowlist.selectors.clone());
+            collect_predicate_items(&allowed);
+
+            let rule = RuleRef {
+                id: ALLOWLIST_RULE_ID.to_owned(),
+                origin: ALLOWLIST_RULE_ID.to_owned(),
+                ty: RuleType::Anything,
+                redaction: allowlist.redaction.clone(),
+            };
+
+            (SelectorSpec::Not(Box::new(allowed)), rule)
+        });
+
+        CompiledPiiConfig {
+            applications,
+            predicate_items,
+            predicate_fields,
+            denied,
+        }
+    }
+}
+
+/// Collects the fields compared by the predicates of a path item, excluding `$key`.
+fn collect_predicate_fields(item: &SelectorPathItem, fields: &mut BTreeSet<String>) {
+    if let SelectorPathItem::Predicate(ref inner, ref predicate) = *item {
+        if !predicate.is_key() {
+            fields.insert(predicate.field.clone());
+        }
+        collect_predicate_fields(inner, fields);
     }
 }
 
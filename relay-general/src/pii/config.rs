@@ -203,6 +203,18 @@ pub struct Vars {
     pub hash_key: Option<String>,
 }
 
+/// Configuration for deny-by-default PII stripping.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Allowlist {
+    /// Selectors of fields that are retained.
+    #[serde(default)]
+    pub selectors: Vec<SelectorSpec>,
+    /// How to redact fields outside of the allowlist. Defaults to removing them.
+    #[serde(default)]
+    pub redaction: Redaction,
+}
+
 /// A set of named rule configurations.
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct PiiConfig {
@@ -218,6 +230,14 @@ pub struct PiiConfig {
     #[serde(default)]
     pub applications: BTreeMap<SelectorSpec, Vec<String>>,
 
+    /// Fields that are retained in deny-by-default mode.
+    ///
+    /// If set, all strings in fields that may contain PII are redacted unless they match one of
+    /// the allowlisted selectors. Allowlisted fields are never scrubbed, not even by rules in
+    /// `applications`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowlist: Option<Allowlist>,
+
     /// PII config derived from datascrubbing settings.
     ///
     /// Cached because the conversion process is expensive.
@@ -233,10 +253,14 @@ impl PartialEq for PiiConfig {
             rules,
             vars,
             applications,
+            allowlist,
             compiled: _compiled,
         } = &self;
 
-        rules == &other.rules && vars == &other.vars && applications == &other.applications
+        rules == &other.rules
+            && vars == &other.vars
+            && applications == &other.applications
+            && allowlist == &other.allowlist
     }
 }
 
@@ -0,0 +1,122 @@
+//! Dry-run explanations for PII configs.
+use serde::{Serialize, Serializer};
+
+use crate::pii::{CompiledPiiConfig, PiiProcessor, Redaction};
+use crate::processor::{process_value, ProcessValue, ProcessingState, SelectorSpec};
+use crate::types::{to_value, Annotated, ProcessingAction, SkipSerialization, ToValue, Value};
+
+/// Describes a single application of a PII rule to a value.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PiiExplanation {
+    /// The path of the value, as it would be addressed by a selector.
+    pub path: String,
+    /// The selector from the config's applications that matched the path.
+    pub selector: SelectorSpec,
+    /// The rule id as it is reported in remarks.
+    pub rule_id: String,
+    /// The redaction method of the applied rule.
+    pub redaction: Redaction,
+    /// The value before the rule was applied.
+    pub original: Option<Value>,
+    /// The value after the rule was applied, or `None` if it was removed.
+    pub redacted: Option<Value>,
+}
+
+/// Explains which PII rules would apply to a value, without modifying it.
+///
+/// This runs the `PiiProcessor` over a copy of the value and returns one entry for every rule that
+/// changed or removed a value, in processing order.
+pub fn explain_pii<T>(
+    compiled_config: &CompiledPiiConfig,
+    value: &Annotated<T>,
+) -> Result<Vec<PiiExplanation>, ProcessingAction>
+where
+    T: ProcessValue + Clone,
+{
+    let mut value = value.clone();
+    let mut processor = PiiProcessor::explaining(compiled_config);
+    process_value(&mut value, &mut processor, ProcessingState::root())?;
+    Ok(processor.into_explanations())
+}
+
+/// Serializes the payload of a structured value without its meta data.
+struct Payload<'a, T>(&'a T);
+
+impl<T: ToValue> Serialize for Payload<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0
+            .serialize_payload(serializer, SkipSerialization::default())
+    }
+}
+
+/// Converts a structured value into a plain `Value` for reporting.
+pub(super) fn payload_to_value<T: ToValue>(value: &T) -> Option<Value> {
+    to_value(&Payload(value)).ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pii::PiiConfig;
+    use crate::protocol::{Event, ExtraValue, User};
+    use crate::types::Object;
+
+    #[test]
+    fn test_explain_does_not_modify() {
+        let config = PiiConfig::from_json(
+            r##"
+            {
+                "applications": {
+                    "$string": ["@email:replace"],
+                    "extra.secret": ["@anything:remove"]
+                }
+            }
+            "##,
+        )
+        .unwrap();
+
+        let event = Annotated::new(Event {
+            user: Annotated::new(User {
+                email: Annotated::new("mail@example.org".to_string()),
+                ..Default::default()
+            }),
+            extra: {
+                let mut map = Object::new();
+                map.insert(
+                    "secret".to_string(),
+                    Annotated::new(ExtraValue(Value::String("hunter2".to_string()))),
+                );
+                Annotated::new(map)
+            },
+            ..Default::default()
+        });
+
+        let original = event.clone();
+        let compiled = config.compiled();
+        let explanations = explain_pii(&compiled, &event).unwrap();
+        assert_eq_dbg!(event, original);
+
+        assert_eq_dbg!(
+            explanations,
+            vec![
+                PiiExplanation {
+                    path: "user.email".to_string(),
+                    selector: "$string".parse().unwrap(),
+                    rule_id: "@email:replace".to_string(),
+                    redaction: Redaction::Replace("[email]".to_string().into()),
+                    original: Some(Value::String("mail@example.org".to_string())),
+                    redacted: Some(Value::String("[email]".to_string())),
+                },
+                PiiExplanation {
+                    path: "extra.secret".to_string(),
+                    selector: "extra.secret".parse().unwrap(),
+                    rule_id: "@anything:remove".to_string(),
+                    redaction: Redaction::Remove,
+                    original: Some(Value::String("hunter2".to_string())),
+                    redacted: None,
+                },
+            ]
+        );
+    }
+}
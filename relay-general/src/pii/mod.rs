@@ -4,18 +4,22 @@ mod builtin;
 mod compiledconfig;
 mod config;
 mod convert;
+mod explain;
 mod legacy;
 mod processor;
+mod pseudonymize;
 mod redactions;
 
 pub use self::builtin::{BUILTIN_RULES, BUILTIN_SELECTORS};
 pub use self::compiledconfig::CompiledPiiConfig;
 pub use self::config::{
-    AliasRule, MultipleRule, Pattern, PatternRule, PiiConfig, RedactPairRule, RuleSpec, RuleType,
-    Vars,
+    AliasRule, Allowlist, MultipleRule, Pattern, PatternRule, PiiConfig, RedactPairRule, RuleSpec,
+    RuleType, Vars,
 };
+pub use self::explain::{explain_pii, PiiExplanation};
 pub use self::legacy::DataScrubbingConfig;
 pub use self::processor::PiiProcessor;
+pub use self::pseudonymize::PseudonymizationConfig;
 pub use self::redactions::{
     HashAlgorithm, HashRedaction, MaskRedaction, Redaction, ReplaceRedaction,
 };
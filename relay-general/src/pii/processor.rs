@@ -4,22 +4,27 @@ use std::collections::BTreeSet;
 
 use hmac::{Hmac, Mac};
 use lazy_static::lazy_static;
-use regex::Regex;
+use regex::{Captures, Regex};
 use sha1::Sha1;
 use sha2::{Sha256, Sha512};
 
 use crate::pii::compiledconfig::RuleRef;
-use crate::pii::{CompiledPiiConfig, HashAlgorithm, Redaction, RuleType};
+use crate::pii::explain::{payload_to_value, PiiExplanation};
+use crate::pii::{CompiledPiiConfig, HashAlgorithm, PseudonymizationConfig, Redaction, RuleType};
 use crate::processor::{
-    process_chunked_value, process_value, Chunk, Pii, ProcessValue, ProcessingState, Processor,
-    SelectorSpec, ValueType,
+    extract_fields, process_chunked_value, process_value, Chunk, Pii, PredicateValues,
+    ProcessValue, ProcessingState, Processor, SelectorSpec, ValueType,
 };
 use crate::protocol::{AsPair, NativeImagePath, PairList};
-use crate::types::{Meta, ProcessingAction, ProcessingResult, Remark, RemarkType};
+use crate::types::{
+    Annotated, Meta, Object, ProcessingAction, ProcessingResult, Remark, RemarkType, Value,
+};
 
 lazy_static! {
     static ref NULL_SPLIT_RE: Regex = #[allow(clippy::trivial_regex)]
     Regex::new("\x00").unwrap();
+    static ref REPLACE_PLACEHOLDER_RE: Regex =
+        Regex::new(r"\{\{|\}\}|\{(rule|len|hash(?::(\d+))?|\d+)\}").unwrap();
 }
 
 #[rustfmt::skip]
@@ -172,6 +177,9 @@ lazy_static! {
 /// A processor that performs PII stripping.
 pub struct PiiProcessor<'a> {
     compiled_config: &'a CompiledPiiConfig,
+    field_values: FieldValues,
+    explanations: Option<Vec<PiiExplanation>>,
+    pseudonymization: Option<&'a PseudonymizationConfig>,
 }
 
 impl<'a> PiiProcessor<'a> {
@@ -182,44 +190,173 @@ impl<'a> PiiProcessor<'a> {
         //
         // Note: We accept both `PiiConfig` and `CompiledPiiConfig` because the latter makes more
         // sense for benchmarks while the former is obviously the cleaner API for relay-server.
-        PiiProcessor { compiled_config }
+        PiiProcessor {
+            compiled_config,
+            field_values: FieldValues::default(),
+            explanations: None,
+            pseudonymization: None,
+        }
     }
 
-    /// Iterate over all matching rules.
-    fn iter_rules<'b>(&self, state: &'b ProcessingState<'b>) -> RuleIterator<'a, 'b> {
-        RuleIterator {
-            state,
-            application_iter: self.compiled_config.applications.iter(),
-            pending_refs: None,
+    /// Creates a new processor that records every rule application.
+    ///
+    /// The processor still modifies the values it processes. To explain a config without changing
+    /// an event, run it on a copy or use `explain_pii`.
+    pub fn explaining(compiled_config: &'a CompiledPiiConfig) -> PiiProcessor<'a> {
+        PiiProcessor {
+            explanations: Some(Vec::new()),
+            ..PiiProcessor::new(compiled_config)
+        }
+    }
+
+    /// Sets the key for `{hash}` placeholders in replace redactions.
+    ///
+    /// Without a key, the processor refuses to hash and removes values instead of replacing them
+    /// with a template that contains `{hash}`.
+    pub fn with_pseudonymization(
+        mut self,
+        pseudonymization: Option<&'a PseudonymizationConfig>,
+    ) -> PiiProcessor<'a> {
+        self.pseudonymization = pseudonymization;
+        self
+    }
+
+    /// Returns the rule applications recorded by an explaining processor.
+    ///
+    /// This is empty unless the processor was created with `PiiProcessor::explaining`.
+    pub fn into_explanations(self) -> Vec<PiiExplanation> {
+        self.explanations.unwrap_or_default()
+    }
+
+    /// Returns whether field values of the value at this state are needed to evaluate selector
+    /// predicates.
+    fn needs_field_values(&self, state: &ProcessingState<'_>) -> bool {
+        self.compiled_config
+            .predicate_items
+            .iter()
+            .any(|item| item.matches_state(state, &()))
+    }
+
+    /// Returns whether the value at this state is allowlisted in deny-by-default mode.
+    ///
+    /// Allowlisted values are never scrubbed, not even by rules in `applications`.
+    fn is_allowlisted(&self, state: &ProcessingState<'_>) -> bool {
+        match self.compiled_config.denied {
+            Some((ref selector, _)) => !state
+                .path()
+                .matches_selector_with(selector, &self.field_values),
+            None => false,
         }
     }
 }
 
+/// Records a rule application if the processor is explaining.
+fn explain(
+    explanations: &mut Option<Vec<PiiExplanation>>,
+    state: &ProcessingState<'_>,
+    selector: &SelectorSpec,
+    rule: &RuleRef,
+    original: Option<Value>,
+    redacted: Option<Value>,
+) {
+    if let Some(explanations) = explanations {
+        explanations.push(PiiExplanation {
+            path: state.path().to_string(),
+            selector: selector.clone(),
+            rule_id: rule.origin.clone(),
+            redaction: rule.redaction.clone(),
+            original,
+            redacted,
+        });
+    }
+}
+
+/// Field values of the containers on the current path, used to evaluate selector predicates.
+#[derive(Debug, Default)]
+struct FieldValues {
+    stack: Vec<(usize, Object<Value>)>,
+}
+
+impl FieldValues {
+    fn push<T: ProcessValue>(
+        &mut self,
+        value: Option<&T>,
+        state: &ProcessingState<'_>,
+        fields: &BTreeSet<String>,
+    ) {
+        self.stack
+            .push((state.depth(), extract_fields(value, fields)));
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+    }
+}
+
+impl PredicateValues for FieldValues {
+    fn get_field(&self, state: &ProcessingState<'_>, field: &str) -> Option<&Value> {
+        // The stack only contains the current value and its ancestors. If a newtype has been
+        // entered, its inner value has the same depth and is pushed last.
+        let (_, fields) = self
+            .stack
+            .iter()
+            .rev()
+            .find(|(depth, _)| *depth == state.depth())?;
+        fields.get(field).and_then(Annotated::value)
+    }
+}
+
 struct RuleIterator<'a, 'b> {
     state: &'b ProcessingState<'b>,
+    field_values: &'b FieldValues,
     application_iter: std::slice::Iter<'a, (SelectorSpec, BTreeSet<RuleRef>)>,
-    pending_refs: Option<std::collections::btree_set::Iter<'a, RuleRef>>,
+    pending_refs: Option<(
+        &'a SelectorSpec,
+        std::collections::btree_set::Iter<'a, RuleRef>,
+    )>,
+}
+
+impl<'a, 'b> RuleIterator<'a, 'b> {
+    /// Iterate over all matching rules.
+    fn new(
+        compiled_config: &'a CompiledPiiConfig,
+        field_values: &'b FieldValues,
+        state: &'b ProcessingState<'b>,
+    ) -> Self {
+        RuleIterator {
+            state,
+            field_values,
+            application_iter: compiled_config.applications.iter(),
+            pending_refs: None,
+        }
+    }
 }
 
 impl<'a, 'b> Iterator for RuleIterator<'a, 'b> {
-    type Item = &'a RuleRef;
+    type Item = (&'a SelectorSpec, &'a RuleRef);
 
-    fn next(&mut self) -> Option<&'a RuleRef> {
+    fn next(&mut self) -> Option<Self::Item> {
         if self.state.attrs().pii == Pii::False {
             return None;
         }
 
         'outer: loop {
-            if let Some(rv) = self.pending_refs.as_mut().and_then(Iterator::next) {
-                return Some(rv);
+            if let Some((selector, ref mut refs)) = self.pending_refs {
+                if let Some(rule) = refs.next() {
+                    return Some((selector, rule));
+                }
             }
 
             while let Some((selector, rules)) = self.application_iter.next() {
                 if self.state.attrs().pii == Pii::Maybe && !selector.is_specific() {
                     continue;
                 }
-                if self.state.path().matches_selector(selector) {
-                    self.pending_refs = Some(rules.iter());
+                if self
+                    .state
+                    .path()
+                    .matches_selector_with(selector, self.field_values)
+                {
+                    self.pending_refs = Some((selector, rules.iter()));
                     continue 'outer;
                 }
             }
@@ -236,25 +373,60 @@ impl<'a> Processor for PiiProcessor<'a> {
         meta: &mut Meta,
         state: &ProcessingState<'_>,
     ) -> ProcessingResult {
+        // remember fields of this value for selector predicates on this value and its children.
+        // this must be symmetric to after_process, so it cannot depend on the value.
+        if self.needs_field_values(state) {
+            let fields = &self.compiled_config.predicate_fields;
+            self.field_values.push(value, state, fields);
+        }
+
         // booleans cannot be PII, and strings are handled in process_string
         if let Some(ValueType::Boolean) | Some(ValueType::String) = state.value_type() {
             return Ok(());
         }
 
-        if value.is_none() {
+        if value.is_none() || self.is_allowlisted(state) {
             return Ok(());
         }
 
         // apply rules based on key/path
-        for rule in self.iter_rules(state) {
-            match apply_rule_to_value(meta, rule, state.path().key(), None) {
+        let rules = RuleIterator::new(self.compiled_config, &self.field_values, state);
+        for (selector, rule) in rules {
+            let key = state.path().key();
+            match apply_rule_to_value(meta, rule, key, None, self.pseudonymization) {
                 Ok(()) => continue,
-                other => return other,
+                other => {
+                    if self.explanations.is_some() {
+                        let original = value.and_then(payload_to_value);
+                        explain(
+                            &mut self.explanations,
+                            state,
+                            selector,
+                            rule,
+                            original,
+                            None,
+                        );
+                    }
+                    return other;
+                }
             }
         }
         Ok(())
     }
 
+    fn after_process<T: ProcessValue>(
+        &mut self,
+        _value: Option<&T>,
+        _meta: &mut Meta,
+        state: &ProcessingState<'_>,
+    ) -> ProcessingResult {
+        if self.needs_field_values(state) {
+            self.field_values.pop();
+        }
+
+        Ok(())
+    }
+
     fn process_string(
         &mut self,
         value: &mut String,
@@ -265,13 +437,39 @@ impl<'a> Processor for PiiProcessor<'a> {
             return Ok(());
         }
 
+        // in deny-by-default mode, strings that are not allowlisted are redacted before any other
+        // rules apply. allowlisted strings are not scrubbed at all.
+        if let Some((ref selector, ref rule)) = self.compiled_config.denied {
+            if self.is_allowlisted(state) {
+                return Ok(());
+            }
+
+            if state.attrs().pii != Pii::False {
+                apply_rule_to_string(
+                    &mut self.explanations,
+                    self.pseudonymization,
+                    meta,
+                    state,
+                    selector,
+                    rule,
+                    value,
+                )?;
+            }
+        }
+
         // same as before_process. duplicated here because we can only check for "true",
         // "false" etc in process_string.
-        for rule in self.iter_rules(state) {
-            match apply_rule_to_value(meta, rule, state.path().key(), Some(value)) {
-                Ok(()) => continue,
-                other => return other,
-            }
+        let rules = RuleIterator::new(self.compiled_config, &self.field_values, state);
+        for (selector, rule) in rules {
+            apply_rule_to_string(
+                &mut self.explanations,
+                self.pseudonymization,
+                meta,
+                state,
+                selector,
+                rule,
+                value,
+            )?;
         }
         Ok(())
     }
@@ -350,11 +548,41 @@ impl<'a> Processor for PiiProcessor<'a> {
     }
 }
 
+/// Applies a rule to a string value and records the application if the processor is explaining.
+fn apply_rule_to_string(
+    explanations: &mut Option<Vec<PiiExplanation>>,
+    pseudonymization: Option<&PseudonymizationConfig>,
+    meta: &mut Meta,
+    state: &ProcessingState<'_>,
+    selector: &SelectorSpec,
+    rule: &RuleRef,
+    value: &mut String,
+) -> ProcessingResult {
+    let original = explanations.as_ref().map(|_| value.clone());
+    let key = state.path().key();
+    let result = apply_rule_to_value(meta, rule, key, Some(value), pseudonymization);
+
+    if let Some(original) = original {
+        let redacted = match result {
+            Ok(()) => Some(value.clone()),
+            Err(_) => None,
+        };
+        if redacted.as_ref() != Some(&original) {
+            let original = Some(Value::String(original));
+            let redacted = redacted.map(Value::String);
+            explain(explanations, state, selector, rule, original, redacted);
+        }
+    }
+
+    result
+}
+
 fn apply_rule_to_value(
     meta: &mut Meta,
     rule: &RuleRef,
     key: Option<&str>,
     mut value: Option<&mut String>,
+    pseudonymization: Option<&PseudonymizationConfig>,
 ) -> ProcessingResult {
     // The rule might specify to remove or to redact. If redaction is chosen, we need to
     // chunk up the value, otherwise we need to simply mark the value for deletion.
@@ -367,7 +595,7 @@ fn apply_rule_to_value(
         ($regex:expr, $replace_groups:expr) => {
             if let Some(ref mut value) = value {
                 process_chunked_value(value, meta, |chunks| {
-                    apply_regex_to_chunks(chunks, rule, $regex, $replace_groups)
+                    apply_regex_to_chunks(chunks, rule, $regex, $replace_groups, pseudonymization)
                 });
             }
         };
@@ -430,6 +658,7 @@ fn apply_regex_to_chunks<'a>(
     rule: &RuleRef,
     regex: &Regex,
     replace_groups: Option<&BTreeSet<u8>>,
+    pseudonymization: Option<&PseudonymizationConfig>,
 ) -> Vec<Chunk<'a>> {
     // NB: This function allocates the entire string and all chunks a second time. This means it
     // cannot reuse chunks and reallocates them. Ideally, we would be able to run the regex directly
@@ -494,7 +723,13 @@ fn apply_regex_to_chunks<'a>(
                                 &mut rv,
                                 &mut replacement_chunks,
                             );
-                            insert_replacement_chunks(&rule, g.as_str(), &mut rv);
+                            insert_replacement_chunks(
+                                &rule,
+                                g.as_str(),
+                                &m,
+                                pseudonymization,
+                                &mut rv,
+                            );
                             pos = g.end();
                         }
                     }
@@ -502,7 +737,7 @@ fn apply_regex_to_chunks<'a>(
             }
             None => {
                 process_text(&"", &mut rv, &mut replacement_chunks);
-                insert_replacement_chunks(&rule, &search_string, &mut rv);
+                insert_replacement_chunks(&rule, &search_string, &m, pseudonymization, &mut rv);
                 pos = search_string.len();
                 break;
             }
@@ -529,15 +764,21 @@ fn in_range(range: (Option<i32>, Option<i32>), pos: usize, len: usize) -> bool {
     pos >= start && pos < end
 }
 
-fn insert_replacement_chunks(rule: &RuleRef, text: &str, output: &mut Vec<Chunk<'_>>) {
+fn insert_replacement_chunks(
+    rule: &RuleRef,
+    text: &str,
+    captures: &Captures<'_>,
+    pseudonymization: Option<&PseudonymizationConfig>,
+    output: &mut Vec<Chunk<'_>>,
+) {
+    let removed = || Chunk::Redaction {
+        text: Cow::Borrowed(""),
+        rule_id: Cow::Owned(rule.origin.to_string()),
+        ty: RemarkType::Removed,
+    };
+
     match &rule.redaction {
-        Redaction::Default | Redaction::Remove => {
-            output.push(Chunk::Redaction {
-                text: Cow::Borrowed(""),
-                rule_id: Cow::Owned(rule.origin.to_string()),
-                ty: RemarkType::Removed,
-            });
-        }
+        Redaction::Default | Redaction::Remove => output.push(removed()),
         Redaction::Mask(mask) => {
             let chars_to_ignore: BTreeSet<char> = mask.chars_to_ignore.chars().collect();
             let mut buf = Vec::with_capacity(text.len());
@@ -563,16 +804,82 @@ fn insert_replacement_chunks(rule: &RuleRef, text: &str, output: &mut Vec<Chunk<
             });
         }
         Redaction::Replace(replace) => {
-            output.push(Chunk::Redaction {
-                ty: RemarkType::Substituted,
-                rule_id: Cow::Owned(rule.origin.to_string()),
-                text: Cow::Owned(replace.text.clone()),
-            });
+            match expand_replacement(&replace.text, rule, text, captures, pseudonymization) {
+                Some(replacement) => output.push(Chunk::Redaction {
+                    ty: RemarkType::Substituted,
+                    rule_id: Cow::Owned(rule.origin.to_string()),
+                    text: Cow::Owned(replacement),
+                }),
+                // Never fall back to an unkeyed hash, which can be reversed for short values.
+                None => output.push(removed()),
+            }
+        }
+    }
+}
+
+/// Expands placeholders in the text of a replace redaction.
+///
+/// Supported placeholders are `{rule}`, `{len}`, `{hash}` with an optional prefix length such as
+/// `{hash:8}`, and capture groups of the rule's pattern such as `{1}`. Literal braces are escaped
+/// by doubling them.
+///
+/// `{hash}` is keyed with the project's pseudonymization config, so that hashes match the
+/// pseudonyms of user identifiers. Returns `None` if the template contains `{hash}` but no key is
+/// configured.
+fn expand_replacement(
+    template: &str,
+    rule: &RuleRef,
+    text: &str,
+    captures: &Captures<'_>,
+    pseudonymization: Option<&PseudonymizationConfig>,
+) -> Option<String> {
+    if !template.contains(|c| c == '{' || c == '}') {
+        return Some(template.to_owned());
+    }
+
+    // Previous redactions are marked with null bytes and are not part of the matched value.
+    let text = || text.replace("\x00", "");
+    let mut missing_key = false;
+
+    let expanded = REPLACE_PLACEHOLDER_RE.replace_all(template, |placeholder: &Captures<'_>| {
+        let name = match placeholder.get(1) {
+            Some(name) => name.as_str(),
+            None => return placeholder[0][..1].to_owned(),
+        };
+
+        match name {
+            "rule" => rule.origin.clone(),
+            "len" => text().chars().count().to_string(),
+            _ if name.starts_with("hash") => {
+                let mut hash = match pseudonymization {
+                    Some(config) => config.pseudonym(&text()),
+                    None => {
+                        missing_key = true;
+                        return String::new();
+                    }
+                };
+                if let Some(len) = placeholder.get(2).and_then(|len| len.as_str().parse().ok()) {
+                    hash.truncate(len);
+                }
+                hash
+            }
+            _ => name
+                .parse()
+                .ok()
+                .and_then(|group| captures.get(group))
+                .map(|group| group.as_str().replace("\x00", ""))
+                .unwrap_or_default(),
         }
+    });
+
+    if missing_key {
+        return None;
     }
+
+    Some(expanded.into_owned())
 }
 
-fn hash_value(algorithm: HashAlgorithm, text: &str, key: Option<&str>) -> String {
+pub(super) fn hash_value(algorithm: HashAlgorithm, text: &str, key: Option<&str>) -> String {
     let key = key.unwrap_or("");
     macro_rules! hmac {
         ($ty:ident) => {{
@@ -592,10 +899,9 @@ fn hash_value(algorithm: HashAlgorithm, text: &str, key: Option<&str>) -> String
 use {
     crate::pii::PiiConfig,
     crate::protocol::{
-        Addr, DebugImage, DebugMeta, Event, ExtraValue, Headers, LogEntry, NativeDebugImage,
-        Request,
+        Addr, DebugImage, DebugMeta, Event, ExtraValue, Frame, FrameVars, Headers, LogEntry,
+        NativeDebugImage, RawStacktrace, Request, Stacktrace, User,
     },
-    crate::types::{Annotated, Object, Value},
 };
 
 #[test]
@@ -735,6 +1041,119 @@ fn test_redact_custom_pattern() {
     assert_annotated_snapshot!(event);
 }
 
+#[test]
+fn test_redact_replace_placeholders() {
+    let config = PiiConfig::from_json(
+        r##"
+        {
+            "applications": {
+                "$string": ["myrule"]
+            },
+            "rules": {
+                "myrule": {
+                    "type": "pattern",
+                    "pattern": "[a-z]+@([a-z.]+)",
+                    "redaction": {
+                        "method": "replace",
+                        "text": "[{rule}:{len}:{hash:8}]@{1} {{x}}"
+                    }
+                }
+            }
+        }
+    "##,
+    )
+    .unwrap();
+
+    let mut event = Annotated::new(Event {
+        extra: {
+            let mut map = Object::new();
+            map.insert(
+                "myvalue".to_string(),
+                Annotated::new(ExtraValue(Value::String(
+                    "contact mail@example.org".to_string(),
+                ))),
+            );
+            Annotated::new(map)
+        },
+        ..Default::default()
+    });
+
+    let pseudonymization = PseudonymizationConfig {
+        hash_key: "secret".to_owned(),
+        algorithm: HashAlgorithm::HmacSha1,
+    };
+
+    let compiled = config.compiled();
+    let mut processor = PiiProcessor::new(&compiled).with_pseudonymization(Some(&pseudonymization));
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let value = event
+        .value()
+        .and_then(|e| e.extra.value())
+        .and_then(|extra| extra.get("myvalue"))
+        .and_then(Annotated::value);
+
+    assert_eq_dbg!(
+        value,
+        Some(&ExtraValue(Value::String(
+            "contact [myrule:16:C2BA3578]@example.org {x}".to_string()
+        )))
+    );
+}
+
+#[test]
+fn test_redact_replace_hash_without_key() {
+    let config = PiiConfig::from_json(
+        r##"
+        {
+            "applications": {
+                "$string": ["myrule"]
+            },
+            "rules": {
+                "myrule": {
+                    "type": "pattern",
+                    "pattern": "[a-z]+@[a-z.]+",
+                    "redaction": {
+                        "method": "replace",
+                        "text": "[{hash}]"
+                    }
+                }
+            }
+        }
+    "##,
+    )
+    .unwrap();
+
+    let mut event = Annotated::new(Event {
+        extra: {
+            let mut map = Object::new();
+            map.insert(
+                "myvalue".to_string(),
+                Annotated::new(ExtraValue(Value::String(
+                    "contact mail@example.org".to_string(),
+                ))),
+            );
+            Annotated::new(map)
+        },
+        ..Default::default()
+    });
+
+    let compiled = config.compiled();
+    let mut processor = PiiProcessor::new(&compiled);
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let value = event
+        .value()
+        .and_then(|e| e.extra.value())
+        .and_then(|extra| extra.get("myvalue"))
+        .and_then(Annotated::value);
+
+    assert_eq_dbg!(
+        value,
+        Some(&ExtraValue(Value::String("contact ".to_string())))
+    );
+}
+
 #[test]
 fn test_no_field_upsert() {
     let config = PiiConfig::from_json(
@@ -1060,3 +1479,154 @@ fn test_quoted_keys() {
     process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
     assert_annotated_snapshot!(event);
 }
+
+#[test]
+fn test_selector_predicates() {
+    let config = PiiConfig::from_json(
+        r##"
+        {
+            "applications": {
+                "$frame[in_app=true].vars.**": ["@anything:remove"]
+            }
+        }
+        "##,
+    )
+    .unwrap();
+
+    fn frame(in_app: bool) -> Annotated<Frame> {
+        let mut vars = Object::new();
+        vars.insert(
+            "password".to_string(),
+            Annotated::new(Value::String("hunter2".to_string())),
+        );
+
+        Annotated::new(Frame {
+            in_app: Annotated::new(in_app),
+            vars: Annotated::new(FrameVars(vars)),
+            ..Default::default()
+        })
+    }
+
+    let mut event = Annotated::new(Event {
+        stacktrace: Annotated::new(Stacktrace(RawStacktrace {
+            frames: Annotated::new(vec![frame(false), frame(true)]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    });
+
+    let compiled = config.compiled();
+    let mut processor = PiiProcessor::new(&compiled);
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let frames = event
+        .value()
+        .and_then(|e| e.stacktrace.value())
+        .and_then(|s| s.frames.value())
+        .unwrap();
+    let password = |idx: usize| {
+        frames[idx]
+            .value()
+            .and_then(|f| f.vars.value())
+            .and_then(|v| v.0.get("password"))
+            .and_then(Annotated::value)
+    };
+
+    assert_eq_dbg!(password(0), Some(&Value::String("hunter2".to_string())));
+    assert_eq_dbg!(password(1), None);
+}
+
+#[test]
+fn test_allowlist() {
+    let config = PiiConfig::from_json(
+        r##"
+        {
+            "allowlist": {
+                "selectors": ["$user.name"]
+            }
+        }
+        "##,
+    )
+    .unwrap();
+
+    let mut event = Annotated::new(Event {
+        user: Annotated::new(User {
+            email: Annotated::new("mail@example.org".to_string()),
+            name: Annotated::new("Jane Doe".to_string()),
+            username: Annotated::new("jane".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+
+    let compiled = config.compiled();
+    let mut processor = PiiProcessor::new(&compiled);
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let user = event.value().and_then(|e| e.user.value()).unwrap();
+
+    // not allowlisted
+    assert_eq_dbg!(user.email.value(), None);
+    assert_eq_dbg!(
+        user.email.meta().iter_remarks().next(),
+        Some(&Remark::new(RemarkType::Removed, "@allowlist"))
+    );
+
+    // allowlisted
+    assert_eq_dbg!(user.name.value(), Some(&"Jane Doe".to_string()));
+
+    // pii = "false"
+    assert_eq_dbg!(user.username.value(), Some(&"jane".to_string()));
+}
+
+#[test]
+fn test_allowlist_overrides_rules() {
+    let config = PiiConfig::from_json(
+        r##"
+        {
+            "applications": {
+                "$string": ["@anything:remove"],
+                "extra.session": ["@anything:remove"],
+                "extra.other": ["@anything:remove"]
+            },
+            "allowlist": {
+                "selectors": ["$user.name", "extra.session", "extra.session.**"]
+            }
+        }
+        "##,
+    )
+    .unwrap();
+
+    let mut event = Annotated::<Event>::from_json(
+        r#"{
+            "user": {"name": "Jane Doe"},
+            "extra": {
+                "session": {"token": "abc", "count": 5},
+                "other": {"count": 5}
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let compiled = config.compiled();
+    let mut processor = PiiProcessor::new(&compiled);
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let event = event.value().unwrap();
+
+    // allowlisted strings are not scrubbed by rules
+    let user = event.user.value().unwrap();
+    assert_eq_dbg!(user.name.value(), Some(&"Jane Doe".to_string()));
+
+    // allowlisted databags are not scrubbed by key
+    let extra = event.extra.value().unwrap();
+    let session = extra.get("session").unwrap();
+    assert_eq_dbg!(
+        session.to_json().unwrap(),
+        r#"{"count":5,"token":"abc"}"#.to_string()
+    );
+
+    // not allowlisted
+    let other = extra.get("other").unwrap();
+    assert_eq_dbg!(other.value(), None);
+}
@@ -0,0 +1,136 @@
+//! Consistent pseudonymization of user identifiers.
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pii::processor::hash_value;
+use crate::pii::HashAlgorithm;
+use crate::processor::{process_chunked_value, Chunk};
+use crate::protocol::{LenientString, SessionUpdate, User, UserReport};
+use crate::types::{Annotated, Meta, RemarkType};
+
+/// The rule id reported in remarks of pseudonymized values.
+const PSEUDONYMIZE_RULE_ID: &str = "@pseudonymize";
+
+/// Configuration for pseudonymizing user identifiers.
+///
+/// Identifiers are mapped to stable pseudonyms with a keyed hash. Since the same key is used for
+/// events, sessions and user reports, an identifier maps to the same pseudonym in all of them.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PseudonymizationConfig {
+    /// The secret key for hashing identifiers.
+    pub hash_key: String,
+    /// The hash algorithm.
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+}
+
+impl PseudonymizationConfig {
+    /// Returns the stable pseudonym of a user identifier.
+    pub fn pseudonym(&self, identifier: &str) -> String {
+        hash_value(self.algorithm, identifier, Some(&self.hash_key))
+    }
+
+    /// Pseudonymizes the id, email and username of an event's user.
+    pub fn pseudonymize_user(&self, user: &mut User) {
+        let Annotated(ref mut id, ref mut meta) = user.id;
+        if let Some(LenientString(ref mut id)) = *id {
+            self.pseudonymize_value(id, meta);
+        }
+
+        self.pseudonymize_field(&mut user.email);
+        self.pseudonymize_field(&mut user.username);
+    }
+
+    /// Pseudonymizes the distinct id of a session update.
+    pub fn pseudonymize_session(&self, session: &mut SessionUpdate) {
+        if let Some(ref mut distinct_id) = session.distinct_id {
+            *distinct_id = self.pseudonym(distinct_id);
+        }
+    }
+
+    /// Pseudonymizes the email address of a user report.
+    pub fn pseudonymize_user_report(&self, report: &mut UserReport) {
+        if !report.email.is_empty() {
+            report.email = self.pseudonym(&report.email);
+        }
+    }
+
+    fn pseudonymize_field(&self, field: &mut Annotated<String>) {
+        let Annotated(ref mut value, ref mut meta) = *field;
+        if let Some(ref mut value) = *value {
+            self.pseudonymize_value(value, meta);
+        }
+    }
+
+    fn pseudonymize_value(&self, value: &mut String, meta: &mut Meta) {
+        if value.is_empty() {
+            return;
+        }
+
+        let pseudonym = self.pseudonym(value);
+        process_chunked_value(value, meta, |_| {
+            vec![Chunk::Redaction {
+                text: Cow::Owned(pseudonym),
+                rule_id: Cow::Borrowed(PSEUDONYMIZE_RULE_ID),
+                ty: RemarkType::Pseudonymized,
+            }]
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::Remark;
+
+    fn config() -> PseudonymizationConfig {
+        PseudonymizationConfig {
+            hash_key: "secret".to_string(),
+            algorithm: HashAlgorithm::HmacSha1,
+        }
+    }
+
+    #[test]
+    fn test_pseudonyms_are_consistent() {
+        let config = config();
+
+        let mut user = User {
+            id: Annotated::new(LenientString("42".to_string())),
+            email: Annotated::new("mail@example.org".to_string()),
+            ..Default::default()
+        };
+        config.pseudonymize_user(&mut user);
+
+        let mut session =
+            SessionUpdate::parse(br#"{"did": "42", "started": "2020-02-07T14:16:00Z"}"#).unwrap();
+        config.pseudonymize_session(&mut session);
+
+        let mut report = UserReport {
+            event_id: "52df9022835246eeb317dbd739ccd059".parse().unwrap(),
+            name: "Jane".to_string(),
+            email: "mail@example.org".to_string(),
+            comments: "it broke".to_string(),
+        };
+        config.pseudonymize_user_report(&mut report);
+
+        let id = user.id.value().map(|id| id.0.as_str());
+        assert_eq_dbg!(id, session.distinct_id.as_deref());
+        assert_ne!(id, Some("42"));
+
+        assert_eq_dbg!(user.email.value(), Some(&report.email));
+        assert_ne!(report.email, "mail@example.org");
+
+        let pseudonym = config.pseudonym("mail@example.org");
+        assert_eq_dbg!(
+            user.email.meta().iter_remarks().next(),
+            Some(&Remark::with_range(
+                RemarkType::Pseudonymized,
+                PSEUDONYMIZE_RULE_ID,
+                (0, pseudonym.len())
+            ))
+        );
+    }
+}
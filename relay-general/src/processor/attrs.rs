@@ -6,7 +6,7 @@ use failure::Fail;
 use regex::Regex;
 use smallvec::SmallVec;
 
-use crate::processor::{ProcessValue, SelectorPathItem, SelectorSpec};
+use crate::processor::{PredicateValues, ProcessValue, SelectorPathItem, SelectorSpec};
 use crate::types::Annotated;
 
 /// Error for unknown value types.
@@ -262,14 +262,14 @@ impl Default for FieldAttrs {
 #[derive(Debug, Clone, Eq, Ord, PartialOrd)]
 enum PathItem<'a> {
     StaticKey(&'a str),
-    Index(usize),
+    Index(usize, Option<usize>),
 }
 
 impl<'a> PartialEq for PathItem<'a> {
     fn eq(&self, other: &PathItem<'a>) -> bool {
         match *self {
             PathItem::StaticKey(ref s) => other.key() == Some(s),
-            PathItem::Index(value) => other.index() == Some(value),
+            PathItem::Index(value, _) => other.index() == Some(value),
         }
     }
 }
@@ -280,7 +280,7 @@ impl<'a> PathItem<'a> {
     pub fn key(&self) -> Option<&str> {
         match *self {
             PathItem::StaticKey(s) => Some(s),
-            PathItem::Index(_) => None,
+            PathItem::Index(_, _) => None,
         }
     }
 
@@ -289,7 +289,16 @@ impl<'a> PathItem<'a> {
     pub fn index(&self) -> Option<usize> {
         match *self {
             PathItem::StaticKey(_) => None,
-            PathItem::Index(idx) => Some(idx),
+            PathItem::Index(idx, _) => Some(idx),
+        }
+    }
+
+    /// Returns the length of the sequence if this is an index into a sequence of known length
+    #[inline]
+    pub fn sequence_len(&self) -> Option<usize> {
+        match *self {
+            PathItem::StaticKey(_) => None,
+            PathItem::Index(_, len) => len,
         }
     }
 }
@@ -298,7 +307,7 @@ impl<'a> fmt::Display for PathItem<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             PathItem::StaticKey(s) => f.pad(s),
-            PathItem::Index(val) => write!(f, "{}", val),
+            PathItem::Index(val, _) => write!(f, "{}", val),
         }
     }
 }
@@ -382,7 +391,26 @@ impl<'a> ProcessingState<'a> {
     ) -> Self {
         ProcessingState {
             parent: Some(self),
-            path_item: Some(PathItem::Index(idx)),
+            path_item: Some(PathItem::Index(idx, None)),
+            attrs,
+            value_type,
+            depth: self.depth + 1,
+        }
+    }
+
+    /// Derives a processing state by entering an index of a sequence with known length.
+    ///
+    /// This allows selectors to address elements relative to the end of the sequence.
+    pub fn enter_index_of(
+        &'a self,
+        idx: usize,
+        len: usize,
+        attrs: Option<Cow<'a, FieldAttrs>>,
+        value_type: Option<ValueType>,
+    ) -> Self {
+        ProcessingState {
+            parent: Some(self),
+            path_item: Some(PathItem::Index(idx, Some(len))),
             attrs,
             value_type,
             depth: self.depth + 1,
@@ -506,8 +534,33 @@ impl<'a> Path<'a> {
         PathItem::index(self.0.path_item()?)
     }
 
+    /// Returns the length of the sequence that contains the current index, if known
+    #[inline]
+    pub fn sequence_len(&self) -> Option<usize> {
+        PathItem::sequence_len(self.0.path_item()?)
+    }
+
+    /// Returns the current index counted from the end of its sequence, where `1` is the last
+    /// element. Returns `None` if the length of the sequence is not known.
+    #[inline]
+    pub fn index_from_end(&self) -> Option<usize> {
+        self.sequence_len()?.checked_sub(self.index()?)
+    }
+
     /// Checks if a path matches given selector.
+    ///
+    /// Predicates on fields of the selected values never match. Use
+    /// `Path::matches_selector_with` to evaluate them.
     pub fn matches_selector(&self, selector: &SelectorSpec) -> bool {
+        self.matches_selector_with(selector, &())
+    }
+
+    /// Checks if a path matches given selector, evaluating predicates against `values`.
+    pub fn matches_selector_with(
+        &self,
+        selector: &SelectorSpec,
+        values: &dyn PredicateValues,
+    ) -> bool {
         match *selector {
             SelectorSpec::Path(ref path) => {
                 // fastest path: the selector is deeper than the current structure.
@@ -525,7 +578,7 @@ impl<'a> Path<'a> {
                             depth_match = true;
                             break;
                         }
-                        Some(ref path_item) => path_item.matches_state(state),
+                        Some(ref path_item) => path_item.matches_state(state, values),
                         None => break,
                     } {
                         return false;
@@ -547,7 +600,7 @@ impl<'a> Path<'a> {
                 let mut path_match_iterator = remaining_states
                     .iter()
                     .rev()
-                    .skip_while(|state| !first_selector_path.matches_state(state));
+                    .skip_while(|state| !first_selector_path.matches_state(state, values));
                 if path_match_iterator.next().is_none() {
                     return false;
                 }
@@ -555,12 +608,12 @@ impl<'a> Path<'a> {
                 // then we check all remaining items and that nothing is left of the selector
                 path_match_iterator
                     .zip(&mut selector_iter)
-                    .all(|(state, selector_path)| selector_path.matches_state(state))
+                    .all(|(state, selector_path)| selector_path.matches_state(state, values))
                     && selector_iter.next().is_none()
             }
-            SelectorSpec::And(ref xs) => xs.iter().all(|x| self.matches_selector(x)),
-            SelectorSpec::Or(ref xs) => xs.iter().any(|x| self.matches_selector(x)),
-            SelectorSpec::Not(ref x) => !self.matches_selector(x),
+            SelectorSpec::And(ref xs) => xs.iter().all(|x| self.matches_selector_with(x, values)),
+            SelectorSpec::Or(ref xs) => xs.iter().any(|x| self.matches_selector_with(x, values)),
+            SelectorSpec::Not(ref x) => !self.matches_selector_with(x, values),
         }
     }
 }
@@ -689,3 +742,93 @@ fn test_path_matching() {
         .path()
         .matches_selector(&"(~$object.**)".parse().unwrap()));
 }
+
+#[test]
+fn test_path_matching_indices() {
+    let event_state = ProcessingState::new_root(None, Some(ValueType::Event)); // .
+    let values_state = event_state.enter_static("values", None, Some(ValueType::Array)); // .values
+    let first_state = values_state.enter_index_of(0, 3, None, None); // .values.0
+    let last_state = values_state.enter_index_of(2, 3, None, None); // .values.2
+    let unknown_state = values_state.enter_index(2, None, None); // .values.2 (unknown length)
+
+    assert!(last_state
+        .path()
+        .matches_selector(&"values.-1".parse().unwrap()));
+    assert!(!first_state
+        .path()
+        .matches_selector(&"values.-1".parse().unwrap()));
+    assert!(first_state
+        .path()
+        .matches_selector(&"values.-3".parse().unwrap()));
+    assert!(!unknown_state
+        .path()
+        .matches_selector(&"values.-1".parse().unwrap()));
+
+    assert!(first_state
+        .path()
+        .matches_selector(&"values.0..2".parse().unwrap()));
+    assert!(!last_state
+        .path()
+        .matches_selector(&"values.0..2".parse().unwrap()));
+    assert!(last_state
+        .path()
+        .matches_selector(&"values.1..".parse().unwrap()));
+    assert!(unknown_state
+        .path()
+        .matches_selector(&"values.1..".parse().unwrap()));
+    assert!(last_state
+        .path()
+        .matches_selector(&"values.-1..".parse().unwrap()));
+    assert!(!first_state
+        .path()
+        .matches_selector(&"values.-1..".parse().unwrap()));
+    assert!(first_state
+        .path()
+        .matches_selector(&"values...-1".parse().unwrap()));
+    assert!(!unknown_state
+        .path()
+        .matches_selector(&"values...-1".parse().unwrap()));
+}
+
+#[test]
+fn test_path_matching_predicates() {
+    struct Fields(crate::types::Value);
+
+    impl PredicateValues for Fields {
+        fn get_field(
+            &self,
+            state: &ProcessingState<'_>,
+            field: &str,
+        ) -> Option<&crate::types::Value> {
+            match (state.value_type(), field) {
+                (Some(ValueType::Frame), "in_app") => Some(&self.0),
+                _ => None,
+            }
+        }
+    }
+
+    let event_state = ProcessingState::new_root(None, Some(ValueType::Event)); // .
+    let frame_state = event_state.enter_static("frame", None, Some(ValueType::Frame)); // .frame
+    let vars_state = frame_state.enter_static("vars", None, Some(ValueType::Object)); // .frame.vars
+    let header_state = vars_state.enter_static("X-Custom", None, Some(ValueType::String)); // .frame.vars.X-Custom
+
+    let in_app = Fields(crate::types::Value::Bool(true));
+    let not_in_app = Fields(crate::types::Value::Bool(false));
+    let selector = "$frame[in_app=true].vars.**".parse().unwrap();
+
+    assert!(header_state
+        .path()
+        .matches_selector_with(&selector, &in_app));
+    assert!(!header_state
+        .path()
+        .matches_selector_with(&selector, &not_in_app));
+    // without field values, field predicates never match
+    assert!(!header_state.path().matches_selector(&selector));
+
+    assert!(header_state
+        .path()
+        .matches_selector(&"vars.*[$key~x-*]".parse().unwrap()));
+    assert!(!header_state
+        .path()
+        .matches_selector(&"vars.*[$key~y-*]".parse().unwrap()));
+}
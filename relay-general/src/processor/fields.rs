@@ -0,0 +1,287 @@
+use std::collections::BTreeSet;
+
+use serde::de::value::Error;
+use serde::ser::{self, Impossible};
+use serde::Serialize;
+
+use crate::types::{Annotated, Object, SkipSerialization, ToValue, Value};
+
+/// Returns the scalar values of the given fields of a structure or object.
+///
+/// Unlike serializing the entire value, this only visits the requested fields and does not recurse
+/// into nested objects or arrays. Fields with non-scalar values are omitted.
+pub fn extract_fields<T: ToValue>(value: Option<&T>, fields: &BTreeSet<String>) -> Object<Value> {
+    let serializer = FieldSerializer {
+        fields: Some(fields),
+    };
+
+    match value.map(|value| value.serialize_payload(serializer, SkipSerialization::default())) {
+        Some(Ok(Value::Object(object))) => object,
+        _ => Object::new(),
+    }
+}
+
+/// Serializes scalars into values and maps into objects of the requested fields.
+///
+/// Nested maps and sequences are not supported and result in an error, which causes the field to
+/// be omitted.
+struct FieldSerializer<'a> {
+    fields: Option<&'a BTreeSet<String>>,
+}
+
+fn unsupported() -> Error {
+    ser::Error::custom("unsupported value")
+}
+
+impl<'a> ser::Serializer for FieldSerializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Value, Error>;
+    type SerializeTuple = Impossible<Value, Error>;
+    type SerializeTupleStruct = Impossible<Value, Error>;
+    type SerializeTupleVariant = Impossible<Value, Error>;
+    type SerializeMap = FieldMapSerializer<'a>;
+    type SerializeStruct = Impossible<Value, Error>;
+    type SerializeStructVariant = Impossible<Value, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::I64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(Value::U64(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::F64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Value, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Value, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Value, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(unsupported())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        match self.fields {
+            Some(fields) => Ok(FieldMapSerializer {
+                fields,
+                key: None,
+                object: Object::new(),
+            }),
+            None => Err(unsupported()),
+        }
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(unsupported())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(unsupported())
+    }
+}
+
+/// Collects the requested fields of a map, skipping all other values without serializing them.
+struct FieldMapSerializer<'a> {
+    fields: &'a BTreeSet<String>,
+    key: Option<String>,
+    object: Object<Value>,
+}
+
+impl ser::SerializeMap for FieldMapSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.key = match key.serialize(FieldSerializer { fields: None }) {
+            Ok(Value::String(key)) if self.fields.contains(&key) => Some(key),
+            _ => None,
+        };
+
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let Some(key) = self.key.take() {
+            if let Ok(value) = value.serialize(FieldSerializer { fields: None }) {
+                self.object.insert(key, Annotated::new(value));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Object(self.object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::protocol::Frame;
+
+    #[test]
+    fn test_extract_fields() {
+        let frame = Annotated::<Frame>::from_json(
+            r#"{
+                "function": "main",
+                "in_app": true,
+                "lineno": 42,
+                "vars": {"function": "nested"},
+                "pre_context": ["a", "b"]
+            }"#,
+        )
+        .unwrap();
+
+        let fields = [
+            "function",
+            "in_app",
+            "lineno",
+            "vars",
+            "pre_context",
+            "module",
+        ]
+        .iter()
+        .map(|field| (*field).to_owned())
+        .collect();
+
+        let values = extract_fields(frame.value(), &fields);
+        let keys: Vec<_> = values.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["function", "in_app", "lineno"]);
+        assert_eq!(values["function"].as_str(), Some("main"));
+        assert_eq!(values["in_app"].value(), Some(&Value::Bool(true)));
+        assert_eq!(values["lineno"].value(), Some(&Value::U64(42)));
+    }
+}
@@ -157,12 +157,15 @@ where
     where
         P: Processor,
     {
+        let len = self.len();
         for (index, element) in self.iter_mut().enumerate() {
-            process_value(
-                element,
-                processor,
-                &state.enter_index(index, state.inner_attrs(), ValueType::for_field(element)),
-            )?;
+            let inner_state = state.enter_index_of(
+                index,
+                len,
+                state.inner_attrs(),
+                ValueType::for_field(element),
+            );
+            process_value(element, processor, &inner_state)?;
         }
 
         Ok(())
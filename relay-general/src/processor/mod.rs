@@ -2,6 +2,7 @@
 
 mod attrs;
 mod chunks;
+mod fields;
 mod funcs;
 mod impls;
 mod selector;
@@ -12,7 +13,10 @@ pub use self::attrs::{
     BagSize, FieldAttrs, MaxChars, Path, Pii, ProcessingState, UnknownValueTypeError, ValueType,
 };
 pub use self::chunks::{join_chunks, process_chunked_value, split_chunks, Chunk};
+pub use self::fields::extract_fields;
 pub use self::funcs::process_value;
-pub use self::selector::{SelectorPathItem, SelectorSpec};
+pub use self::selector::{
+    PredicateOperator, PredicateValues, SelectorPathItem, SelectorPredicate, SelectorSpec,
+};
 pub use self::size::{estimate_size, estimate_size_flat};
 pub use self::traits::{ProcessValue, Processor};
@@ -6,7 +6,10 @@ use pest::error::Error;
 use pest::iterators::Pair;
 use pest::Parser;
 
+use relay_common::{glob_match, GlobOptions};
+
 use crate::processor::{ProcessingState, ValueType};
+use crate::types::Value;
 
 /// Error for invalid selectors
 #[derive(Debug, Fail)]
@@ -20,6 +23,12 @@ pub enum InvalidSelectorError {
     #[fail(display = "invalid selector: invalid index")]
     InvalidIndex,
 
+    #[fail(display = "invalid selector: invalid range")]
+    InvalidRange,
+
+    #[fail(display = "invalid selector: predicates cannot be applied to deep wildcards")]
+    InvalidPredicate,
+
     #[fail(display = "invalid selector: unknown value")]
     UnknownType,
 
@@ -37,13 +46,121 @@ mod parser {
 
 use self::parser::{Rule, SelectorParser};
 
+/// The comparison performed by a `SelectorPredicate`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum PredicateOperator {
+    /// The value must be equal to the given value, ignoring case (`=`).
+    Equals,
+    /// The value must match the given glob pattern, ignoring case (`~`).
+    Glob,
+}
+
+impl fmt::Display for PredicateOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PredicateOperator::Equals => write!(f, "="),
+            PredicateOperator::Glob => write!(f, "~"),
+        }
+    }
+}
+
+/// A condition on the selected value, such as `[in_app=true]` or `[$key~"X-*"]`.
+///
+/// The left-hand side is either the name of a field of the selected value, or `$key` to refer to
+/// the key under which the selected value is stored.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct SelectorPredicate {
+    /// The field to compare, or `$key` for the key of the selected value.
+    pub field: String,
+    /// The comparison to perform.
+    pub operator: PredicateOperator,
+    /// The value or pattern to compare against.
+    pub value: String,
+}
+
+impl SelectorPredicate {
+    /// Returns `true` if this predicate compares the key of the selected value.
+    pub fn is_key(&self) -> bool {
+        self.field == "$key"
+    }
+
+    fn matches_str(&self, value: &str) -> bool {
+        match self.operator {
+            PredicateOperator::Equals => value.to_lowercase() == self.value.to_lowercase(),
+            PredicateOperator::Glob => {
+                let options = GlobOptions {
+                    case_insensitive: true,
+                    ..Default::default()
+                };
+                glob_match(value, &self.value, options)
+            }
+        }
+    }
+
+    fn matches_value(&self, value: &Value) -> bool {
+        match *value {
+            Value::Bool(b) => self.matches_str(if b { "true" } else { "false" }),
+            Value::I64(i) => self.matches_str(&i.to_string()),
+            Value::U64(u) => self.matches_str(&u.to_string()),
+            Value::F64(f) => self.matches_str(&f.to_string()),
+            Value::String(ref s) => self.matches_str(s),
+            Value::Array(_) | Value::Object(_) => false,
+        }
+    }
+
+    fn matches_state(&self, state: &ProcessingState<'_>, values: &dyn PredicateValues) -> bool {
+        if self.is_key() {
+            return state
+                .path()
+                .key()
+                .map_or(false, |key| self.matches_str(key));
+        }
+
+        values
+            .get_field(state, &self.field)
+            .map_or(false, |value| self.matches_value(value))
+    }
+}
+
+impl fmt::Display for SelectorPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}{}", self.field, self.operator)?;
+        if predicate_value_needs_quoting(&self.value) {
+            write!(f, "\"{}\"]", self.value.replace("\"", "\"\""))
+        } else {
+            write!(f, "{}]", self.value)
+        }
+    }
+}
+
+/// Provides access to fields of the values being processed to evaluate selector predicates.
+///
+/// Processors that support field predicates keep track of the relevant values while descending
+/// into a structure. See `SelectorSpec::predicate_items` for the path items that require this.
+pub trait PredicateValues {
+    /// Returns the value of a field of the value at the given processing state.
+    fn get_field(&self, state: &ProcessingState<'_>, field: &str) -> Option<&Value>;
+}
+
+impl PredicateValues for () {
+    fn get_field(&self, _state: &ProcessingState<'_>, _field: &str) -> Option<&Value> {
+        None
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum SelectorPathItem {
     Type(ValueType),
     Index(usize),
+    /// An index counted from the end of a sequence, where `1` is the last element.
+    IndexFromEnd(usize),
+    /// A half-open range of indices. Negative bounds count from the end of a sequence.
+    Range(Option<isize>, Option<isize>),
     Key(String),
     Wildcard,
     DeepWildcard,
+    /// A path item that only matches if the predicate holds for the selected value.
+    Predicate(Box<SelectorPathItem>, SelectorPredicate),
 }
 
 impl fmt::Display for SelectorPathItem {
@@ -51,6 +168,17 @@ impl fmt::Display for SelectorPathItem {
         match *self {
             SelectorPathItem::Type(ty) => write!(f, "${}", ty),
             SelectorPathItem::Index(index) => write!(f, "{}", index),
+            SelectorPathItem::IndexFromEnd(index) => write!(f, "-{}", index),
+            SelectorPathItem::Range(start, end) => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "..")?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
             SelectorPathItem::Key(ref key) => {
                 if key_needs_quoting(key) {
                     write!(f, "'{}'", key.replace("'", "''"))
@@ -60,24 +188,73 @@ impl fmt::Display for SelectorPathItem {
             }
             SelectorPathItem::Wildcard => write!(f, "*"),
             SelectorPathItem::DeepWildcard => write!(f, "**"),
+            SelectorPathItem::Predicate(ref item, ref predicate) => {
+                write!(f, "{}{}", item, predicate)
+            }
         }
     }
 }
 
 impl SelectorPathItem {
-    pub(super) fn matches_state(&self, state: &ProcessingState<'_>) -> bool {
+    /// Checks if this item matches the last segment of the given processing state.
+    pub fn matches_state(&self, state: &ProcessingState<'_>, values: &dyn PredicateValues) -> bool {
         match *self {
             SelectorPathItem::Wildcard => true,
             SelectorPathItem::DeepWildcard => true,
             SelectorPathItem::Type(ty) => state.value_type() == Some(ty),
             SelectorPathItem::Index(idx) => state.path().index() == Some(idx),
+            SelectorPathItem::IndexFromEnd(idx) => state.path().index_from_end() == Some(idx),
+            SelectorPathItem::Range(start, end) => {
+                let path = state.path();
+                match path.index() {
+                    Some(idx) => {
+                        let len = path.sequence_len();
+                        range_bound(start, len, 0).map_or(false, |start| idx >= start)
+                            && range_bound(end, len, usize::max_value())
+                                .map_or(false, |end| idx < end)
+                    }
+                    None => false,
+                }
+            }
             SelectorPathItem::Key(ref key) => state
                 .path()
                 .key()
                 .map(|k| k.to_lowercase() == key.to_lowercase())
                 .unwrap_or(false),
+            SelectorPathItem::Predicate(ref item, ref predicate) => {
+                item.matches_state(state, values) && predicate.matches_state(state, values)
+            }
+        }
+    }
+
+    /// Returns `true` if matching this item requires field values of the selected value.
+    pub fn needs_predicate_values(&self) -> bool {
+        match *self {
+            SelectorPathItem::Predicate(ref item, ref predicate) => {
+                !predicate.is_key() || item.needs_predicate_values()
+            }
+            _ => false,
         }
     }
+
+    /// Returns the item without any predicates.
+    pub fn without_predicates(&self) -> &SelectorPathItem {
+        match *self {
+            SelectorPathItem::Predicate(ref item, _) => item.without_predicates(),
+            ref other => other,
+        }
+    }
+}
+
+/// Resolves a range bound against the length of a sequence.
+///
+/// Returns `None` if the bound is negative but the length of the sequence is not known.
+fn range_bound(bound: Option<isize>, len: Option<usize>, default: usize) -> Option<usize> {
+    match bound {
+        None => Some(default),
+        Some(bound) if bound < 0 => len.map(|len| len.saturating_sub(-bound as usize)),
+        Some(bound) => Some(bound as usize),
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -96,18 +273,46 @@ impl SelectorSpec {
             SelectorSpec::And(_) | SelectorSpec::Or(_) | SelectorSpec::Not(_) => false,
             SelectorSpec::Path(ref path) => {
                 path.iter().all(|item| {
-                    match *item {
+                    match *item.without_predicates() {
                         SelectorPathItem::Type(_) => false,
                         SelectorPathItem::Index(_) => true,
+                        SelectorPathItem::IndexFromEnd(_) => true,
+                        SelectorPathItem::Range(_, _) => true,
                         SelectorPathItem::Key(_) => true,
                         // necessary because of array indices
                         SelectorPathItem::Wildcard => true,
                         SelectorPathItem::DeepWildcard => false,
+                        // unreachable, predicates have been removed above
+                        SelectorPathItem::Predicate(_, _) => false,
                     }
                 })
             }
         }
     }
+
+    /// Returns all path items in this selector that require field values of the selected value.
+    ///
+    /// Processors that evaluate such selectors need to provide `PredicateValues` for every state
+    /// matched by one of these items.
+    pub fn predicate_items(&self) -> Vec<&SelectorPathItem> {
+        let mut rv = Vec::new();
+        self.collect_predicate_items(&mut rv);
+        rv
+    }
+
+    fn collect_predicate_items<'a>(&'a self, items: &mut Vec<&'a SelectorPathItem>) {
+        match *self {
+            SelectorSpec::And(ref xs) | SelectorSpec::Or(ref xs) => {
+                for x in xs {
+                    x.collect_predicate_items(items);
+                }
+            }
+            SelectorSpec::Not(ref x) => x.collect_predicate_items(items),
+            SelectorSpec::Path(ref path) => {
+                items.extend(path.iter().filter(|item| item.needs_predicate_values()));
+            }
+        }
+    }
 }
 
 impl fmt::Display for SelectorSpec {
@@ -268,7 +473,20 @@ fn handle_selector(pair: Pair<Rule>) -> Result<SelectorSpec, InvalidSelectorErro
 }
 
 fn handle_selector_path_item(pair: Pair<Rule>) -> Result<SelectorPathItem, InvalidSelectorError> {
-    let pair = pair.into_inner().next().unwrap();
+    let mut pairs = pair.into_inner();
+    let mut item = handle_selector_path_base(pairs.next().unwrap())?;
+
+    for pair in pairs {
+        if item == SelectorPathItem::DeepWildcard {
+            return Err(InvalidSelectorError::InvalidPredicate);
+        }
+        item = SelectorPathItem::Predicate(Box::new(item), handle_predicate(pair)?);
+    }
+
+    Ok(item)
+}
+
+fn handle_selector_path_base(pair: Pair<Rule>) -> Result<SelectorPathItem, InvalidSelectorError> {
     match pair.as_rule() {
         Rule::ObjectType => Ok(SelectorPathItem::Type(
             pair.as_str()[1..]
@@ -277,11 +495,36 @@ fn handle_selector_path_item(pair: Pair<Rule>) -> Result<SelectorPathItem, Inval
         )),
         Rule::Wildcard => Ok(SelectorPathItem::Wildcard),
         Rule::DeepWildcard => Ok(SelectorPathItem::DeepWildcard),
-        Rule::Index => Ok(SelectorPathItem::Index(
-            pair.as_str()
-                .parse()
-                .map_err(|_| InvalidSelectorError::InvalidIndex)?,
-        )),
+        Rule::Index => {
+            let index = pair.as_str();
+            if index.starts_with('-') {
+                match index[1..].parse() {
+                    Ok(0) | Err(_) => Err(InvalidSelectorError::InvalidIndex),
+                    Ok(index) => Ok(SelectorPathItem::IndexFromEnd(index)),
+                }
+            } else {
+                Ok(SelectorPathItem::Index(
+                    index
+                        .parse()
+                        .map_err(|_| InvalidSelectorError::InvalidIndex)?,
+                ))
+            }
+        }
+        Rule::Range => {
+            let mut bounds = pair.as_str().splitn(2, "..").map(|bound| {
+                if bound.is_empty() {
+                    Ok(None)
+                } else {
+                    bound
+                        .parse()
+                        .map(Some)
+                        .map_err(|_| InvalidSelectorError::InvalidRange)
+                }
+            });
+            let start = bounds.next().unwrap()?;
+            let end = bounds.next().unwrap()?;
+            Ok(SelectorPathItem::Range(start, end))
+        }
         Rule::Key => Ok(SelectorPathItem::Key(handle_key(pair)?)),
         rule => Err(InvalidSelectorError::UnexpectedToken(
             format!("{:?}", rule),
@@ -308,8 +551,52 @@ fn handle_key(pair: Pair<Rule>) -> Result<String, InvalidSelectorError> {
     }
 }
 
+fn handle_predicate(pair: Pair<Rule>) -> Result<SelectorPredicate, InvalidSelectorError> {
+    let mut pairs = pair.into_inner();
+    let field = pairs.next().unwrap().as_str().to_owned();
+    let operator = match pairs.next().unwrap().as_str() {
+        "=" => PredicateOperator::Equals,
+        "~" => PredicateOperator::Glob,
+        other => {
+            return Err(InvalidSelectorError::UnexpectedToken(
+                other.to_owned(),
+                "a predicate operator",
+            ))
+        }
+    };
+
+    let pair = pairs.next().unwrap().into_inner().next().unwrap();
+    let value = match pair.as_rule() {
+        Rule::UnquotedPredicateValue => pair.as_str().to_owned(),
+        Rule::QuotedPredicateValue => {
+            let mut value = String::new();
+            for token in pair.into_inner() {
+                value.push_str(token.as_str());
+            }
+            value
+        }
+        rule => {
+            return Err(InvalidSelectorError::UnexpectedToken(
+                format!("{:?}", rule),
+                "a predicate value",
+            ))
+        }
+    };
+
+    Ok(SelectorPredicate {
+        field,
+        operator,
+        value,
+    })
+}
+
 fn key_needs_quoting(key: &str) -> bool {
     SelectorParser::parse(Rule::RootUnquotedKey, key).is_err()
+        || SelectorParser::parse(Rule::RootIndexOrRange, key).is_ok()
+}
+
+fn predicate_value_needs_quoting(value: &str) -> bool {
+    SelectorParser::parse(Rule::RootUnquotedPredicateValue, value).is_err()
 }
 
 #[test]
@@ -323,4 +610,61 @@ fn test_roundtrip() {
     check_roundtrip("!a && !b");
     check_roundtrip("!(a && !b)");
     check_roundtrip("!(a && b)");
+    check_roundtrip("$exception.values.-1");
+    check_roundtrip("$frame.0..5");
+    check_roundtrip("$frame.-3..");
+    check_roundtrip("$frame...-1");
+    check_roundtrip("$frame[in_app=true].vars.**");
+    check_roundtrip("$request.headers.*[$key~X-*]");
+    check_roundtrip("$frame[module~\"foo bar\"\"\"][in_app=false]");
+    check_roundtrip("extra.'0'");
+}
+
+#[test]
+fn test_parse_extensions() {
+    assert_eq_dbg!(
+        SelectorSpec::from_str("$exception.values.-1").unwrap(),
+        SelectorSpec::Path(vec![
+            SelectorPathItem::Type(ValueType::Exception),
+            SelectorPathItem::Key("values".to_owned()),
+            SelectorPathItem::IndexFromEnd(1),
+        ])
+    );
+
+    assert_eq_dbg!(
+        SelectorSpec::from_str("$frame.0..5").unwrap(),
+        SelectorSpec::Path(vec![
+            SelectorPathItem::Type(ValueType::Frame),
+            SelectorPathItem::Range(Some(0), Some(5)),
+        ])
+    );
+
+    assert_eq_dbg!(
+        SelectorSpec::from_str("$request.headers.*[$key~\"X-*\"]").unwrap(),
+        SelectorSpec::Path(vec![
+            SelectorPathItem::Type(ValueType::Request),
+            SelectorPathItem::Key("headers".to_owned()),
+            SelectorPathItem::Predicate(
+                Box::new(SelectorPathItem::Wildcard),
+                SelectorPredicate {
+                    field: "$key".to_owned(),
+                    operator: PredicateOperator::Glob,
+                    value: "X-*".to_owned(),
+                }
+            ),
+        ])
+    );
+
+    // keys that look like numbers but are not
+    assert_eq_dbg!(
+        SelectorSpec::from_str("extra.-foo.1a").unwrap(),
+        SelectorSpec::Path(vec![
+            SelectorPathItem::Key("extra".to_owned()),
+            SelectorPathItem::Key("-foo".to_owned()),
+            SelectorPathItem::Key("1a".to_owned()),
+        ])
+    );
+
+    assert!(SelectorSpec::from_str("$frame.-0").is_err());
+    assert!(SelectorSpec::from_str("**[in_app=true]").is_err());
 }
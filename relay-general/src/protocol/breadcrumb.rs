@@ -33,6 +33,14 @@ pub struct Breadcrumb {
     #[metastructure(skip_serialization = "empty")]
     pub data: Annotated<Object<Value>>,
 
+    /// The number of identical consecutive breadcrumbs collapsed into this one.
+    ///
+    /// If set, `timestamp` is the timestamp of the first collapsed breadcrumb.
+    pub repeat_count: Annotated<u64>,
+
+    /// The timestamp of the last breadcrumb collapsed into this one.
+    pub last_timestamp: Annotated<DateTime<Utc>>,
+
     /// Additional arbitrary fields for forwards compatibility.
     #[metastructure(additional_properties)]
     pub other: Object<Value>,
@@ -80,6 +88,8 @@ fn test_breadcrumb_roundtrip() {
             );
             Annotated::new(map)
         },
+        repeat_count: Annotated::empty(),
+        last_timestamp: Annotated::empty(),
         other: {
             let mut map = Map::new();
             map.insert(
@@ -0,0 +1,22 @@
+use crate::types::Object;
+
+/// Time spent in operations of a transaction, keyed by operation prefix.
+///
+/// Each value is the wall-clock time in milliseconds covered by at least one span of the
+/// operation. Overlapping spans are only counted once.
+#[derive(Clone, Debug, Default, PartialEq, Empty, FromValue, ToValue, ProcessValue)]
+pub struct Breakdowns(pub Object<f64>);
+
+impl std::ops::Deref for Breakdowns {
+    type Target = Object<f64>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for Breakdowns {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
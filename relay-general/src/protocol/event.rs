@@ -10,9 +10,9 @@ use chrono::TimeZone;
 
 use crate::processor::ProcessValue;
 use crate::protocol::{
-    Breadcrumb, ClientSdkInfo, Contexts, Csp, DebugMeta, Exception, ExpectCt, ExpectStaple,
-    Fingerprint, Hpkp, LenientString, Level, LogEntry, Metrics, Request, Span, Stacktrace, Tags,
-    TemplateInfo, Thread, User, Values,
+    Breadcrumb, Breakdowns, ClientSdkInfo, Contexts, Csp, DebugMeta, Exception, ExpectCt,
+    ExpectStaple, Fingerprint, Hpkp, LenientString, Level, LogEntry, Measurements, Metrics, Nel,
+    Request, Span, Stacktrace, Tags, TemplateInfo, Thread, TransactionInfo, User, Values,
 };
 use crate::types::{
     Annotated, Array, Empty, ErrorKind, FromValue, Object, SkipSerialization, ToValue, Value,
@@ -73,6 +73,7 @@ pub enum EventType {
     Hpkp,
     ExpectCT,
     ExpectStaple,
+    Nel,
     Transaction,
 }
 
@@ -98,6 +99,7 @@ impl FromStr for EventType {
             "hpkp" => EventType::Hpkp,
             "expectct" => EventType::ExpectCT,
             "expectstaple" => EventType::ExpectStaple,
+            "nel" => EventType::Nel,
             "transaction" => EventType::Transaction,
             _ => return Err(ParseEventTypeError),
         })
@@ -113,6 +115,7 @@ impl fmt::Display for EventType {
             EventType::Hpkp => write!(f, "hpkp"),
             EventType::ExpectCT => write!(f, "expectct"),
             EventType::ExpectStaple => write!(f, "expectstaple"),
+            EventType::Nel => write!(f, "nel"),
             EventType::Transaction => write!(f, "transaction"),
         }
     }
@@ -222,6 +225,10 @@ pub struct Event {
     #[metastructure(skip_serialization = "empty")]
     pub fingerprint: Annotated<Fingerprint>,
 
+    /// Grouping hashes of the event, if computed by Relay.
+    #[metastructure(skip_serialization = "empty")]
+    pub hashes: Annotated<Array<String>>,
+
     /// Custom culprit of the event.
     #[metastructure(max_chars = "culprit")]
     pub culprit: Annotated<String>,
@@ -230,6 +237,9 @@ pub struct Event {
     #[metastructure(max_chars = "culprit")]
     pub transaction: Annotated<String>,
 
+    /// Additional information about the name of the transaction.
+    pub transaction_info: Annotated<TransactionInfo>,
+
     /// Time since the start of the transaction until the error occurred.
     pub time_spent: Annotated<u64>,
 
@@ -388,9 +398,21 @@ pub struct Event {
     #[metastructure(pii = "true", legacy_alias = "sentry.interfaces.ExpectStaple")]
     pub expectstaple: Annotated<ExpectStaple>,
 
+    /// Network Error Logging (NEL) reports.
+    #[metastructure(pii = "true")]
+    pub nel: Annotated<Nel>,
+
     /// Spans for tracing.
     pub spans: Annotated<Array<Span>>,
 
+    /// Time spent in operations of a transaction, keyed by operation prefix.
+    ///
+    /// This value should not be ingested and will be overwritten by the store normalizer.
+    pub breakdowns: Annotated<Breakdowns>,
+
+    /// Numeric performance measurements of a transaction, such as Web Vitals.
+    pub measurements: Annotated<Measurements>,
+
     /// Internal ingestion and processing metrics.
     ///
     /// This value should not be ingested and will be overwritten by the store normalizer.
@@ -0,0 +1,62 @@
+use crate::types::{Annotated, Object};
+
+/// A single measurement value, such as a Web Vital.
+#[derive(Clone, Debug, Default, PartialEq, Empty, FromValue, ToValue, ProcessValue)]
+pub struct Measurement {
+    /// The observed value of the measurement.
+    #[metastructure(required = "true")]
+    pub value: Annotated<f64>,
+}
+
+/// Numeric performance measurements of a transaction, keyed by lowercase name.
+#[derive(Clone, Debug, Default, PartialEq, Empty, FromValue, ToValue, ProcessValue)]
+pub struct Measurements(pub Object<Measurement>);
+
+impl std::ops::Deref for Measurements {
+    type Target = Object<Measurement>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for Measurements {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measurements_serialization() {
+        let json = r#"{
+  "fcp": {
+    "value": 1000.5
+  },
+  "lcp": {
+    "value": 2500.0
+  }
+}"#;
+
+        let mut measurements = Measurements::default();
+        measurements.insert(
+            "fcp".to_owned(),
+            Annotated::new(Measurement {
+                value: Annotated::new(1000.5),
+            }),
+        );
+        measurements.insert(
+            "lcp".to_owned(),
+            Annotated::new(Measurement {
+                value: Annotated::new(2500.0),
+            }),
+        );
+
+        let measurements = Annotated::new(measurements);
+        assert_eq_dbg!(measurements, Annotated::from_json(json).unwrap());
+        assert_eq_str!(json, measurements.to_json_pretty().unwrap());
+    }
+}
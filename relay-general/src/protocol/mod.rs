@@ -1,5 +1,6 @@
 //! Implements the sentry event protocol.
 mod breadcrumb;
+mod breakdowns;
 mod clientsdk;
 mod constants;
 mod contexts;
@@ -8,8 +9,10 @@ mod event;
 mod exception;
 mod fingerprint;
 mod logentry;
+mod measurements;
 mod mechanism;
 mod metrics;
+mod otlp;
 mod request;
 mod security_report;
 mod session;
@@ -18,11 +21,13 @@ mod stacktrace;
 mod tags;
 mod templateinfo;
 mod thread;
+mod transaction;
 mod types;
 mod user;
 mod user_report;
 
 pub use self::breadcrumb::Breadcrumb;
+pub use self::breakdowns::Breakdowns;
 pub use self::clientsdk::{ClientSdkInfo, ClientSdkPackage};
 pub use self::constants::{INVALID_ENVIRONMENTS, INVALID_RELEASES, VALID_PLATFORMS};
 pub use self::contexts::{
@@ -39,16 +44,21 @@ pub use self::event::{
 pub use self::exception::Exception;
 pub use self::fingerprint::Fingerprint;
 pub use self::logentry::LogEntry;
+pub use self::measurements::{Measurement, Measurements};
 pub use self::mechanism::{CError, MachException, Mechanism, MechanismMeta, PosixSignal};
 pub use self::metrics::Metrics;
+pub use self::otlp::{OtlpError, OtlpTraces};
 pub use self::request::{Cookies, HeaderName, HeaderValue, Headers, Query, Request};
-pub use self::security_report::{Csp, ExpectCt, ExpectStaple, Hpkp, SecurityReportType};
+pub use self::security_report::{
+    apply_browser_report_to_event, Csp, ExpectCt, ExpectStaple, Hpkp, Nel, SecurityReportType,
+};
 pub use self::session::{ParseSessionStatusError, SessionAttributes, SessionStatus, SessionUpdate};
 pub use self::span::Span;
 pub use self::stacktrace::{Frame, FrameData, FrameVars, RawStacktrace, Stacktrace};
 pub use self::tags::{TagEntry, Tags};
 pub use self::templateinfo::TemplateInfo;
 pub use self::thread::{Thread, ThreadId};
+pub use self::transaction::{ParseTransactionSourceError, TransactionInfo, TransactionSource};
 pub use self::types::{
     Addr, AsPair, InvalidRegVal, IpAddr, JsonLenientString, LenientString, Level, PairList,
     ParseLevelError, RegVal, Values,
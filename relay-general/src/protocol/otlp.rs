@@ -0,0 +1,1109 @@
+//! Conversion of OpenTelemetry trace exports into transaction events.
+//!
+//! Relay accepts traces exported via OTLP/HTTP, either encoded as protobuf or as JSON. Spans are
+//! grouped by resource and trace. Every span without a parent in its group is a local root and
+//! becomes a transaction, and all of its descendants become the spans of that transaction.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use chrono::{DateTime, TimeZone, Utc};
+use failure::Fail;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer};
+use sha1::{Digest, Sha1};
+use uuid::Uuid;
+
+use crate::protocol::{
+    Context, Contexts, Event, EventId, EventType, LenientString, Span, SpanId, SpanStatus,
+    TraceContext, TraceId,
+};
+use crate::types::{Annotated, Object, Value};
+
+/// The resource attribute holding the version of the instrumented service.
+const SERVICE_VERSION: &str = "service.version";
+
+/// The resource attribute holding the deployment environment of the instrumented service.
+const DEPLOYMENT_ENVIRONMENT: &str = "deployment.environment";
+
+/// The key of the context holding OpenTelemetry resource attributes.
+const OTEL_CONTEXT: &str = "otel";
+
+const SPAN_KIND_SERVER: i32 = 2;
+const SPAN_KIND_CLIENT: i32 = 3;
+const SPAN_KIND_PRODUCER: i32 = 4;
+const SPAN_KIND_CONSUMER: i32 = 5;
+
+const STATUS_CODE_ERROR: i32 = 2;
+
+/// Maximum nesting depth of protobuf messages, which bounds recursion into nested attribute values.
+const MAX_PROTOBUF_DEPTH: usize = 64;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+/// An error returned when parsing OTLP trace exports.
+#[derive(Debug, Fail)]
+pub enum OtlpError {
+    /// The JSON payload could not be parsed.
+    #[fail(display = "invalid OTLP JSON payload")]
+    InvalidJson(#[cause] serde_json::Error),
+
+    /// The protobuf payload could not be decoded.
+    #[fail(display = "invalid OTLP protobuf payload: {}", _0)]
+    InvalidProtobuf(&'static str),
+}
+
+/// Deserializes an integer that may be encoded as JSON number or string.
+///
+/// The protobuf JSON mapping encodes 64-bit integers as strings, but not all exporters follow this.
+fn deserialize_lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: fmt::Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Lenient<T> {
+        Number(T),
+        String(String),
+    }
+
+    match Lenient::<T>::deserialize(deserializer)? {
+        Lenient::Number(value) => Ok(value),
+        Lenient::String(value) => value.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// The value of an attribute.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum AnyValue {
+    StringValue(String),
+    BoolValue(bool),
+    #[serde(deserialize_with = "deserialize_lenient")]
+    IntValue(i64),
+    DoubleValue(f64),
+    ArrayValue(ArrayValue),
+    KvlistValue(KeyValueList),
+    /// Binary values are not supported in events and are skipped.
+    BytesValue(IgnoredAny),
+}
+
+impl AnyValue {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            AnyValue::StringValue(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            AnyValue::IntValue(int) => Some(*int),
+            AnyValue::StringValue(string) => string.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Option<Value> {
+        Some(match self {
+            AnyValue::StringValue(string) => Value::String(string.clone()),
+            AnyValue::BoolValue(boolean) => Value::Bool(*boolean),
+            AnyValue::IntValue(int) => Value::I64(*int),
+            AnyValue::DoubleValue(double) => Value::F64(*double),
+            AnyValue::ArrayValue(array) => Value::Array(
+                array
+                    .values
+                    .iter()
+                    .map(|value| Annotated::from(value.to_value()))
+                    .collect(),
+            ),
+            AnyValue::KvlistValue(list) => Value::Object(attributes_to_object(&list.values)),
+            AnyValue::BytesValue(_) => return None,
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ArrayValue {
+    #[serde(default)]
+    values: Vec<AnyValue>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KeyValueList {
+    #[serde(default)]
+    values: Vec<KeyValue>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KeyValue {
+    #[serde(default)]
+    key: String,
+    #[serde(default)]
+    value: Option<AnyValue>,
+}
+
+/// Returns the value of the attribute with the given key.
+fn get_attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a AnyValue> {
+    let attribute = attributes.iter().find(|attribute| attribute.key == key)?;
+    attribute.value.as_ref()
+}
+
+/// Converts attributes into an object, skipping all values that cannot be represented.
+fn attributes_to_object(attributes: &[KeyValue]) -> Object<Value> {
+    attributes
+        .iter()
+        .filter_map(|attribute| {
+            let value = attribute.value.as_ref()?.to_value()?;
+            Some((attribute.key.clone(), Annotated::new(value)))
+        })
+        .collect()
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Status {
+    #[serde(default)]
+    code: i32,
+}
+
+/// A span as exported by OpenTelemetry.
+///
+/// Trace and span ids are stored as lowercase hex strings, which is their encoding in JSON.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OtelSpan {
+    #[serde(default)]
+    trace_id: String,
+    #[serde(default)]
+    span_id: String,
+    #[serde(default)]
+    parent_span_id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    kind: i32,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    start_time_unix_nano: u64,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    end_time_unix_nano: u64,
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+    #[serde(default)]
+    status: Status,
+}
+
+impl OtelSpan {
+    fn attribute(&self, key: &str) -> Option<&AnyValue> {
+        get_attribute(&self.attributes, key)
+    }
+
+    /// Derives the operation from semantic conventions and the kind of the span.
+    fn op(&self) -> &'static str {
+        let kind = self.kind;
+
+        if self.attribute("db.system").is_some() {
+            "db"
+        } else if self.attribute("http.method").is_some() {
+            match kind {
+                SPAN_KIND_SERVER => "http.server",
+                _ => "http.client",
+            }
+        } else if self.attribute("rpc.system").is_some() {
+            match kind {
+                SPAN_KIND_SERVER => "rpc.server",
+                _ => "rpc.client",
+            }
+        } else if self.attribute("messaging.system").is_some() {
+            match kind {
+                SPAN_KIND_CONSUMER => "queue.process",
+                _ => "queue.publish",
+            }
+        } else {
+            match kind {
+                SPAN_KIND_SERVER => "server",
+                SPAN_KIND_CLIENT => "client",
+                SPAN_KIND_PRODUCER => "producer",
+                SPAN_KIND_CONSUMER => "consumer",
+                _ => "default",
+            }
+        }
+    }
+
+    /// Maps the OpenTelemetry status to a span status.
+    ///
+    /// OpenTelemetry only distinguishes unset, ok and error. For errors, the HTTP status code is
+    /// used to determine a more specific status if available.
+    fn status(&self) -> SpanStatus {
+        if self.status.code != STATUS_CODE_ERROR {
+            return SpanStatus::Ok;
+        }
+
+        match self
+            .attribute("http.status_code")
+            .and_then(AnyValue::as_i64)
+        {
+            Some(code) => span_status_from_http(code),
+            None => SpanStatus::UnknownError,
+        }
+    }
+}
+
+fn span_status_from_http(code: i64) -> SpanStatus {
+    match code {
+        401 => SpanStatus::Unauthenticated,
+        403 => SpanStatus::PermissionDenied,
+        404 => SpanStatus::NotFound,
+        409 => SpanStatus::AlreadyExists,
+        429 => SpanStatus::ResourceExhausted,
+        499 => SpanStatus::Cancelled,
+        501 => SpanStatus::Unimplemented,
+        503 => SpanStatus::Unavailable,
+        504 => SpanStatus::DeadlineExceeded,
+        400..=499 => SpanStatus::InvalidArgument,
+        500..=599 => SpanStatus::InternalError,
+        _ => SpanStatus::UnknownError,
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ScopeSpans {
+    #[serde(default)]
+    spans: Vec<OtelSpan>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Resource {
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    #[serde(default)]
+    resource: Resource,
+    /// Spans grouped by instrumentation scope, formerly called instrumentation library.
+    #[serde(default, alias = "instrumentationLibrarySpans")]
+    scope_spans: Vec<ScopeSpans>,
+}
+
+/// A trace export request sent by OpenTelemetry SDKs or collectors.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OtlpTraces {
+    #[serde(default)]
+    resource_spans: Vec<ResourceSpans>,
+}
+
+impl OtlpTraces {
+    /// Parses a trace export request from its JSON encoding.
+    pub fn from_json(data: &[u8]) -> Result<Self, OtlpError> {
+        serde_json::from_slice(data).map_err(OtlpError::InvalidJson)
+    }
+
+    /// Parses a trace export request from its protobuf encoding.
+    pub fn from_protobuf(data: &[u8]) -> Result<Self, OtlpError> {
+        Self::decode(data)
+    }
+
+    /// Returns `true` if this request does not contain any spans.
+    pub fn is_empty(&self) -> bool {
+        self.resource_spans
+            .iter()
+            .flat_map(|resource_spans| &resource_spans.scope_spans)
+            .all(|scope_spans| scope_spans.spans.is_empty())
+    }
+
+    /// Converts all spans into transaction events.
+    ///
+    /// Spans with cyclic parent references cannot be attributed to a transaction and are dropped.
+    pub fn into_events(self) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        for resource_spans in self.resource_spans {
+            let mut traces = BTreeMap::<String, Vec<OtelSpan>>::new();
+            for scope_spans in resource_spans.scope_spans {
+                for span in scope_spans.spans {
+                    traces.entry(span.trace_id.clone()).or_default().push(span);
+                }
+            }
+
+            for spans in traces.values() {
+                events.extend(group_transactions(&resource_spans.resource, spans));
+            }
+        }
+
+        events
+    }
+}
+
+/// Walks up the parent chain of a span and returns the id of its local root.
+///
+/// Returns `None` if the span has no id or the chain contains a cycle.
+fn find_root<'a>(parents: &HashMap<&'a str, &'a str>, span_id: &'a str) -> Option<&'a str> {
+    // Spans without an id cannot be referenced by other spans and are dropped.
+    if span_id.is_empty() {
+        return None;
+    }
+
+    let mut current = span_id;
+
+    for _ in 0..=parents.len() {
+        match parents.get(current) {
+            Some(&parent) if parents.contains_key(parent) => current = parent,
+            _ => return Some(current),
+        }
+    }
+
+    None
+}
+
+/// Creates transactions for all local roots among spans of a single resource and trace.
+fn group_transactions(resource: &Resource, spans: &[OtelSpan]) -> Vec<Event> {
+    let parents: HashMap<&str, &str> = spans
+        .iter()
+        .filter(|span| !span.span_id.is_empty())
+        .map(|span| (span.span_id.as_str(), span.parent_span_id.as_str()))
+        .collect();
+
+    let roots: Vec<_> = spans
+        .iter()
+        .map(|span| find_root(&parents, &span.span_id))
+        .collect();
+
+    let mut events = Vec::new();
+    let mut indexes = HashMap::new();
+
+    for (span, root) in spans.iter().zip(&roots) {
+        if *root == Some(span.span_id.as_str()) {
+            indexes.insert(span.span_id.as_str(), events.len());
+            events.push(transaction_from_span(resource, span));
+        }
+    }
+
+    for (span, root) in spans.iter().zip(&roots) {
+        let index = match *root {
+            Some(root) if root != span.span_id => indexes.get(root),
+            _ => None,
+        };
+
+        if let Some(&index) = index {
+            let event: &mut Event = &mut events[index];
+            if let Some(spans) = event.spans.value_mut() {
+                spans.push(Annotated::new(span_from_otel(span)));
+            }
+        }
+    }
+
+    events
+}
+
+fn timestamp(nanos: u64) -> Annotated<DateTime<Utc>> {
+    if nanos == 0 {
+        return Annotated::empty();
+    }
+
+    let secs = (nanos / 1_000_000_000) as i64;
+    let nsecs = (nanos % 1_000_000_000) as u32;
+    Annotated::from(Utc.timestamp_opt(secs, nsecs).single())
+}
+
+fn span_id(id: &str) -> Annotated<SpanId> {
+    match id {
+        "" => Annotated::empty(),
+        _ => Annotated::new(SpanId(id.to_owned())),
+    }
+}
+
+fn trace_id(id: &str) -> Annotated<TraceId> {
+    match id {
+        "" => Annotated::empty(),
+        _ => Annotated::new(TraceId(id.to_owned())),
+    }
+}
+
+fn span_data(span: &OtelSpan) -> Object<Value> {
+    let mut other = Object::new();
+
+    if !span.attributes.is_empty() {
+        let data = Value::Object(attributes_to_object(&span.attributes));
+        other.insert("data".to_owned(), Annotated::new(data));
+    }
+
+    other
+}
+
+fn span_from_otel(span: &OtelSpan) -> Span {
+    let mut other = span_data(span);
+    let status = Value::String(span.status().to_string());
+    other.insert("status".to_owned(), Annotated::new(status));
+
+    Span {
+        timestamp: timestamp(span.end_time_unix_nano),
+        start_timestamp: timestamp(span.start_time_unix_nano),
+        description: Annotated::new(span.name.clone()),
+        op: Annotated::new(span.op().to_owned()),
+        span_id: span_id(&span.span_id),
+        parent_span_id: span_id(&span.parent_span_id),
+        trace_id: trace_id(&span.trace_id),
+        exclusive_time: Annotated::empty(),
+        other,
+    }
+}
+
+/// Derives the event id of a transaction from its trace id and root span id.
+///
+/// Exporters retry failed requests, so the same transaction must always receive the same id.
+fn event_id(span: &OtelSpan) -> EventId {
+    let mut hasher = Sha1::new();
+    hasher.input(span.trace_id.as_bytes());
+    hasher.input(b"\0");
+    hasher.input(span.span_id.as_bytes());
+
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hasher.result()[..16]);
+    EventId(Uuid::from_bytes(bytes))
+}
+
+fn transaction_from_span(resource: &Resource, span: &OtelSpan) -> Event {
+    let mut contexts = Contexts::new();
+
+    contexts.add(Context::Trace(Box::new(TraceContext {
+        trace_id: trace_id(&span.trace_id),
+        span_id: span_id(&span.span_id),
+        parent_span_id: span_id(&span.parent_span_id),
+        op: Annotated::new(span.op().to_owned()),
+        status: Annotated::new(span.status()),
+        other: span_data(span),
+    })));
+
+    if !resource.attributes.is_empty() {
+        let mut otel = Object::new();
+        let attributes = Value::Object(attributes_to_object(&resource.attributes));
+        otel.insert("resource".to_owned(), Annotated::new(attributes));
+        contexts.insert(
+            OTEL_CONTEXT.to_owned(),
+            Annotated::new(Context::Other(otel).into()),
+        );
+    }
+
+    let release = get_attribute(&resource.attributes, SERVICE_VERSION)
+        .and_then(AnyValue::as_str)
+        .map(|release| LenientString(release.to_owned()));
+
+    let environment = get_attribute(&resource.attributes, DEPLOYMENT_ENVIRONMENT)
+        .and_then(AnyValue::as_str)
+        .map(str::to_owned);
+
+    Event {
+        id: Annotated::new(event_id(span)),
+        ty: Annotated::new(EventType::Transaction),
+        transaction: Annotated::new(span.name.clone()),
+        platform: Annotated::new("other".to_owned()),
+        timestamp: timestamp(span.end_time_unix_nano),
+        start_timestamp: timestamp(span.start_time_unix_nano),
+        release: Annotated::from(release),
+        environment: Annotated::from(environment),
+        contexts: Annotated::new(contexts),
+        spans: Annotated::new(Vec::new()),
+        ..Default::default()
+    }
+}
+
+/// Encodes binary ids as lowercase hex strings.
+fn hex_encode(bytes: &[u8]) -> String {
+    let mut string = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(string, "{:02x}", byte).ok();
+    }
+    string
+}
+
+/// A minimal reader for the protobuf wire format.
+///
+/// This supports the subset of the wire format used by trace exports. Unknown fields are skipped.
+struct ProtoReader<'a> {
+    data: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, depth: 0 }
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<&'a [u8], OtlpError> {
+        if self.data.len() < len {
+            return Err(OtlpError::InvalidProtobuf("unexpected end of data"));
+        }
+
+        let (slice, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(slice)
+    }
+
+    fn read_varint(&mut self) -> Result<u64, OtlpError> {
+        let mut value = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_slice(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(OtlpError::InvalidProtobuf("varint exceeds 64 bits"))
+    }
+
+    fn read_fixed64(&mut self) -> Result<u64, OtlpError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_slice(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_bytes(&mut self) -> Result<&'a [u8], OtlpError> {
+        let len = self.read_varint()? as usize;
+        self.read_slice(len)
+    }
+
+    fn read_string(&mut self) -> Result<String, OtlpError> {
+        let bytes = self.read_bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| OtlpError::InvalidProtobuf("invalid string"))
+    }
+
+    /// Returns a reader for an embedded message, failing if messages are nested too deeply.
+    fn read_nested(&mut self) -> Result<ProtoReader<'a>, OtlpError> {
+        if self.depth >= MAX_PROTOBUF_DEPTH {
+            return Err(OtlpError::InvalidProtobuf("nesting too deep"));
+        }
+
+        let data = self.read_bytes()?;
+        Ok(ProtoReader {
+            data,
+            depth: self.depth + 1,
+        })
+    }
+
+    /// Reads and decodes an embedded message.
+    fn read_message<T: DecodeProto>(&mut self) -> Result<T, OtlpError> {
+        T::decode_from(self.read_nested()?)
+    }
+
+    /// Returns the next field number and wire type, or `None` at the end of the message.
+    fn next_field(&mut self) -> Result<Option<(u64, u8)>, OtlpError> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+
+        let key = self.read_varint()?;
+        Ok(Some((key >> 3, (key & 0x7) as u8)))
+    }
+
+    fn skip(&mut self, wire_type: u8) -> Result<(), OtlpError> {
+        match wire_type {
+            WIRE_VARINT => self.read_varint().map(drop),
+            WIRE_FIXED64 => self.read_slice(8).map(drop),
+            WIRE_LEN => self.read_bytes().map(drop),
+            WIRE_FIXED32 => self.read_slice(4).map(drop),
+            _ => Err(OtlpError::InvalidProtobuf("unsupported wire type")),
+        }
+    }
+}
+
+/// A message that can be decoded from the protobuf wire format.
+trait DecodeProto: Default {
+    /// Decodes a single field of this message from the reader.
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError>;
+
+    fn decode(data: &[u8]) -> Result<Self, OtlpError> {
+        Self::decode_from(ProtoReader::new(data))
+    }
+
+    fn decode_from(mut reader: ProtoReader<'_>) -> Result<Self, OtlpError> {
+        let mut message = Self::default();
+
+        while let Some((field, wire_type)) = reader.next_field()? {
+            message.decode_field(field, wire_type, &mut reader)?;
+        }
+
+        Ok(message)
+    }
+}
+
+impl DecodeProto for OtlpTraces {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (1, WIRE_LEN) => self.resource_spans.push(reader.read_message()?),
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl DecodeProto for ResourceSpans {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (1, WIRE_LEN) => self.resource = reader.read_message()?,
+            // Field 1000 holds the deprecated instrumentation library spans.
+            (2, WIRE_LEN) | (1000, WIRE_LEN) => self.scope_spans.push(reader.read_message()?),
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl DecodeProto for Resource {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (1, WIRE_LEN) => self.attributes.push(reader.read_message()?),
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl DecodeProto for ScopeSpans {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (2, WIRE_LEN) => self.spans.push(reader.read_message()?),
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl DecodeProto for OtelSpan {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (1, WIRE_LEN) => self.trace_id = hex_encode(reader.read_bytes()?),
+            (2, WIRE_LEN) => self.span_id = hex_encode(reader.read_bytes()?),
+            (4, WIRE_LEN) => self.parent_span_id = hex_encode(reader.read_bytes()?),
+            (5, WIRE_LEN) => self.name = reader.read_string()?,
+            (6, WIRE_VARINT) => self.kind = reader.read_varint()? as i32,
+            (7, WIRE_FIXED64) => self.start_time_unix_nano = reader.read_fixed64()?,
+            (8, WIRE_FIXED64) => self.end_time_unix_nano = reader.read_fixed64()?,
+            (9, WIRE_LEN) => self.attributes.push(reader.read_message()?),
+            (15, WIRE_LEN) => self.status = reader.read_message()?,
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl DecodeProto for Status {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (3, WIRE_VARINT) => self.code = reader.read_varint()? as i32,
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl DecodeProto for KeyValue {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (1, WIRE_LEN) => self.key = reader.read_string()?,
+            (2, WIRE_LEN) => self.value = decode_any_value(reader.read_nested()?)?,
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl DecodeProto for ArrayValue {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (1, WIRE_LEN) => {
+                if let Some(value) = decode_any_value(reader.read_nested()?)? {
+                    self.values.push(value);
+                }
+            }
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl DecodeProto for KeyValueList {
+    fn decode_field(
+        &mut self,
+        field: u64,
+        wire_type: u8,
+        reader: &mut ProtoReader<'_>,
+    ) -> Result<(), OtlpError> {
+        match (field, wire_type) {
+            (1, WIRE_LEN) => self.values.push(reader.read_message()?),
+            _ => reader.skip(wire_type)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Decodes an `AnyValue`, which is a `oneof` in protobuf. Returns `None` if no value is set.
+fn decode_any_value(mut reader: ProtoReader<'_>) -> Result<Option<AnyValue>, OtlpError> {
+    let mut value = None;
+
+    while let Some((field, wire_type)) = reader.next_field()? {
+        value = Some(match (field, wire_type) {
+            (1, WIRE_LEN) => AnyValue::StringValue(reader.read_string()?),
+            (2, WIRE_VARINT) => AnyValue::BoolValue(reader.read_varint()? != 0),
+            (3, WIRE_VARINT) => AnyValue::IntValue(reader.read_varint()? as i64),
+            (4, WIRE_FIXED64) => AnyValue::DoubleValue(f64::from_bits(reader.read_fixed64()?)),
+            (5, WIRE_LEN) => AnyValue::ArrayValue(reader.read_message()?),
+            (6, WIRE_LEN) => AnyValue::KvlistValue(reader.read_message()?),
+            (7, WIRE_LEN) => {
+                reader.read_bytes()?;
+                AnyValue::BytesValue(IgnoredAny)
+            }
+            _ => {
+                reader.skip(wire_type)?;
+                continue;
+            }
+        });
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace_context(event: &Event) -> &TraceContext {
+        let contexts = event.contexts.value().unwrap();
+        match contexts
+            .get("trace")
+            .and_then(Annotated::value)
+            .map(|c| &c.0)
+        {
+            Some(Context::Trace(trace)) => &**trace,
+            _ => panic!("missing trace context"),
+        }
+    }
+
+    fn span_ops(event: &Event) -> Vec<&str> {
+        event
+            .spans
+            .value()
+            .unwrap()
+            .iter()
+            .filter_map(|span| span.value()?.op.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_json_transaction() {
+        let json = r#"{
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        {"key": "service.name", "value": {"stringValue": "checkout"}},
+                        {"key": "service.version", "value": {"stringValue": "1.2.3"}},
+                        {"key": "deployment.environment", "value": {"stringValue": "prod"}}
+                    ]
+                },
+                "scopeSpans": [{
+                    "spans": [
+                        {
+                            "traceId": "4c79f60c11214eb38604f4ae0781bfb2",
+                            "spanId": "fa90fdead5f74052",
+                            "name": "GET /orders",
+                            "kind": 2,
+                            "startTimeUnixNano": "1600000000000000000",
+                            "endTimeUnixNano": "1600000001000000000",
+                            "attributes": [
+                                {"key": "http.method", "value": {"stringValue": "GET"}},
+                                {"key": "http.status_code", "value": {"intValue": "404"}}
+                            ],
+                            "status": {"code": 2}
+                        },
+                        {
+                            "traceId": "4c79f60c11214eb38604f4ae0781bfb2",
+                            "spanId": "fa90fdead5f74053",
+                            "parentSpanId": "fa90fdead5f74052",
+                            "name": "SELECT orders",
+                            "kind": 3,
+                            "startTimeUnixNano": 1600000000100000000,
+                            "endTimeUnixNano": 1600000000200000000,
+                            "attributes": [
+                                {"key": "db.system", "value": {"stringValue": "postgresql"}}
+                            ]
+                        }
+                    ]
+                }]
+            }]
+        }"#;
+
+        let events = OtlpTraces::from_json(json.as_bytes())
+            .unwrap()
+            .into_events();
+        assert_eq!(events.len(), 1);
+
+        let event = &events[0];
+        assert_eq_dbg!(event.ty.value(), Some(&EventType::Transaction));
+        assert_eq_dbg!(event.transaction.as_str(), Some("GET /orders"));
+        assert_eq_dbg!(event.release.as_str(), Some("1.2.3"));
+        assert_eq_dbg!(event.environment.as_str(), Some("prod"));
+        assert_eq_dbg!(
+            event.start_timestamp.value(),
+            Some(&Utc.timestamp(1_600_000_000, 0))
+        );
+
+        let trace = trace_context(event);
+        assert_eq_dbg!(trace.op.as_str(), Some("http.server"));
+        assert_eq_dbg!(trace.status.value(), Some(&SpanStatus::NotFound));
+        assert_eq_dbg!(trace.parent_span_id.value(), None);
+
+        assert_eq!(span_ops(event), vec!["db"]);
+        let span = event.spans.value().unwrap()[0].value().unwrap();
+        assert_eq_dbg!(
+            span.parent_span_id.value(),
+            Some(&SpanId("fa90fdead5f74052".to_owned()))
+        );
+        assert_eq_dbg!(
+            span.start_timestamp.value(),
+            Some(&Utc.timestamp(1_600_000_000, 100_000_000))
+        );
+    }
+
+    #[test]
+    fn test_local_roots() {
+        // The first span has a remote parent from another service. The second span belongs to a
+        // different trace. The next two spans reference each other and are dropped, as is the last
+        // span without an id.
+        let json = r#"{
+            "resourceSpans": [{
+                "instrumentationLibrarySpans": [{
+                    "spans": [
+                        {
+                            "traceId": "4c79f60c11214eb38604f4ae0781bfb2",
+                            "spanId": "fa90fdead5f74052",
+                            "parentSpanId": "aaaaaaaaaaaaaaaa",
+                            "name": "process",
+                            "kind": 5,
+                            "attributes": [
+                                {"key": "messaging.system", "value": {"stringValue": "kafka"}}
+                            ]
+                        },
+                        {
+                            "traceId": "4c79f60c11214eb38604f4ae0781bfb3",
+                            "spanId": "fa90fdead5f74053",
+                            "name": "cleanup"
+                        },
+                        {
+                            "traceId": "4c79f60c11214eb38604f4ae0781bfb3",
+                            "spanId": "bbbbbbbbbbbbbbbb",
+                            "parentSpanId": "cccccccccccccccc",
+                            "name": "a"
+                        },
+                        {
+                            "traceId": "4c79f60c11214eb38604f4ae0781bfb3",
+                            "spanId": "cccccccccccccccc",
+                            "parentSpanId": "bbbbbbbbbbbbbbbb",
+                            "name": "b"
+                        },
+                        {
+                            "traceId": "4c79f60c11214eb38604f4ae0781bfb3",
+                            "spanId": "",
+                            "name": "c"
+                        }
+                    ]
+                }]
+            }]
+        }"#;
+
+        let events = OtlpTraces::from_json(json.as_bytes())
+            .unwrap()
+            .into_events();
+        assert_eq!(events.len(), 2);
+
+        // Event ids are stable across exports of the same spans.
+        let retried = OtlpTraces::from_json(json.as_bytes())
+            .unwrap()
+            .into_events();
+        assert_eq_dbg!(events[0].id, retried[0].id);
+        assert_ne!(events[0].id.value(), events[1].id.value());
+
+        let trace = trace_context(&events[0]);
+        assert_eq_dbg!(trace.op.as_str(), Some("queue.process"));
+        assert_eq_dbg!(
+            trace.parent_span_id.value(),
+            Some(&SpanId("aaaaaaaaaaaaaaaa".to_owned()))
+        );
+
+        assert_eq_dbg!(events[1].transaction.as_str(), Some("cleanup"));
+        assert_eq_dbg!(trace_context(&events[1]).op.as_str(), Some("default"));
+        assert!(span_ops(&events[1]).is_empty());
+    }
+
+    fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    fn encode_len(field: u64, data: &[u8], buf: &mut Vec<u8>) {
+        encode_varint((field << 3) | u64::from(WIRE_LEN), buf);
+        encode_varint(data.len() as u64, buf);
+        buf.extend_from_slice(data);
+    }
+
+    fn encode_fixed64(field: u64, value: u64, buf: &mut Vec<u8>) {
+        encode_varint((field << 3) | u64::from(WIRE_FIXED64), buf);
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn encode_attribute(key: &str, value: &str, buf: &mut Vec<u8>) {
+        let mut any_value = Vec::new();
+        encode_len(1, value.as_bytes(), &mut any_value);
+
+        let mut key_value = Vec::new();
+        encode_len(1, key.as_bytes(), &mut key_value);
+        encode_len(2, &any_value, &mut key_value);
+
+        encode_len(9, &key_value, buf);
+    }
+
+    #[test]
+    fn test_protobuf_transaction() {
+        let mut span = Vec::new();
+        encode_len(1, &[0x4c; 16], &mut span);
+        encode_len(2, &[0xfa; 8], &mut span);
+        encode_len(5, b"GET /orders", &mut span);
+        encode_varint((6 << 3) | u64::from(WIRE_VARINT), &mut span);
+        encode_varint(2, &mut span);
+        encode_fixed64(7, 1_600_000_000_000_000_000, &mut span);
+        encode_fixed64(8, 1_600_000_001_000_000_000, &mut span);
+        encode_attribute("http.method", "GET", &mut span);
+        // An unknown field that must be skipped.
+        encode_len(42, b"ignored", &mut span);
+
+        let mut scope_spans = Vec::new();
+        encode_len(2, &span, &mut scope_spans);
+
+        let mut resource_spans = Vec::new();
+        encode_len(2, &scope_spans, &mut resource_spans);
+
+        let mut request = Vec::new();
+        encode_len(1, &resource_spans, &mut request);
+
+        let events = OtlpTraces::from_protobuf(&request).unwrap().into_events();
+        assert_eq!(events.len(), 1);
+
+        let event = &events[0];
+        assert_eq_dbg!(event.transaction.as_str(), Some("GET /orders"));
+        assert_eq_dbg!(
+            event.timestamp.value(),
+            Some(&Utc.timestamp(1_600_000_001, 0))
+        );
+
+        let trace = trace_context(event);
+        assert_eq_dbg!(trace.op.as_str(), Some("http.server"));
+        assert_eq_dbg!(trace.status.value(), Some(&SpanStatus::Ok));
+        assert_eq_dbg!(trace.trace_id.value(), Some(&TraceId("4c".repeat(16))));
+        assert_eq_dbg!(trace.span_id.value(), Some(&SpanId("fa".repeat(8))));
+    }
+
+    #[test]
+    fn test_protobuf_truncated() {
+        let mut request = Vec::new();
+        encode_len(1, b"resource spans", &mut request);
+        request.truncate(5);
+
+        assert!(OtlpTraces::from_protobuf(&request).is_err());
+    }
+
+    #[test]
+    fn test_protobuf_nesting_too_deep() {
+        // An attribute value of arrays nested far beyond the depth limit.
+        let mut any_value = Vec::new();
+        for _ in 0..10_000 {
+            let mut array_value = Vec::new();
+            encode_len(1, &any_value, &mut array_value);
+            any_value.clear();
+            encode_len(5, &array_value, &mut any_value);
+        }
+
+        let mut key_value = Vec::new();
+        encode_len(1, b"nested", &mut key_value);
+        encode_len(2, &any_value, &mut key_value);
+
+        let mut span = Vec::new();
+        encode_len(9, &key_value, &mut span);
+
+        let mut scope_spans = Vec::new();
+        encode_len(2, &span, &mut scope_spans);
+
+        let mut resource_spans = Vec::new();
+        encode_len(2, &scope_spans, &mut resource_spans);
+
+        let mut request = Vec::new();
+        encode_len(1, &resource_spans, &mut request);
+
+        assert!(OtlpTraces::from_protobuf(&request).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 //! Contains definitions for the security report interfaces.
 //!
-//! The security interfaces are CSP, HPKP, ExpectCT and ExpectStaple.
+//! The security interfaces are CSP, HPKP, ExpectCT, ExpectStaple and NEL. Reports sent through the
+//! Reporting API are also supported, see `ReportingApiReport`.
 
 use std::borrow::Cow;
 use std::collections::BTreeMap;
@@ -13,7 +14,7 @@ use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::protocol::{
-    Event, HeaderName, HeaderValue, Headers, LogEntry, PairList, Request, TagEntry, Tags,
+    Event, HeaderName, HeaderValue, Headers, Level, LogEntry, PairList, Request, TagEntry, Tags,
 };
 use crate::types::{Annotated, Array, Object, Value};
 
@@ -434,10 +435,74 @@ struct CspReportRaw {
     csp_report: CspRaw,
 }
 
+/// Body of a `csp-violation` report sent through the Reporting API.
+///
+/// This carries the same information as `CspRaw`, but uses different field names. There is no
+/// violated directive in these reports, so the effective directive is used in its place.
+///
+/// See https://www.w3.org/TR/CSP3/#reporting
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CspViolationRaw {
+    #[serde(rename = "documentURL")]
+    document_url: Option<String>,
+    #[serde(rename = "blockedURL", default = "CspRaw::default_blocked_uri")]
+    blocked_url: String,
+    effective_directive: CspDirective,
+    original_policy: Option<String>,
+    referrer: Option<String>,
+    status_code: Option<u64>,
+    source_file: Option<String>,
+    line_number: Option<u64>,
+    column_number: Option<u64>,
+    sample: Option<String>,
+    disposition: Option<String>,
+
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_json::Value>,
+}
+
+impl CspViolationRaw {
+    fn into_csp_raw(self) -> CspRaw {
+        CspRaw {
+            effective_directive: Some(self.effective_directive),
+            blocked_uri: self.blocked_url,
+            document_uri: self.document_url,
+            original_policy: self.original_policy,
+            referrer: self.referrer,
+            status_code: self.status_code,
+            violated_directive: self.effective_directive.to_string(),
+            source_file: self.source_file,
+            line_number: self.line_number,
+            column_number: self.column_number,
+            script_sample: self.sample,
+            disposition: self.disposition,
+            other: self.other,
+        }
+    }
+}
+
+/// A CSP report in either the legacy (report-uri) or the Reporting API (report-to) format.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum AnyCspReportRaw {
+    Legacy(CspReportRaw),
+    ReportingApi(ReportingApiReportRaw<CspViolationRaw>),
+}
+
+impl AnyCspReportRaw {
+    fn into_csp_raw(self) -> CspRaw {
+        match self {
+            AnyCspReportRaw::Legacy(report) => report.csp_report,
+            AnyCspReportRaw::ReportingApi(report) => report.body.into_csp_raw(),
+        }
+    }
+}
+
 /// Models the content of a CSP report.
 ///
-/// Note this models the older CSP reports (report-uri policy directive).
-/// The new CSP reports (using report-to policy directive) are different.
+/// Note this models the older CSP reports (report-uri policy directive). The new CSP reports
+/// (using report-to policy directive) are converted into this structure.
 ///
 /// NOTE: This is the structure used inside the Event (serialization is based on Annotated
 /// infrastructure). We also use a version of this structure to deserialize from raw JSON
@@ -483,8 +548,8 @@ pub struct Csp {
 
 impl Csp {
     pub fn apply_to_event(data: &[u8], event: &mut Event) -> Result<(), serde_json::Error> {
-        let raw_report = serde_json::from_slice::<CspReportRaw>(data)?;
-        let raw_csp = raw_report.csp_report;
+        let raw_report = serde_json::from_slice::<AnyCspReportRaw>(data)?;
+        let raw_csp = raw_report.into_csp_raw();
 
         let effective_directive = raw_csp
             .effective_directive()
@@ -1104,18 +1169,266 @@ impl ExpectStaple {
     }
 }
 
+/// A single report sent through the Reporting API.
+///
+/// Browsers send batches of these reports as JSON array with content type
+/// `application/reports+json`. The contents of the body depend on the type of the report.
+///
+/// See https://w3c.github.io/reporting/#serialize-reports
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+struct ReportingApiReportRaw<T> {
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age: Option<u64>,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    body: T,
+}
+
+/// Body of a `network-error` report as sent by a user agent.
+///
+/// See `Nel` for meaning of fields.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+struct NelRaw {
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phase: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protocol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referrer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sampling_fraction: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_code: Option<u64>,
+
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_json::Value>,
+}
+
+impl NelRaw {
+    fn get_message(&self, url: &str) -> String {
+        format!("Network error '{}' for '{}'", self.ty, url)
+    }
+
+    fn into_protocol(self, url: String) -> Nel {
+        Nel {
+            url: Annotated::new(url),
+            ty: Annotated::new(self.ty),
+            phase: Annotated::from(self.phase),
+            elapsed_time: Annotated::from(self.elapsed_time),
+            method: Annotated::from(self.method),
+            protocol: Annotated::from(self.protocol),
+            referrer: Annotated::from(self.referrer),
+            sampling_fraction: Annotated::from(self.sampling_fraction),
+            server_ip: Annotated::from(self.server_ip),
+            status_code: Annotated::from(self.status_code),
+            other: self
+                .other
+                .into_iter()
+                .map(|(k, v)| (k, Annotated::from(v)))
+                .collect(),
+        }
+    }
+
+    fn get_tags(&self) -> Tags {
+        let mut tags = vec![Annotated::new(TagEntry(
+            Annotated::new("error-type".to_string()),
+            Annotated::new(self.ty.clone()),
+        ))];
+
+        if let Some(ref phase) = self.phase {
+            tags.push(Annotated::new(TagEntry(
+                Annotated::new("phase".to_string()),
+                Annotated::new(phase.clone()),
+            )));
+        }
+
+        if let Some(ref protocol) = self.protocol {
+            tags.push(Annotated::new(TagEntry(
+                Annotated::new("protocol".to_string()),
+                Annotated::new(protocol.clone()),
+            )));
+        }
+
+        Tags(PairList::from(tags))
+    }
+
+    fn get_request(&self, url: &str) -> Request {
+        let headers = match self.referrer {
+            Some(ref referrer) if !referrer.is_empty() => {
+                Annotated::new(Headers(PairList(vec![Annotated::new((
+                    Annotated::new(HeaderName::new("Referer")),
+                    Annotated::new(HeaderValue::new(referrer.clone())),
+                ))])))
+            }
+            Some(_) | None => Annotated::empty(),
+        };
+
+        Request {
+            url: Annotated::new(url.to_owned()),
+            method: Annotated::from(self.method.clone()),
+            headers,
+            ..Request::default()
+        }
+    }
+}
+
+/// Models a Network Error Logging (NEL) report.
+///
+/// NEL reports are sent by user agents through the Reporting API when a request to an origin with
+/// a NEL policy fails or, depending on the policy, succeeds.
+///
+/// See https://www.w3.org/TR/network-error-logging/
+#[derive(Clone, Debug, Default, PartialEq, Empty, FromValue, ToValue, ProcessValue)]
+pub struct Nel {
+    /// The URL of the request that the report was generated for.
+    #[metastructure(pii = "true")]
+    pub url: Annotated<String>,
+    /// The type of the network error, such as `dns.name_not_resolved`, or `ok` for successful
+    /// requests.
+    #[metastructure(field = "type")]
+    pub ty: Annotated<String>,
+    /// The phase of the request in which the error occurred (`dns`, `connection` or
+    /// `application`).
+    pub phase: Annotated<String>,
+    /// The time in milliseconds between the start of the request and its completion or abortion.
+    pub elapsed_time: Annotated<u64>,
+    /// The HTTP method of the request.
+    pub method: Annotated<String>,
+    /// The network protocol used to fetch the resource, such as `http/1.1` or `h2`.
+    pub protocol: Annotated<String>,
+    /// The referrer of the request.
+    #[metastructure(pii = "true")]
+    pub referrer: Annotated<String>,
+    /// The sampling rate that was applied by the user agent to send this report.
+    pub sampling_fraction: Annotated<f64>,
+    /// The IP address of the server the request was sent to.
+    #[metastructure(pii = "true")]
+    pub server_ip: Annotated<String>,
+    /// The HTTP status code of the response, or `0` if no response was received.
+    pub status_code: Annotated<u64>,
+    /// Additional arbitrary fields for forwards compatibility.
+    #[metastructure(pii = "true", additional_properties)]
+    pub other: Object<Value>,
+}
+
+impl Nel {
+    pub fn apply_to_event(data: &[u8], event: &mut Event) -> Result<(), serde_json::Error> {
+        let raw_report = serde_json::from_slice::<ReportingApiReportRaw<NelRaw>>(data)?;
+        let url = raw_report.url;
+        let raw_nel = raw_report.body;
+
+        event.logentry = Annotated::new(LogEntry::from(raw_nel.get_message(&url)));
+        event.culprit = Annotated::new(url.clone());
+        event.tags = Annotated::new(raw_nel.get_tags());
+        event.request = Annotated::new(raw_nel.get_request(&url));
+        event.nel = Annotated::new(raw_nel.into_protocol(url));
+
+        Ok(())
+    }
+}
+
+/// Body of a `deprecation` or `intervention` report sent through the Reporting API.
+///
+/// See https://wicg.github.io/deprecation-reporting/ and
+/// https://wicg.github.io/intervention-reporting/
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BrowserReportRaw {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anticipated_removal: Option<String>,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column_number: Option<u64>,
+}
+
+impl BrowserReportRaw {
+    fn get_culprit(&self) -> Option<String> {
+        let source_file = self.source_file.as_ref()?;
+        Some(match (self.line_number, self.column_number) {
+            (Some(line), Some(column)) => format!("{}:{}:{}", source_file, line, column),
+            (Some(line), None) => format!("{}:{}", source_file, line),
+            _ => source_file.clone(),
+        })
+    }
+
+    fn get_tags(&self, ty: &str) -> Tags {
+        let mut tags = vec![Annotated::new(TagEntry(
+            Annotated::new("report-type".to_string()),
+            Annotated::new(ty.to_owned()),
+        ))];
+
+        if let Some(ref id) = self.id {
+            tags.push(Annotated::new(TagEntry(
+                Annotated::new("report-id".to_string()),
+                Annotated::new(id.clone()),
+            )));
+        }
+
+        Tags(PairList::from(tags))
+    }
+}
+
+/// Applies a `deprecation` or `intervention` report from the Reporting API to an event.
+///
+/// These reports do not have a dedicated interface. Instead, they are stored as default events
+/// with the report's message, and the source location of the report as culprit.
+pub fn apply_browser_report_to_event(
+    data: &[u8],
+    event: &mut Event,
+) -> Result<(), serde_json::Error> {
+    let raw_report = serde_json::from_slice::<ReportingApiReportRaw<BrowserReportRaw>>(data)?;
+    let raw_body = raw_report.body;
+
+    let level = match raw_report.ty.as_str() {
+        "deprecation" => Level::Warning,
+        _ => Level::Info,
+    };
+
+    event.logentry = Annotated::new(LogEntry::from(raw_body.message.clone()));
+    event.level = Annotated::new(level);
+    event.culprit = Annotated::from(raw_body.get_culprit());
+    event.tags = Annotated::new(raw_body.get_tags(&raw_report.ty));
+    event.request = Annotated::new(Request {
+        url: Annotated::new(raw_report.url),
+        ..Request::default()
+    });
+
+    Ok(())
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SecurityReportType {
     Csp,
     ExpectCt,
     ExpectStaple,
     Hpkp,
+    Nel,
+    Deprecation,
+    Intervention,
 }
 
 impl SecurityReportType {
     /// Infers the type of a security report from its payload.
     ///
-    /// This looks into the JSON payload and tries to infer the type from keys. If no report
+    /// This looks into the JSON payload and tries to infer the type from keys. Reports sent
+    /// through the Reporting API are identified by their `type` attribute. If no report
     /// matches, an error is returned.
     pub fn from_json(data: &[u8]) -> Result<Option<Self>, serde_json::Error> {
         #[derive(Deserialize)]
@@ -1125,10 +1438,23 @@ impl SecurityReportType {
             known_pins: Option<IgnoredAny>,
             expect_staple_report: Option<IgnoredAny>,
             expect_ct_report: Option<IgnoredAny>,
+            #[serde(rename = "type")]
+            ty: Option<String>,
+            body: Option<IgnoredAny>,
         }
 
         let helper: SecurityReport = serde_json::from_slice(data)?;
 
+        if let (Some(ty), Some(_)) = (helper.ty.as_deref(), helper.body) {
+            return Ok(match ty {
+                "csp-violation" => Some(SecurityReportType::Csp),
+                "network-error" => Some(SecurityReportType::Nel),
+                "deprecation" => Some(SecurityReportType::Deprecation),
+                "intervention" => Some(SecurityReportType::Intervention),
+                _ => None,
+            });
+        }
+
         Ok(if helper.csp_report.is_some() {
             Some(SecurityReportType::Csp)
         } else if helper.known_pins.is_some() {
@@ -1957,4 +2283,189 @@ mod tests {
         let report_type = SecurityReportType::from_json(hpkp_report_text.as_bytes()).unwrap();
         assert_eq!(report_type, Some(SecurityReportType::Hpkp));
     }
+
+    #[test]
+    fn test_csp_reporting_api() {
+        let json = r#"{
+            "type": "csp-violation",
+            "age": 53531,
+            "url": "http://example.com",
+            "user_agent": "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36",
+            "body": {
+                "documentURL": "http://example.com",
+                "blockedURL": "http://example.com/lol.css",
+                "effectiveDirective": "style-src",
+                "disposition": "enforce"
+            }
+        }"#;
+
+        let mut event = Event::default();
+        Csp::apply_to_event(json.as_bytes(), &mut event).unwrap();
+
+        assert_annotated_snapshot!(Annotated::new(event), @r###"
+        {
+          "culprit": "style-src",
+          "logentry": {
+            "formatted": "Blocked 'style' from 'example.com'"
+          },
+          "request": {
+            "url": "http://example.com"
+          },
+          "tags": [
+            [
+              "effective-directive",
+              "style-src"
+            ],
+            [
+              "blocked-uri",
+              "http://example.com/lol.css"
+            ]
+          ],
+          "csp": {
+            "effective_directive": "style-src",
+            "blocked_uri": "http://example.com/lol.css",
+            "document_uri": "http://example.com",
+            "violated_directive": "style-src",
+            "disposition": "enforce"
+          }
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_nel_basic() {
+        let json = r#"{
+            "type": "network-error",
+            "age": 29,
+            "url": "https://example.com/about/",
+            "user_agent": "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36",
+            "body": {
+                "referrer": "https://example.com/",
+                "sampling_fraction": 1.0,
+                "server_ip": "192.0.2.1",
+                "protocol": "h2",
+                "method": "GET",
+                "status_code": 0,
+                "elapsed_time": 823,
+                "phase": "connection",
+                "type": "tcp.timed_out"
+            }
+        }"#;
+
+        let mut event = Event::default();
+        Nel::apply_to_event(json.as_bytes(), &mut event).unwrap();
+
+        assert_annotated_snapshot!(Annotated::new(event), @r###"
+        {
+          "culprit": "https://example.com/about/",
+          "logentry": {
+            "formatted": "Network error 'tcp.timed_out' for 'https://example.com/about/'"
+          },
+          "request": {
+            "url": "https://example.com/about/",
+            "method": "GET",
+            "headers": [
+              [
+                "Referer",
+                "https://example.com/"
+              ]
+            ]
+          },
+          "tags": [
+            [
+              "error-type",
+              "tcp.timed_out"
+            ],
+            [
+              "phase",
+              "connection"
+            ],
+            [
+              "protocol",
+              "h2"
+            ]
+          ],
+          "nel": {
+            "url": "https://example.com/about/",
+            "type": "tcp.timed_out",
+            "phase": "connection",
+            "elapsed_time": 823,
+            "method": "GET",
+            "protocol": "h2",
+            "referrer": "https://example.com/",
+            "sampling_fraction": 1.0,
+            "server_ip": "192.0.2.1",
+            "status_code": 0
+          }
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_deprecation_report() {
+        let json = r#"{
+            "type": "deprecation",
+            "age": 27,
+            "url": "https://example.com/",
+            "user_agent": "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36",
+            "body": {
+                "id": "websql",
+                "anticipatedRemoval": "2020-01-01",
+                "message": "WebSQL is deprecated and will be removed in Chrome 97",
+                "sourceFile": "https://example.com/index.js",
+                "lineNumber": 1234,
+                "columnNumber": 42
+            }
+        }"#;
+
+        let mut event = Event::default();
+        apply_browser_report_to_event(json.as_bytes(), &mut event).unwrap();
+
+        assert_annotated_snapshot!(Annotated::new(event), @r###"
+        {
+          "level": "warning",
+          "culprit": "https://example.com/index.js:1234:42",
+          "logentry": {
+            "formatted": "WebSQL is deprecated and will be removed in Chrome 97"
+          },
+          "request": {
+            "url": "https://example.com/"
+          },
+          "tags": [
+            [
+              "report-type",
+              "deprecation"
+            ],
+            [
+              "report-id",
+              "websql"
+            ]
+          ]
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_security_report_type_deserializer_recognizes_reporting_api_reports() {
+        let report = |ty: &str| {
+            format!(
+                r#"{{"type": "{}", "age": 0, "url": "https://example.com/", "body": {{}}}}"#,
+                ty
+            )
+        };
+
+        let report_type = |ty: &str| SecurityReportType::from_json(report(ty).as_bytes()).unwrap();
+
+        assert_eq!(report_type("csp-violation"), Some(SecurityReportType::Csp));
+        assert_eq!(report_type("network-error"), Some(SecurityReportType::Nel));
+        assert_eq!(
+            report_type("deprecation"),
+            Some(SecurityReportType::Deprecation)
+        );
+        assert_eq!(
+            report_type("intervention"),
+            Some(SecurityReportType::Intervention)
+        );
+        assert_eq!(report_type("crash"), None);
+    }
 }
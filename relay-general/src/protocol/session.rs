@@ -8,6 +8,8 @@ use failure::Fail;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use relay_common::is_false;
+
 /// The type of session event we're dealing with.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
@@ -94,11 +96,6 @@ fn default_sequence() -> u64 {
         .as_millis() as u64
 }
 
-#[allow(clippy::trivially_copy_pass_by_ref)]
-fn is_false(val: &bool) -> bool {
-    !val
-}
-
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SessionUpdate {
     /// The session identifier.
@@ -34,6 +34,11 @@ pub struct Span {
     #[metastructure(required = "true")]
     pub trace_id: Annotated<TraceId>,
 
+    /// Time in milliseconds spent in this span, excluding time covered by its child spans.
+    ///
+    /// This value should not be ingested and will be overwritten by the store normalizer.
+    pub exclusive_time: Annotated<f64>,
+
     // TODO remove retain when the api stabilizes
     /// Additional arbitrary fields for forwards compatibility.
     #[metastructure(additional_properties, retain = "true", pii = "maybe")]
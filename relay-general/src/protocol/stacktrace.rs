@@ -127,17 +127,17 @@ pub struct FrameVars(#[metastructure(skip_serialization = "empty")] pub Object<V
 pub struct FrameData {
     /// A reference to the sourcemap used.
     #[metastructure(max_chars = "path")]
-    sourcemap: Annotated<String>,
+    pub sourcemap: Annotated<String>,
     /// The original function name before it was resolved.
     #[metastructure(max_chars = "symbol")]
-    orig_function: Annotated<String>,
+    pub orig_function: Annotated<String>,
     /// The original minified filename.
     #[metastructure(max_chars = "path")]
-    orig_filename: Annotated<String>,
+    pub orig_filename: Annotated<String>,
     /// The original line number.
-    orig_lineno: Annotated<u64>,
+    pub orig_lineno: Annotated<u64>,
     /// The original column number.
-    orig_colno: Annotated<u64>,
+    pub orig_colno: Annotated<u64>,
     /// The original value of the in_app flag before grouping enhancers ran.
     ///
     /// Because we need to handle more cases the following values are used:
@@ -146,7 +146,7 @@ pub struct FrameData {
     /// - `-1`: in_app was set to `null`
     /// - `0`: in_app was set to `false`
     /// - `1`: in_app was set to `true`
-    orig_in_app: Annotated<i64>,
+    pub orig_in_app: Annotated<i64>,
     /// Additional keys not handled by this protocol.
     #[metastructure(additional_properties)]
     pub other: Object<Value>,
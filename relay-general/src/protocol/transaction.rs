@@ -0,0 +1,117 @@
+use std::fmt;
+use std::str::FromStr;
+
+use failure::Fail;
+
+use crate::processor::ProcessValue;
+use crate::types::{Annotated, Object, Value};
+
+/// Describes how the name of a transaction was determined.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TransactionSource {
+    /// User-defined name set by the application.
+    Custom,
+    /// Raw URL, potentially containing identifiers.
+    Url,
+    /// Parametrized URL or route.
+    Route,
+    /// Name of the view handling the request.
+    View,
+    /// Named after a software component, such as a function or class name.
+    Component,
+    /// Name of a background task.
+    Task,
+    /// URL that has been normalized by Relay.
+    Sanitized,
+    /// The source is not known.
+    Unknown,
+}
+
+impl TransactionSource {
+    /// Returns the string representation of this source.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TransactionSource::Custom => "custom",
+            TransactionSource::Url => "url",
+            TransactionSource::Route => "route",
+            TransactionSource::View => "view",
+            TransactionSource::Component => "component",
+            TransactionSource::Task => "task",
+            TransactionSource::Sanitized => "sanitized",
+            TransactionSource::Unknown => "unknown",
+        }
+    }
+}
+
+/// An error used when parsing `TransactionSource`.
+#[derive(Debug, Fail)]
+#[fail(display = "invalid transaction source")]
+pub struct ParseTransactionSourceError;
+
+impl FromStr for TransactionSource {
+    type Err = ParseTransactionSourceError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Ok(match string {
+            "custom" => TransactionSource::Custom,
+            "url" => TransactionSource::Url,
+            "route" => TransactionSource::Route,
+            "view" => TransactionSource::View,
+            "component" => TransactionSource::Component,
+            "task" => TransactionSource::Task,
+            "sanitized" => TransactionSource::Sanitized,
+            "unknown" => TransactionSource::Unknown,
+            _ => return Err(ParseTransactionSourceError),
+        })
+    }
+}
+
+impl fmt::Display for TransactionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+derive_string_meta_structure!(TransactionSource, "a transaction source");
+
+impl ProcessValue for TransactionSource {}
+
+/// Additional information about the name of a transaction.
+#[derive(Clone, Debug, Default, PartialEq, Empty, FromValue, ToValue, ProcessValue)]
+pub struct TransactionInfo {
+    /// Describes how the name of the transaction was determined.
+    pub source: Annotated<TransactionSource>,
+
+    /// Additional arbitrary fields for forwards compatibility.
+    #[metastructure(additional_properties, retain = "true")]
+    pub other: Object<Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_info_roundtrip() {
+        let json = r#"{
+  "source": "url"
+}"#;
+
+        let info = Annotated::new(TransactionInfo {
+            source: Annotated::new(TransactionSource::Url),
+            ..Default::default()
+        });
+
+        assert_eq_dbg!(info, Annotated::from_json(json).unwrap());
+        assert_eq_str!(json, info.to_json_pretty().unwrap());
+    }
+
+    #[test]
+    fn test_transaction_source_invalid() {
+        let info = Annotated::<TransactionInfo>::from_json(r#"{"source": "foo"}"#).unwrap();
+        let source = &info.value().unwrap().source;
+
+        assert!(source.value().is_none());
+        assert!(source.meta().has_errors());
+    }
+}
@@ -295,8 +295,10 @@ where
     where
         P: Processor,
     {
+        let len = self.0.len();
         for (idx, pair) in self.0.iter_mut().enumerate() {
-            let state = state.enter_index(idx, state.inner_attrs(), ValueType::for_field(pair));
+            let state =
+                state.enter_index_of(idx, len, state.inner_attrs(), ValueType::for_field(pair));
             process_value(pair, processor, &state)?;
         }
 
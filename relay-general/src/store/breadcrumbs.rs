@@ -0,0 +1,157 @@
+//! Collapsing of repeated breadcrumbs.
+use crate::protocol::{Breadcrumb, Event};
+use crate::types::{Annotated, Array};
+
+/// Returns whether `breadcrumb` repeats `previous`.
+///
+/// Breadcrumbs repeat if their category, message, level and data are equal. Differences in the
+/// type and timestamp are ignored, as well as meta data.
+fn is_repeat(previous: &Breadcrumb, breadcrumb: &Breadcrumb) -> bool {
+    previous.category.value() == breadcrumb.category.value()
+        && previous.message.value() == breadcrumb.message.value()
+        && previous.level.value() == breadcrumb.level.value()
+        && previous.data.value() == breadcrumb.data.value()
+}
+
+/// Merges a repeated breadcrumb into the previous one.
+///
+/// Breadcrumbs that have been collapsed before count with their full repeat count.
+fn merge_repeat(previous: &mut Breadcrumb, breadcrumb: &Breadcrumb) {
+    let count = previous.repeat_count.value().copied().unwrap_or(1)
+        + breadcrumb.repeat_count.value().copied().unwrap_or(1);
+    previous.repeat_count.set_value(Some(count));
+
+    let last_timestamp = breadcrumb
+        .last_timestamp
+        .value()
+        .or_else(|| breadcrumb.timestamp.value());
+
+    if let Some(last_timestamp) = last_timestamp {
+        previous.last_timestamp.set_value(Some(*last_timestamp));
+    }
+}
+
+/// Collapses consecutive repeated breadcrumbs into one.
+///
+/// The collapsed breadcrumb retains the timestamp of the first breadcrumb, and carries the number
+/// of repetitions in `repeat_count` and the timestamp of the last breadcrumb in `last_timestamp`.
+/// Invalid breadcrumbs are never collapsed.
+pub fn collapse_breadcrumbs(event: &mut Event) {
+    let breadcrumbs = match event
+        .breadcrumbs
+        .value_mut()
+        .as_mut()
+        .and_then(|breadcrumbs| breadcrumbs.values.value_mut().as_mut())
+    {
+        Some(breadcrumbs) => breadcrumbs,
+        None => return,
+    };
+
+    let mut collapsed = Array::<Breadcrumb>::with_capacity(breadcrumbs.len());
+    for breadcrumb in breadcrumbs.drain(..) {
+        let previous = collapsed.last_mut().and_then(|b| b.value_mut().as_mut());
+        if let (Some(previous), Some(current)) = (previous, breadcrumb.value()) {
+            if is_repeat(previous, current) {
+                merge_repeat(previous, current);
+                continue;
+            }
+        }
+
+        collapsed.push(breadcrumb);
+    }
+
+    *breadcrumbs = collapsed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::protocol::{Level, Values};
+
+    fn breadcrumb(category: &str, message: &str, second: u32) -> Annotated<Breadcrumb> {
+        Annotated::new(Breadcrumb {
+            timestamp: Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, second)),
+            category: Annotated::new(category.to_owned()),
+            level: Annotated::new(Level::Info),
+            message: Annotated::new(message.to_owned()),
+            ..Default::default()
+        })
+    }
+
+    fn collapse(breadcrumbs: Vec<Annotated<Breadcrumb>>) -> Array<Breadcrumb> {
+        let mut event = Event {
+            breadcrumbs: Annotated::new(Values::new(breadcrumbs)),
+            ..Default::default()
+        };
+
+        collapse_breadcrumbs(&mut event);
+        event
+            .breadcrumbs
+            .into_value()
+            .unwrap()
+            .values
+            .into_value()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_collapse_consecutive() {
+        let collapsed = collapse(vec![
+            breadcrumb("console", "tick", 1),
+            breadcrumb("console", "tick", 2),
+            breadcrumb("console", "tick", 3),
+            breadcrumb("ui.click", "button", 4),
+            breadcrumb("console", "tick", 5),
+        ]);
+
+        assert_eq!(collapsed.len(), 3);
+
+        let first = collapsed[0].value().unwrap();
+        assert_eq_dbg!(first.repeat_count.value(), Some(&3));
+        assert_eq_dbg!(
+            first.timestamp.value(),
+            Some(&Utc.ymd(2000, 1, 1).and_hms(0, 0, 1))
+        );
+        assert_eq_dbg!(
+            first.last_timestamp.value(),
+            Some(&Utc.ymd(2000, 1, 1).and_hms(0, 0, 3))
+        );
+
+        // Breadcrumbs that are not repeated remain unchanged.
+        assert_eq_dbg!(collapsed[1], breadcrumb("ui.click", "button", 4));
+        assert_eq_dbg!(collapsed[2], breadcrumb("console", "tick", 5));
+    }
+
+    #[test]
+    fn test_collapse_different_level() {
+        let mut warning = breadcrumb("console", "tick", 2);
+        if let Some(warning) = warning.value_mut() {
+            warning.level = Annotated::new(Level::Warning);
+        }
+
+        let collapsed = collapse(vec![breadcrumb("console", "tick", 1), warning]);
+        assert_eq!(collapsed.len(), 2);
+    }
+
+    #[test]
+    fn test_collapse_already_collapsed() {
+        let mut repeated = breadcrumb("console", "tick", 2);
+        if let Some(repeated) = repeated.value_mut() {
+            repeated.repeat_count = Annotated::new(5);
+            repeated.last_timestamp = Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, 6));
+        }
+
+        let collapsed = collapse(vec![breadcrumb("console", "tick", 1), repeated]);
+        assert_eq!(collapsed.len(), 1);
+
+        let first = collapsed[0].value().unwrap();
+        assert_eq_dbg!(first.repeat_count.value(), Some(&6));
+        assert_eq_dbg!(
+            first.last_timestamp.value(),
+            Some(&Utc.ymd(2000, 1, 1).and_hms(0, 0, 6))
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Computation of exclusive span times and operation breakdowns for transactions.
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::protocol::{Breakdowns, Event, EventType, Span};
+use crate::types::Annotated;
+
+/// Operation prefixes that are broken down in transactions.
+const BREAKDOWN_OPERATIONS: &[&str] = &["db", "http", "resource", "browser"];
+
+/// A time interval covered by a span.
+type Interval = (DateTime<Utc>, DateTime<Utc>);
+
+/// Returns the time covered by a span, if both of its timestamps are valid.
+fn get_interval(span: &Span) -> Option<Interval> {
+    let start = *span.start_timestamp.value()?;
+    let end = *span.timestamp.value()?;
+    if end < start {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+/// Returns the duration between two timestamps in milliseconds.
+fn duration_millis(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+    match (end - start).num_microseconds() {
+        Some(micros) => micros as f64 / 1000.0,
+        None => (end - start).num_milliseconds() as f64,
+    }
+}
+
+/// Returns the total time in milliseconds covered by at least one of the intervals.
+fn covered_millis(mut intervals: Vec<Interval>) -> f64 {
+    intervals.sort_unstable();
+
+    let mut total = 0.0;
+    let mut current: Option<Interval> = None;
+
+    for (start, end) in intervals {
+        current = match current {
+            Some((current_start, current_end)) if start <= current_end => {
+                Some((current_start, current_end.max(end)))
+            }
+            Some((current_start, current_end)) => {
+                total += duration_millis(current_start, current_end);
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+
+    if let Some((start, end)) = current {
+        total += duration_millis(start, end);
+    }
+
+    total
+}
+
+/// Returns the breakdown operation of a span, if any.
+fn get_operation(span: &Span) -> Option<&'static str> {
+    let op = span.op.value()?;
+    let prefix = op.split('.').next()?;
+    BREAKDOWN_OPERATIONS.iter().copied().find(|o| *o == prefix)
+}
+
+/// Computes the exclusive time of every span.
+///
+/// The exclusive time is the duration of a span minus the time covered by its direct children.
+/// Overlapping children are only subtracted once, and children exceeding the span are cut off.
+fn compute_exclusive_times(spans: &mut [Annotated<Span>]) {
+    let mut children = HashMap::<String, Vec<Interval>>::new();
+
+    for span in spans.iter().filter_map(Annotated::value) {
+        let parent_id = match span.parent_span_id.value() {
+            Some(parent_id) => parent_id.0.clone(),
+            None => continue,
+        };
+
+        if let Some(interval) = get_interval(span) {
+            children.entry(parent_id).or_default().push(interval);
+        }
+    }
+
+    for span in spans
+        .iter_mut()
+        .filter_map(|span| span.value_mut().as_mut())
+    {
+        let (start, end) = match get_interval(span) {
+            Some(interval) => interval,
+            None => {
+                span.exclusive_time = Annotated::empty();
+                continue;
+            }
+        };
+
+        let child_intervals = span
+            .span_id
+            .value()
+            .and_then(|span_id| children.get(&span_id.0))
+            .map(|intervals| {
+                intervals
+                    .iter()
+                    .map(|&(child_start, child_end)| (child_start.max(start), child_end.min(end)))
+                    .filter(|(child_start, child_end)| child_start < child_end)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let exclusive_time = duration_millis(start, end) - covered_millis(child_intervals);
+        span.exclusive_time = Annotated::new(exclusive_time.max(0.0));
+    }
+}
+
+/// Computes the time spent in each breakdown operation of a transaction.
+fn compute_operation_breakdowns(spans: &[Annotated<Span>]) -> Breakdowns {
+    let mut intervals = HashMap::<&str, Vec<Interval>>::new();
+
+    for span in spans.iter().filter_map(Annotated::value) {
+        if let (Some(operation), Some(interval)) = (get_operation(span), get_interval(span)) {
+            intervals.entry(operation).or_default().push(interval);
+        }
+    }
+
+    let mut breakdowns = Breakdowns::default();
+    for (operation, intervals) in intervals {
+        breakdowns.insert(
+            operation.to_owned(),
+            Annotated::new(covered_millis(intervals)),
+        );
+    }
+
+    breakdowns
+}
+
+/// Computes exclusive span times and operation breakdowns of a transaction.
+///
+/// Values sent by clients are overwritten. Breakdowns of all other event types are removed.
+pub fn compute_breakdowns(event: &mut Event) {
+    if event.ty.value() != Some(&EventType::Transaction) {
+        event.breakdowns = Annotated::empty();
+        return;
+    }
+
+    let spans = match event.spans.value_mut() {
+        Some(spans) => spans,
+        None => {
+            event.breakdowns = Annotated::empty();
+            return;
+        }
+    };
+
+    compute_exclusive_times(spans);
+    let breakdowns = compute_operation_breakdowns(spans);
+
+    event.breakdowns = if breakdowns.is_empty() {
+        Annotated::empty()
+    } else {
+        Annotated::new(breakdowns)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    use crate::protocol::SpanId;
+
+    fn span(id: &str, parent_id: &str, op: &str, start: u32, end: u32) -> Annotated<Span> {
+        Annotated::new(Span {
+            start_timestamp: Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, start)),
+            timestamp: Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, end)),
+            op: Annotated::new(op.to_owned()),
+            span_id: Annotated::new(SpanId(id.to_owned())),
+            parent_span_id: Annotated::new(SpanId(parent_id.to_owned())),
+            ..Default::default()
+        })
+    }
+
+    fn transaction(spans: Vec<Annotated<Span>>) -> Event {
+        let mut event = Event {
+            ty: Annotated::new(EventType::Transaction),
+            spans: Annotated::new(spans),
+            ..Default::default()
+        };
+
+        compute_breakdowns(&mut event);
+        event
+    }
+
+    fn exclusive_times(event: &Event) -> Vec<Option<f64>> {
+        event
+            .spans
+            .value()
+            .unwrap()
+            .iter()
+            .map(|span| span.value().unwrap().exclusive_time.value().copied())
+            .collect()
+    }
+
+    #[test]
+    fn test_exclusive_time_overlapping_children() {
+        let event = transaction(vec![
+            span("aaaaaaaaaaaaaaaa", "ffffffffffffffff", "http", 0, 10),
+            span("bbbbbbbbbbbbbbbb", "aaaaaaaaaaaaaaaa", "db", 1, 4),
+            span("cccccccccccccccc", "aaaaaaaaaaaaaaaa", "db", 3, 5),
+            span("dddddddddddddddd", "aaaaaaaaaaaaaaaa", "db", 8, 12),
+        ]);
+
+        // Children cover 1-5 and 8-10 of the parent, the part exceeding the parent is ignored.
+        assert_eq_dbg!(
+            exclusive_times(&event),
+            vec![Some(4000.0), Some(3000.0), Some(2000.0), Some(4000.0)]
+        );
+    }
+
+    #[test]
+    fn test_exclusive_time_grandchildren() {
+        let event = transaction(vec![
+            span("aaaaaaaaaaaaaaaa", "ffffffffffffffff", "http", 0, 10),
+            span("bbbbbbbbbbbbbbbb", "aaaaaaaaaaaaaaaa", "db", 2, 8),
+            span("cccccccccccccccc", "bbbbbbbbbbbbbbbb", "db", 3, 5),
+        ]);
+
+        // Only direct children are subtracted.
+        assert_eq_dbg!(
+            exclusive_times(&event),
+            vec![Some(4000.0), Some(4000.0), Some(2000.0)]
+        );
+    }
+
+    #[test]
+    fn test_operation_breakdowns() {
+        let event = transaction(vec![
+            span("aaaaaaaaaaaaaaaa", "ffffffffffffffff", "db.query", 0, 4),
+            span("bbbbbbbbbbbbbbbb", "ffffffffffffffff", "db", 2, 6),
+            span("cccccccccccccccc", "ffffffffffffffff", "http.client", 1, 2),
+            span(
+                "dddddddddddddddd",
+                "ffffffffffffffff",
+                "resource.script",
+                3,
+                4,
+            ),
+            span("eeeeeeeeeeeeeeee", "ffffffffffffffff", "custom", 0, 10),
+        ]);
+
+        let breakdowns = event.breakdowns.value().unwrap();
+        assert_eq_dbg!(breakdowns.get("db"), Some(&Annotated::new(6000.0)));
+        assert_eq_dbg!(breakdowns.get("http"), Some(&Annotated::new(1000.0)));
+        assert_eq_dbg!(breakdowns.get("resource"), Some(&Annotated::new(1000.0)));
+        assert_eq_dbg!(breakdowns.get("browser"), None);
+        assert_eq_dbg!(breakdowns.get("custom"), None);
+    }
+
+    #[test]
+    fn test_breakdowns_overwrite_input() {
+        let mut breakdowns = Breakdowns::default();
+        breakdowns.insert("db".to_owned(), Annotated::new(42.0));
+
+        let mut event = Event {
+            ty: Annotated::new(EventType::Transaction),
+            breakdowns: Annotated::new(breakdowns),
+            ..Default::default()
+        };
+
+        compute_breakdowns(&mut event);
+        assert!(event.breakdowns.value().is_none());
+    }
+
+    #[test]
+    fn test_breakdowns_removed_from_errors() {
+        let mut breakdowns = Breakdowns::default();
+        breakdowns.insert("db".to_owned(), Annotated::new(42.0));
+
+        let mut event = Event {
+            breakdowns: Annotated::new(breakdowns),
+            ..Default::default()
+        };
+
+        compute_breakdowns(&mut event);
+        assert!(event.breakdowns.value().is_none());
+    }
+}
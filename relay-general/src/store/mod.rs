@@ -5,20 +5,29 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::grouping::{self, GroupingVersion};
 use crate::processor::{ProcessingState, Processor};
-use crate::protocol::{Event, IpAddr};
+use crate::protocol::{Event, EventType, IpAddr};
 use crate::types::{Meta, ProcessingResult};
 
+mod breadcrumbs;
+mod breakdowns;
 mod event_error;
 mod geo;
 mod legacy;
 mod normalize;
 mod remove_other;
 mod schema;
+mod sourcemaps;
+mod stacktrace_rules;
+mod transaction_names;
 mod transactions;
 mod trimming;
 
 pub use crate::store::geo::{GeoIpError, GeoIpLookup};
+pub use crate::store::sourcemaps::{SourceMapError, SourceMapLookup, SourceMapProcessor};
+pub use crate::store::stacktrace_rules::StacktraceRule;
+pub use crate::store::transaction_names::TransactionNameRule;
 
 /// The config for store.
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -48,6 +57,22 @@ pub struct StoreConfig {
 
     /// When the event has been sent, according to the SDK. Passed in via envelope headers.
     pub sent_at: Option<DateTime<Utc>>,
+
+    /// Rules for normalizing the names of URL transactions.
+    pub transaction_name_rules: Vec<TransactionNameRule>,
+
+    /// Rules for setting or overriding `in_app` of stack frames.
+    pub stacktrace_rules: Vec<StacktraceRule>,
+
+    /// When `true`, grouping hashes are written into `hashes` of error events if the grouping config
+    /// is supported.
+    ///
+    /// Only the `relay:v1` grouping config is supported. Sentry's own grouping configs are not
+    /// implemented by Relay, which is why this defaults to `false`.
+    pub write_grouping_hashes: Option<bool>,
+
+    /// When `true`, consecutive repeated breadcrumbs are collapsed into one.
+    pub collapse_breadcrumbs: Option<bool>,
 }
 
 /// The processor that normalizes events for store.
@@ -70,6 +95,24 @@ impl<'a> StoreProcessor<'a> {
     pub fn config(&self) -> &StoreConfig {
         &self.config
     }
+
+    /// Writes grouping hashes into error events if the grouping config is supported.
+    fn write_grouping_hashes(&self, event: &mut Event) {
+        if event.ty.value() == Some(&EventType::Transaction) {
+            return;
+        }
+
+        let config_id = self
+            .config
+            .grouping_config
+            .as_ref()
+            .and_then(|config| config.get("id"))
+            .and_then(Value::as_str);
+
+        if let Some(Ok(version)) = config_id.map(GroupingVersion::from_config_id) {
+            grouping::write_hashes(event, version);
+        }
+    }
 }
 
 impl<'a> Processor for StoreProcessor<'a> {
@@ -92,6 +135,12 @@ impl<'a> Processor for StoreProcessor<'a> {
             // can revert some changes to ProcessingAction
             transactions::TransactionsProcessor::new(self.config.sent_at)
                 .process_event(event, meta, state)?;
+
+            // Replace identifiers in URL transaction names
+            transaction_names::normalize_transaction_name(
+                event,
+                &self.config.transaction_name_rules,
+            );
         }
 
         if !is_renormalize {
@@ -100,6 +149,14 @@ impl<'a> Processor for StoreProcessor<'a> {
 
             // Normalize data in all interfaces
             self.normalize.process_event(event, meta, state)?;
+
+            // Compute exclusive span times and operation breakdowns of transactions
+            breakdowns::compute_breakdowns(event);
+
+            if self.config.collapse_breadcrumbs.unwrap_or(false) {
+                // Collapse repeated breadcrumbs before they are trimmed
+                breadcrumbs::collapse_breadcrumbs(event);
+            }
         }
 
         if remove_other {
@@ -117,6 +174,11 @@ impl<'a> Processor for StoreProcessor<'a> {
             trimming::TrimmingProcessor::new().process_event(event, meta, state)?;
         }
 
+        if self.config.write_grouping_hashes.unwrap_or(false) {
+            // Compute grouping hashes of the final event
+            self.write_grouping_hashes(event);
+        }
+
         Ok(())
     }
 }
@@ -15,7 +15,7 @@ use crate::protocol::{
     Frame, HeaderName, HeaderValue, Headers, IpAddr, Level, LogEntry, Request, SpanStatus,
     Stacktrace, Tags, TraceContext, User, INVALID_ENVIRONMENTS, INVALID_RELEASES, VALID_PLATFORMS,
 };
-use crate::store::{GeoIpLookup, StoreConfig};
+use crate::store::{stacktrace_rules, GeoIpLookup, StoreConfig};
 use crate::types::{
     Annotated, Empty, Error, ErrorKind, FromValue, Meta, Object, ProcessingAction,
     ProcessingResult, Value,
@@ -30,6 +30,9 @@ mod stacktrace;
 #[cfg(feature = "uaparser")]
 mod user_agent;
 
+/// The maximum number of measurements allowed per event.
+const MAX_MEASUREMENTS: usize = 32;
+
 /// Validate fields that go into a `sentry.models.BoundedIntegerField`.
 fn validate_bounded_integer_field(value: u64) -> ProcessingResult {
     if value < 2_147_483_647 {
@@ -78,6 +81,8 @@ pub fn is_valid_release(release: &str) -> bool {
 pub struct NormalizeProcessor<'a> {
     config: Arc<StoreConfig>,
     geoip_lookup: Option<&'a GeoIpLookup>,
+    /// The platform of the current event, used as fallback for frames without platform.
+    platform: Option<String>,
 }
 
 impl<'a> NormalizeProcessor<'a> {
@@ -86,6 +91,7 @@ impl<'a> NormalizeProcessor<'a> {
         NormalizeProcessor {
             config,
             geoip_lookup,
+            platform: None,
         }
     }
 
@@ -244,6 +250,8 @@ impl<'a> NormalizeProcessor<'a> {
             EventType::ExpectCT
         } else if event.expectstaple.value().is_some() {
             EventType::ExpectStaple
+        } else if event.nel.value().is_some() {
+            EventType::Nel
         } else {
             EventType::Default
         }
@@ -254,6 +262,7 @@ impl<'a> NormalizeProcessor<'a> {
             || event.expectct.value().is_some()
             || event.expectstaple.value().is_some()
             || event.hpkp.value().is_some()
+            || event.nel.value().is_some()
     }
 
     /// Backfills common security report attributes.
@@ -384,6 +393,69 @@ impl<'a> NormalizeProcessor<'a> {
         Ok(())
     }
 
+    /// Lowercases measurement names and removes invalid measurements.
+    ///
+    /// Measurements are only allowed on transactions and are removed from all other events.
+    fn normalize_measurements(&self, event: &mut Event) {
+        if event.ty.value() != Some(&EventType::Transaction) {
+            if event.measurements.value().is_some() {
+                let reason = "measurements are only allowed on transactions";
+                event
+                    .measurements
+                    .meta_mut()
+                    .add_error(Error::invalid(reason));
+                event.measurements.set_value(None);
+            }
+            return;
+        }
+
+        let measurements = match event.measurements.value_mut() {
+            Some(measurements) => measurements,
+            None => return,
+        };
+
+        let original_length = measurements.len();
+        let mut normalized = Object::new();
+        let mut has_duplicates = false;
+
+        for (name, mut measurement) in mem::take(&mut measurements.0) {
+            if normalized.len() >= MAX_MEASUREMENTS {
+                break;
+            }
+
+            if let Some(measurement) = measurement.value_mut() {
+                let value = &mut measurement.value;
+                if value.value().map_or(false, |v| !v.is_finite() || *v < 0.0) {
+                    let original = value.value().copied();
+                    value.meta_mut().add_error(Error::invalid(
+                        "measurement must be a finite, non-negative number",
+                    ));
+                    value.meta_mut().set_original_value(original);
+                    value.set_value(None);
+                }
+            }
+
+            let name = name.to_lowercase();
+            if normalized.contains_key(&name) {
+                has_duplicates = true;
+            } else {
+                normalized.insert(name, measurement);
+            }
+        }
+
+        let length = normalized.len();
+        measurements.0 = normalized;
+
+        let meta = event.measurements.meta_mut();
+        if has_duplicates {
+            meta.add_error(Error::invalid("duplicate measurement names"));
+        }
+
+        if length != original_length {
+            meta.set_original_length(Some(original_length));
+        }
+    }
+
     fn normalize_user_agent(&self, _event: &mut Event) {
         if self.config.normalize_user_agent.unwrap_or(false) {
             #[cfg(feature = "uaparser")]
@@ -408,6 +480,9 @@ impl<'a> Processor for NormalizeProcessor<'a> {
         // Insert IP addrs before recursing, since geo lookup depends on it.
         self.normalize_ip_addresses(event);
 
+        // Remember the platform before recursing, since stack trace rules depend on it.
+        self.platform = event.platform.value().cloned();
+
         event.process_child_values(self, state)?;
 
         // Override internal attributes, even if they were set in the payload
@@ -415,6 +490,7 @@ impl<'a> Processor for NormalizeProcessor<'a> {
         event.key_id = Annotated::from(self.config.key_id.clone());
         event.ty = Annotated::from(self.infer_event_type(event));
         event.version = Annotated::from(self.config.protocol_version.clone());
+        event.hashes = Annotated::empty();
         event.grouping_config = self
             .config
             .grouping_config
@@ -467,6 +543,7 @@ impl<'a> Processor for NormalizeProcessor<'a> {
         self.normalize_event_tags(event)?;
         self.normalize_exceptions(event)?;
         self.normalize_user_agent(event);
+        self.normalize_measurements(event);
 
         Ok(())
     }
@@ -623,6 +700,19 @@ impl<'a> Processor for NormalizeProcessor<'a> {
             frame.context_line.set_value(Some(String::new()));
         }
 
+        let rules = &self.config.stacktrace_rules;
+        if let Some(in_app) = stacktrace_rules::get_in_app(rules, frame, self.platform.as_deref()) {
+            let Annotated(ref mut value, ref mut meta) = frame.in_app;
+            match *value {
+                Some(original) if original != in_app => {
+                    meta.set_original_value(Some(original));
+                    *value = Some(in_app);
+                }
+                Some(_) => (),
+                None => *value = Some(in_app),
+            }
+        }
+
         Ok(())
     }
 
@@ -1233,6 +1323,47 @@ fn test_context_line_default() {
     assert_eq_dbg!(frame.context_line.as_str(), Some(""));
 }
 
+#[test]
+fn test_stacktrace_rules_in_app() {
+    use crate::store::StacktraceRule;
+
+    let rule = StacktraceRule {
+        module: Some("app.vendor.**".into()),
+        package: None,
+        filename: None,
+        abs_path: None,
+        platform: Some("python".to_owned()),
+        in_app: false,
+    };
+
+    let mut processor = NormalizeProcessor::new(
+        Arc::new(StoreConfig {
+            stacktrace_rules: vec![rule],
+            ..StoreConfig::default()
+        }),
+        None,
+    );
+    processor.platform = Some("python".to_owned());
+
+    let mut frame = Annotated::new(Frame {
+        module: Annotated::new("app.vendor.six".to_string()),
+        in_app: Annotated::new(true),
+        ..Frame::default()
+    });
+    process_value(&mut frame, &mut processor, ProcessingState::root()).unwrap();
+
+    let in_app = &frame.value().unwrap().in_app;
+    assert_eq_dbg!(in_app.value(), Some(&false));
+    assert_eq_dbg!(in_app.meta().original_value(), Some(&Value::Bool(true)));
+
+    let mut frame = Annotated::new(Frame {
+        module: Annotated::new("app.views".to_string()),
+        ..Frame::default()
+    });
+    process_value(&mut frame, &mut processor, ProcessingState::root()).unwrap();
+    assert_eq_dbg!(frame.value().unwrap().in_app.value(), None);
+}
+
 #[test]
 fn test_context_line_retain() {
     let mut frame = Annotated::new(Frame {
@@ -1409,6 +1540,19 @@ fn test_discards_received() {
     );
 }
 
+#[test]
+fn test_hashes_removed() {
+    let json = r#"{
+        "hashes": ["deadbeef"]
+    }"#;
+
+    let mut event = Annotated::<Event>::from_json(json).unwrap();
+    let mut processor = NormalizeProcessor::default();
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    assert!(event.value().unwrap().hashes.value().is_none());
+}
+
 #[test]
 fn test_grouping_config() {
     use crate::protocol::LogEntry;
@@ -1458,3 +1602,114 @@ fn test_grouping_config() {
     }
     "###);
 }
+
+#[test]
+fn test_measurements_normalized() {
+    use crate::protocol::Measurement;
+
+    let json = r#"{
+        "type": "transaction",
+        "measurements": {
+            "LCP": {"value": 2500.0},
+            "fcp": {"value": -1.0},
+            "fid": {"value": 12}
+        }
+    }"#;
+
+    let mut event = Annotated::<Event>::from_json(json).unwrap();
+    let mut processor = NormalizeProcessor::default();
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let measurements = event.value().unwrap().measurements.value().unwrap();
+    assert_eq_dbg!(
+        measurements.get("lcp"),
+        Some(&Annotated::new(Measurement {
+            value: Annotated::new(2500.0),
+        }))
+    );
+    assert_eq_dbg!(
+        measurements.get("fid"),
+        Some(&Annotated::new(Measurement {
+            value: Annotated::new(12.0),
+        }))
+    );
+    assert_eq_dbg!(measurements.get("LCP"), None);
+
+    let fcp = &measurements.get("fcp").unwrap().value().unwrap().value;
+    assert_eq_dbg!(fcp.value(), None);
+    assert_eq_dbg!(fcp.meta().original_value(), Some(&Value::F64(-1.0)));
+}
+
+#[test]
+fn test_measurements_duplicate_names() {
+    use crate::protocol::Measurement;
+
+    let json = r#"{
+        "type": "transaction",
+        "measurements": {
+            "LCP": {"value": 2500.0},
+            "lcp": {"value": 3000.0}
+        }
+    }"#;
+
+    let mut event = Annotated::<Event>::from_json(json).unwrap();
+    let mut processor = NormalizeProcessor::default();
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let measurements = &event.value().unwrap().measurements;
+    assert_eq_dbg!(measurements.value().unwrap().len(), 1);
+    assert_eq_dbg!(
+        measurements.value().unwrap().get("lcp"),
+        Some(&Annotated::new(Measurement {
+            value: Annotated::new(2500.0),
+        }))
+    );
+    assert!(measurements.meta().has_errors());
+    assert_eq_dbg!(measurements.meta().original_length(), Some(2));
+}
+
+#[test]
+fn test_measurements_capped() {
+    use crate::protocol::{Measurement, Measurements};
+
+    let mut measurements = Measurements::default();
+    for i in 0..MAX_MEASUREMENTS + 1 {
+        let measurement = Measurement {
+            value: Annotated::new(i as f64),
+        };
+        measurements.insert(format!("m{:02}", i), Annotated::new(measurement));
+    }
+
+    let mut event = Annotated::new(Event {
+        ty: Annotated::new(EventType::Transaction),
+        measurements: Annotated::new(measurements),
+        ..Default::default()
+    });
+
+    let mut processor = NormalizeProcessor::default();
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let measurements = &event.value().unwrap().measurements;
+    assert_eq_dbg!(measurements.value().unwrap().len(), MAX_MEASUREMENTS);
+    assert_eq_dbg!(
+        measurements.meta().original_length(),
+        Some(MAX_MEASUREMENTS + 1)
+    );
+}
+
+#[test]
+fn test_measurements_only_on_transactions() {
+    let json = r#"{
+        "measurements": {
+            "lcp": {"value": 2500.0}
+        }
+    }"#;
+
+    let mut event = Annotated::<Event>::from_json(json).unwrap();
+    let mut processor = NormalizeProcessor::default();
+    process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+    let measurements = &event.value().unwrap().measurements;
+    assert!(measurements.value().is_none());
+    assert!(measurements.meta().has_errors());
+}
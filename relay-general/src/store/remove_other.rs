@@ -30,7 +30,6 @@ impl Processor for RemoveOtherProcessor {
 
         // Drop Sentry internal attributes
         other.remove("metadata");
-        other.remove("hashes");
 
         // Drop known legacy attributes at top-level without errors
         other.remove("applecrashreport");
@@ -0,0 +1,537 @@
+//! Resolution of minified JavaScript frames with source maps from a local directory.
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use failure::Fail;
+use lru::LruCache;
+use parking_lot::Mutex;
+use sourcemap::{DecodedMap, SourceMap, SourceView};
+use url::Url;
+
+use crate::processor::{ProcessValue, ProcessingState, Processor};
+use crate::protocol::{Event, Frame, FrameData};
+use crate::types::{Annotated, Array, Error, Meta, ProcessingResult};
+
+/// Platforms of frames that are resolved with source maps.
+const PLATFORMS: &[&str] = &["javascript", "node"];
+
+/// Number of lines of source context before and after the line of a frame.
+const CONTEXT_LINES: u32 = 5;
+
+/// Maximum number of characters of a source context line.
+const MAX_LINE_LENGTH: usize = 150;
+
+/// Maximum number of parsed source maps kept in memory.
+const MAX_CACHED_SOURCE_MAPS: usize = 50;
+
+/// Maximum size of source map files in bytes. Larger files are not read.
+const MAX_SOURCE_MAP_SIZE: u64 = 20 * 1024 * 1024;
+
+/// An error when loading a source map.
+#[derive(Clone, Debug, Fail)]
+pub enum SourceMapError {
+    /// There is no source map for the minified file in the release.
+    #[fail(display = "missing source map")]
+    Missing,
+
+    /// The source map could not be read or parsed.
+    #[fail(display = "invalid source map: {}", _0)]
+    Invalid(String),
+}
+
+/// A source map lookup helper based on a local directory.
+///
+/// The directory contains a folder per release. Source maps are stored at the path of the minified
+/// file's URL with a `.map` extension. For example, the source map of
+/// `https://example.com/static/app.min.js` in release `1.0` is `1.0/static/app.min.js.map`.
+///
+/// The scheme and host of the URL are ignored, so files with the same path on different hosts
+/// share a source map.
+///
+/// Parsed source maps are cached in memory, so that they are shared between events and threads.
+pub struct SourceMapLookup {
+    root: PathBuf,
+    cache: Mutex<LruCache<PathBuf, Arc<SourceMap>>>,
+}
+
+impl SourceMapLookup {
+    /// Opens a directory of source maps by path.
+    pub fn open<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let root = path.as_ref().to_owned();
+        if !fs::metadata(&root)?.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::Other, "not a directory"));
+        }
+
+        Ok(SourceMapLookup {
+            root,
+            cache: Mutex::new(LruCache::new(MAX_CACHED_SOURCE_MAPS)),
+        })
+    }
+
+    /// Returns the source map at the given path relative to the directory.
+    ///
+    /// Source maps are loaded from the cache if possible. Errors are not cached, so that source
+    /// maps uploaded after the first lookup are picked up.
+    fn get(&self, path: &Path) -> Result<Arc<SourceMap>, SourceMapError> {
+        if let Some(map) = self.cache.lock().get(&path.to_owned()) {
+            return Ok(map.clone());
+        }
+
+        // Load outside of the lock, since parsing large source maps takes a while.
+        let map = Arc::new(self.load(path)?);
+        self.cache.lock().put(path.to_owned(), map.clone());
+        Ok(map)
+    }
+
+    /// Reads and parses the source map at the given path relative to the directory.
+    fn load(&self, path: &Path) -> Result<SourceMap, SourceMapError> {
+        let invalid_io = |error: io::Error| SourceMapError::Invalid(error.to_string());
+        let full_path = self.root.join(path);
+
+        let metadata = match fs::metadata(&full_path) {
+            Ok(metadata) => metadata,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(SourceMapError::Missing)
+            }
+            Err(error) => return Err(invalid_io(error)),
+        };
+
+        if metadata.len() > MAX_SOURCE_MAP_SIZE {
+            return Err(SourceMapError::Invalid("source map too large".to_owned()));
+        }
+
+        let data = fs::read(&full_path).map_err(invalid_io)?;
+
+        let invalid = |error: sourcemap::Error| SourceMapError::Invalid(error.to_string());
+        match sourcemap::decode_slice(&data).map_err(invalid)? {
+            DecodedMap::Regular(map) => Ok(map),
+            DecodedMap::Index(index) => index.flatten().map_err(invalid),
+            DecodedMap::Hermes(map) => Ok(map.into_inner()),
+        }
+    }
+}
+
+/// Checks whether a release or URL segment can be used as a path component.
+fn is_safe_component(component: &str) -> bool {
+    !component.is_empty()
+        && component != "."
+        && component != ".."
+        && !component.contains(|c| c == '/' || c == '\\' || c == '\0')
+}
+
+/// Returns the path of the source map for a minified file, relative to the lookup directory.
+///
+/// Only the path of the URL is used, its scheme and host are ignored. Returns `None` if the release
+/// or the path of the file cannot be mapped to a file safely, or if the absolute path of the file
+/// is not a URL.
+fn source_map_path(release: &str, abs_path: &str) -> Option<PathBuf> {
+    if !is_safe_component(release) {
+        return None;
+    }
+
+    let url = Url::parse(abs_path).ok()?;
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    if !segments.iter().all(|segment| is_safe_component(segment)) {
+        return None;
+    }
+
+    let (file_name, directories) = segments.split_last()?;
+    let mut path = PathBuf::from(release);
+    path.extend(directories);
+    path.push(format!("{}.map", file_name));
+    Some(path)
+}
+
+/// Returns a line of source context, truncated to a maximum length.
+fn get_line(view: &SourceView<'_>, line: u32) -> Option<String> {
+    let line = view.get_line(line)?;
+    if line.chars().count() <= MAX_LINE_LENGTH {
+        return Some(line.to_owned());
+    }
+
+    let mut truncated: String = line.chars().take(MAX_LINE_LENGTH).collect();
+    truncated.push_str(" {snip}");
+    Some(truncated)
+}
+
+/// Returns the lines of source context in the given range.
+fn get_lines(view: &SourceView<'_>, lines: std::ops::Range<u32>) -> Array<String> {
+    lines
+        .filter_map(|line| get_line(view, line))
+        .map(Annotated::new)
+        .collect()
+}
+
+/// Resolves the position of a minified frame with a source map.
+///
+/// The original position of the frame is retained in its `data`. Frames without a position or a
+/// mapping in the source map remain unchanged.
+fn resolve_frame(frame: &mut Frame, map: &SourceMap, map_path: &str) {
+    let (lineno, colno) = match (frame.lineno.value(), frame.colno.value()) {
+        (Some(&lineno), Some(&colno)) if lineno > 0 && colno > 0 => (lineno, colno),
+        _ => return,
+    };
+
+    let token = match (u32::try_from(lineno - 1), u32::try_from(colno - 1)) {
+        (Ok(line), Ok(col)) => map.lookup_token(line, col),
+        _ => None,
+    };
+
+    let token = match token {
+        Some(token) => token,
+        None => return,
+    };
+
+    let minified_path = frame.abs_path.value().map(|path| path.as_str().to_owned());
+
+    let data = frame.data.get_or_insert_with(FrameData::default);
+    data.sourcemap = Annotated::new(map_path.to_owned());
+    data.orig_function = frame.function.clone();
+    data.orig_filename = Annotated::from(minified_path.clone());
+    data.orig_lineno = frame.lineno.clone();
+    data.orig_colno = frame.colno.clone();
+
+    if let Some(source) = token.get_source() {
+        // Sources are relative to the URL of the minified file.
+        let abs_path = minified_path
+            .and_then(|path| Url::parse(&path).ok())
+            .and_then(|url| url.join(source).ok())
+            .or_else(|| Url::parse(source).ok());
+
+        match abs_path {
+            Some(url) => {
+                frame.filename = Annotated::new(url.path().into());
+                frame.abs_path = Annotated::new(url.as_str().into());
+            }
+            None => {
+                frame.filename = Annotated::new(source.into());
+                frame.abs_path = Annotated::new(source.into());
+            }
+        }
+    }
+
+    if let Some(name) = token.get_name() {
+        frame.function = Annotated::new(name.to_owned());
+    }
+
+    let line = token.get_src_line();
+    frame.lineno = Annotated::new(u64::from(line) + 1);
+    frame.colno = Annotated::new(u64::from(token.get_src_col()) + 1);
+
+    // The context of the minified file no longer matches, even if there is no original source.
+    match map.get_source_view(token.get_src_id()) {
+        Some(view) => {
+            let pre_start = line.saturating_sub(CONTEXT_LINES);
+            let post_end = line.saturating_add(CONTEXT_LINES + 1);
+
+            frame.pre_context = Annotated::new(get_lines(view, pre_start..line));
+            frame.context_line = Annotated::from(get_line(view, line));
+            frame.post_context = Annotated::new(get_lines(view, line.saturating_add(1)..post_end));
+        }
+        None => {
+            frame.pre_context = Annotated::empty();
+            frame.context_line = Annotated::empty();
+            frame.post_context = Annotated::empty();
+        }
+    }
+}
+
+/// Resolves minified JavaScript frames with source maps from a [`SourceMapLookup`].
+///
+/// Source maps are looked up by the release of the event and the absolute path of each frame.
+/// Frames of events without a release are not resolved. If the source map of a frame is missing
+/// or invalid, an error is added to the frame's `abs_path`.
+pub struct SourceMapProcessor<'a> {
+    lookup: &'a SourceMapLookup,
+    release: Option<String>,
+    platform: Option<String>,
+    maps: BTreeMap<PathBuf, Result<Arc<SourceMap>, SourceMapError>>,
+}
+
+impl<'a> SourceMapProcessor<'a> {
+    /// Creates a new source map processor.
+    pub fn new(lookup: &'a SourceMapLookup) -> Self {
+        SourceMapProcessor {
+            lookup,
+            release: None,
+            platform: None,
+            maps: BTreeMap::new(),
+        }
+    }
+}
+
+impl Processor for SourceMapProcessor<'_> {
+    fn process_event(
+        &mut self,
+        event: &mut Event,
+        _meta: &mut Meta,
+        state: &ProcessingState<'_>,
+    ) -> ProcessingResult {
+        self.release = event.release.as_str().map(str::to_owned);
+        self.platform = event.platform.value().cloned();
+
+        if self.release.is_some() {
+            event.process_child_values(self, state)?;
+        }
+
+        Ok(())
+    }
+
+    fn process_frame(
+        &mut self,
+        frame: &mut Frame,
+        _meta: &mut Meta,
+        _state: &ProcessingState<'_>,
+    ) -> ProcessingResult {
+        let platform = frame.platform.as_str().or_else(|| self.platform.as_deref());
+        if !platform.map_or(false, |platform| PLATFORMS.contains(&platform)) {
+            return Ok(());
+        }
+
+        let path = match (&self.release, frame.abs_path.value()) {
+            (Some(release), Some(abs_path)) => source_map_path(release, abs_path.as_str()),
+            _ => None,
+        };
+
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let lookup = self.lookup;
+        let map_path = path.to_string_lossy().into_owned();
+        let map = self
+            .maps
+            .entry(path.clone())
+            .or_insert_with(|| lookup.get(&path));
+
+        match map {
+            Ok(map) => resolve_frame(frame, map, &map_path),
+            Err(error) => frame.abs_path.meta_mut().add_error(Error::invalid(error)),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sourcemap::SourceMapBuilder;
+
+    use crate::processor::process_value;
+
+    /// The original source of the minified file `app.min.js`.
+    const SOURCE: &str = "\
+// Application code
+
+function explode() {
+  throw new Error(\"boom\");
+}
+
+explode();
+";
+
+    fn write_source_map(dir: &Path) {
+        let mut builder = SourceMapBuilder::new(Some("app.min.js"));
+        let src_id = builder.add_source("../src/app.js");
+        builder.set_source_contents(src_id, Some(SOURCE));
+        let name_id = builder.add_name("explode");
+
+        // function explode(){throw new Error("boom")}explode();
+        builder.add_raw(0, 0, 2, 0, Some(src_id), None);
+        builder.add_raw(0, 19, 3, 2, Some(src_id), Some(name_id));
+        builder.add_raw(0, 44, 6, 0, Some(src_id), None);
+
+        let map_dir = dir.join("1.0").join("static");
+        fs::create_dir_all(&map_dir).unwrap();
+        let file = fs::File::create(map_dir.join("app.min.js.map")).unwrap();
+        builder.into_sourcemap().to_writer(file).unwrap();
+    }
+
+    fn process_event(dir: &Path, json: &str) -> Event {
+        let lookup = SourceMapLookup::open(dir).unwrap();
+        let mut processor = SourceMapProcessor::new(&lookup);
+        let mut event = Annotated::<Event>::from_json(json).unwrap();
+        process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+        event.into_value().unwrap()
+    }
+
+    fn get_frame(event: &Event) -> &Frame {
+        let exceptions = event.exceptions.value().and_then(|e| e.values.value());
+        let exception = exceptions.unwrap()[0].value().unwrap();
+        let frames = exception.stacktrace.value().unwrap().frames.value();
+        frames.unwrap()[0].value().unwrap()
+    }
+
+    #[test]
+    fn test_lookup_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        write_source_map(dir.path());
+
+        let lookup = SourceMapLookup::open(dir.path()).unwrap();
+        let path = Path::new("1.0/static/app.min.js.map");
+        let first = lookup.get(path).unwrap();
+        let second = lookup.get(path).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        assert!(lookup.get(Path::new("1.0/missing.js.map")).is_err());
+    }
+
+    #[test]
+    fn test_source_map_path() {
+        assert_eq!(
+            source_map_path("1.0", "https://example.com/static/app.min.js"),
+            Some(PathBuf::from("1.0/static/app.min.js.map"))
+        );
+        assert_eq!(
+            source_map_path("1.0", "https://cdn.example.org/static/app.min.js"),
+            Some(PathBuf::from("1.0/static/app.min.js.map"))
+        );
+        assert_eq!(
+            source_map_path("1.0", "app:///main.js"),
+            Some(PathBuf::from("1.0/main.js.map"))
+        );
+        assert_eq!(
+            source_map_path("1.0", "https://example.com/static/../../app.js"),
+            Some(PathBuf::from("1.0/app.js.map"))
+        );
+
+        assert_eq!(source_map_path("1.0", "<anonymous>"), None);
+        assert_eq!(source_map_path("1.0", "https://example.com/"), None);
+        assert_eq!(source_map_path("..", "https://example.com/app.js"), None);
+        assert_eq!(source_map_path("a/b", "https://example.com/app.js"), None);
+    }
+
+    #[test]
+    fn test_resolve_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        write_source_map(dir.path());
+
+        let event = process_event(
+            dir.path(),
+            r#"{
+                "release": "1.0",
+                "platform": "javascript",
+                "exception": {
+                    "values": [{
+                        "type": "Error",
+                        "stacktrace": {
+                            "frames": [{
+                                "function": "e",
+                                "abs_path": "https://example.com/static/app.min.js",
+                                "lineno": 1,
+                                "colno": 20,
+                                "context_line": "function explode(){throw new Error(\"boom\")}"
+                            }]
+                        }
+                    }]
+                }
+            }"#,
+        );
+
+        let frame = get_frame(&event);
+        assert_eq!(
+            frame.abs_path.value().map(|p| p.as_str()),
+            Some("https://example.com/src/app.js")
+        );
+        assert_eq!(
+            frame.filename.value().map(|p| p.as_str()),
+            Some("/src/app.js")
+        );
+        assert_eq!(frame.function.as_str(), Some("explode"));
+        assert_eq!(frame.lineno.value(), Some(&4));
+        assert_eq!(frame.colno.value(), Some(&3));
+
+        assert_eq!(
+            frame.context_line.as_str(),
+            Some("  throw new Error(\"boom\");")
+        );
+        let pre_context = frame.pre_context.value().unwrap();
+        assert_eq!(pre_context.len(), 3);
+        assert_eq!(pre_context[2].as_str(), Some("function explode() {"));
+        let post_context = frame.post_context.value().unwrap();
+        assert_eq!(post_context[0].as_str(), Some("}"));
+
+        let data = frame.data.value().unwrap();
+        assert_eq!(data.sourcemap.as_str(), Some("1.0/static/app.min.js.map"));
+        assert_eq!(data.orig_function.as_str(), Some("e"));
+        assert_eq!(
+            data.orig_filename.as_str(),
+            Some("https://example.com/static/app.min.js")
+        );
+        assert_eq!(data.orig_lineno.value(), Some(&1));
+        assert_eq!(data.orig_colno.value(), Some(&20));
+    }
+
+    #[test]
+    fn test_missing_source_map() {
+        let dir = tempfile::tempdir().unwrap();
+        write_source_map(dir.path());
+
+        let event = process_event(
+            dir.path(),
+            r#"{
+                "release": "2.0",
+                "platform": "javascript",
+                "exception": {
+                    "values": [{
+                        "type": "Error",
+                        "stacktrace": {
+                            "frames": [{
+                                "abs_path": "https://example.com/static/app.min.js",
+                                "lineno": 1,
+                                "colno": 20
+                            }]
+                        }
+                    }]
+                }
+            }"#,
+        );
+
+        let frame = get_frame(&event);
+        assert_eq!(frame.lineno.value(), Some(&1));
+        assert!(frame.data.value().is_none());
+
+        let errors: Vec<_> = frame.abs_path.meta().iter_errors().collect();
+        assert_eq!(errors, vec![&Error::invalid("missing source map")]);
+    }
+
+    #[test]
+    fn test_skip_other_platforms() {
+        let dir = tempfile::tempdir().unwrap();
+        write_source_map(dir.path());
+
+        let event = process_event(
+            dir.path(),
+            r#"{
+                "release": "1.0",
+                "platform": "python",
+                "exception": {
+                    "values": [{
+                        "type": "Error",
+                        "stacktrace": {
+                            "frames": [{
+                                "abs_path": "https://example.com/static/missing.min.js",
+                                "lineno": 1,
+                                "colno": 20
+                            }]
+                        }
+                    }]
+                }
+            }"#,
+        );
+
+        let frame = get_frame(&event);
+        assert!(frame.abs_path.meta().is_empty());
+        assert!(frame.data.value().is_none());
+    }
+}
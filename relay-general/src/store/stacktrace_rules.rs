@@ -0,0 +1,160 @@
+//! Project-configured rules for classifying stack frames as in-app.
+use serde::{Deserialize, Serialize};
+
+use relay_common::Glob;
+
+use crate::protocol::Frame;
+
+/// A rule that sets or overrides `in_app` of matching stack frames.
+///
+/// All patterns that are given must match the respective attribute of a frame. Frames without the
+/// attribute do not match. A rule without patterns matches all frames of its platform.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StacktraceRule {
+    /// Glob pattern matched against the frame's module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<Glob>,
+    /// Glob pattern matched against the frame's package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<Glob>,
+    /// Glob pattern matched against the frame's filename.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<Glob>,
+    /// Glob pattern matched against the frame's absolute path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abs_path: Option<Glob>,
+    /// The platform of matching frames. Matches frames of all platforms if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    /// The `in_app` value of matching frames.
+    pub in_app: bool,
+}
+
+/// Checks whether an optional pattern matches an optional value.
+fn matches_pattern(pattern: &Option<Glob>, value: Option<&str>) -> bool {
+    match (pattern, value) {
+        (Some(pattern), Some(value)) => pattern.is_match(value),
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+impl StacktraceRule {
+    /// Checks whether this rule matches a frame.
+    ///
+    /// The platform of the frame defaults to the platform of the event.
+    pub fn matches(&self, frame: &Frame, event_platform: Option<&str>) -> bool {
+        if let Some(ref platform) = self.platform {
+            let frame_platform = frame.platform.as_str().or(event_platform);
+            if frame_platform != Some(platform.as_str()) {
+                return false;
+            }
+        }
+
+        matches_pattern(&self.module, frame.module.as_str())
+            && matches_pattern(&self.package, frame.package.as_str())
+            && matches_pattern(&self.filename, frame.filename.value().map(|p| p.as_str()))
+            && matches_pattern(&self.abs_path, frame.abs_path.value().map(|p| p.as_str()))
+    }
+}
+
+/// Returns the `in_app` value of the first rule matching the frame.
+pub fn get_in_app(
+    rules: &[StacktraceRule],
+    frame: &Frame,
+    event_platform: Option<&str>,
+) -> Option<bool> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(frame, event_platform))
+        .map(|rule| rule.in_app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::protocol::NativeImagePath;
+    use crate::types::Annotated;
+
+    fn rule(in_app: bool) -> StacktraceRule {
+        StacktraceRule {
+            module: None,
+            package: None,
+            filename: None,
+            abs_path: None,
+            platform: None,
+            in_app,
+        }
+    }
+
+    #[test]
+    fn test_matches_all_patterns() {
+        let rule = StacktraceRule {
+            module: Some("vendor.**".into()),
+            abs_path: Some("**/site-packages/**".into()),
+            ..rule(false)
+        };
+
+        let frame = Frame {
+            module: Annotated::new("vendor.requests.api".to_owned()),
+            abs_path: Annotated::new(NativeImagePath(
+                "/usr/lib/site-packages/requests/api.py".to_owned(),
+            )),
+            ..Default::default()
+        };
+        assert!(rule.matches(&frame, None));
+
+        let frame = Frame {
+            module: Annotated::new("vendor.requests.api".to_owned()),
+            ..Default::default()
+        };
+        assert!(!rule.matches(&frame, None));
+    }
+
+    #[test]
+    fn test_matches_platform() {
+        let rule = StacktraceRule {
+            package: Some("**/libfoo.so".into()),
+            platform: Some("native".to_owned()),
+            ..rule(true)
+        };
+
+        let mut frame = Frame {
+            package: Annotated::new("/usr/lib/libfoo.so".to_owned()),
+            ..Default::default()
+        };
+        assert!(rule.matches(&frame, Some("native")));
+        assert!(!rule.matches(&frame, Some("python")));
+
+        frame.platform = Annotated::new("native".to_owned());
+        assert!(rule.matches(&frame, Some("python")));
+    }
+
+    #[test]
+    fn test_first_rule_wins() {
+        let rules = vec![
+            StacktraceRule {
+                module: Some("app.vendor.*".into()),
+                ..rule(false)
+            },
+            StacktraceRule {
+                module: Some("app.**".into()),
+                ..rule(true)
+            },
+        ];
+
+        let frame = Frame {
+            module: Annotated::new("app.vendor.six".to_owned()),
+            ..Default::default()
+        };
+        assert_eq_dbg!(get_in_app(&rules, &frame, None), Some(false));
+
+        let frame = Frame {
+            module: Annotated::new("other".to_owned()),
+            ..Default::default()
+        };
+        assert_eq_dbg!(get_in_app(&rules, &frame, None), None);
+    }
+}
@@ -0,0 +1,281 @@
+//! Normalization of high-cardinality transaction names.
+use std::borrow::Cow;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use relay_common::Glob;
+
+use crate::protocol::{Event, EventType, TransactionInfo, TransactionSource};
+use crate::types::{Annotated, Remark, RemarkType};
+
+/// The rule id reported in remarks of names normalized by the built-in heuristics.
+const DEFAULT_RULE_ID: &str = "@transaction_name";
+
+lazy_static! {
+    static ref DATE_RE: Regex =
+        Regex::new(r"^\d{4}-\d{2}-\d{2}(T\d{2}:\d{2}(:\d{2}(\.\d+)?)?(Z|[+-]\d{2}:?\d{2})?)?$")
+            .unwrap();
+    static ref UUID_RE: Regex =
+        Regex::new(r"^(?i)[0-9a-f]{8}-?[0-9a-f]{4}-?[0-9a-f]{4}-?[0-9a-f]{4}-?[0-9a-f]{12}$")
+            .unwrap();
+    static ref ID_RE: Regex = Regex::new(r"^\d+$").unwrap();
+    static ref HASH_RE: Regex = Regex::new(r"^(?i)[0-9a-f]{8,}$").unwrap();
+}
+
+/// A project-configured rule for normalizing transaction names.
+///
+/// If the glob pattern matches the entire name of a URL transaction, the name is replaced with the
+/// replacement. Rules take precedence over the built-in heuristics.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionNameRule {
+    /// The glob pattern matched against transaction names.
+    ///
+    /// `*` matches within a single path segment and `**` matches across segments.
+    pub pattern: Glob,
+    /// The name of matching transactions.
+    pub replacement: String,
+}
+
+/// Returns the placeholder for a high-cardinality path segment, if any.
+fn get_placeholder(segment: &str) -> Option<&'static str> {
+    if DATE_RE.is_match(segment) {
+        Some("{date}")
+    } else if UUID_RE.is_match(segment) {
+        Some("{uuid}")
+    } else if ID_RE.is_match(segment) {
+        Some("{id}")
+    } else if HASH_RE.is_match(segment) {
+        Some("{hash}")
+    } else {
+        None
+    }
+}
+
+/// Replaces identifiers in the path of a URL with placeholders.
+///
+/// The query string and fragment are removed, since they never identify the transaction. The
+/// scheme and host of absolute URLs are retained.
+fn normalize_url(url: &str) -> String {
+    let url = url
+        .split(|c| c == '?' || c == '#')
+        .next()
+        .unwrap_or_default();
+
+    let (prefix, path) = match url.find("://") {
+        Some(index) => {
+            let path_start = url[index + 3..]
+                .find('/')
+                .map_or(url.len(), |offset| index + 3 + offset);
+            url.split_at(path_start)
+        }
+        None => ("", url),
+    };
+
+    let path = path
+        .split('/')
+        .map(|segment| get_placeholder(segment).unwrap_or(segment))
+        .collect::<Vec<_>>()
+        .join("/");
+
+    format!("{}{}", prefix, path)
+}
+
+/// Returns whether the transaction name looks like a URL.
+fn is_url_like(name: &str) -> bool {
+    name.starts_with('/') || name.contains("://")
+}
+
+/// Returns the normalized transaction name and the id of the applied rule.
+///
+/// Returns `None` if the name does not look like a URL.
+fn get_normalized_name<'a>(
+    name: &str,
+    rules: &'a [TransactionNameRule],
+) -> Option<(String, Cow<'a, str>)> {
+    if !is_url_like(name) {
+        return None;
+    }
+
+    if let Some(rule) = rules.iter().find(|rule| rule.pattern.is_match(name)) {
+        let rule_id = Cow::Borrowed(rule.pattern.pattern());
+        return Some((rule.replacement.clone(), rule_id));
+    }
+
+    Some((normalize_url(name), Cow::Borrowed(DEFAULT_RULE_ID)))
+}
+
+/// Normalizes the name of URL transactions.
+///
+/// Names are normalized if the client reports a `url` source, or if no source is given and the
+/// name looks like a URL. If the name changes, the original name is retained in the meta data of
+/// the transaction name and the source is set to `sanitized`.
+pub fn normalize_transaction_name(event: &mut Event, rules: &[TransactionNameRule]) {
+    if event.ty.value() != Some(&EventType::Transaction) {
+        return;
+    }
+
+    let source = event
+        .transaction_info
+        .value()
+        .and_then(|info| info.source.value())
+        .copied();
+
+    match source {
+        Some(TransactionSource::Url) | None => (),
+        Some(_) => return,
+    }
+
+    let Annotated(ref mut name, ref mut meta) = event.transaction;
+    let name = match name {
+        Some(name) => name,
+        None => return,
+    };
+
+    let (normalized, rule_id) = match get_normalized_name(name, rules) {
+        Some(normalized) => normalized,
+        None => return,
+    };
+
+    if normalized == *name {
+        return;
+    }
+
+    let original = std::mem::replace(name, normalized);
+    meta.set_original_value(Some(original));
+    meta.add_remark(Remark::new(RemarkType::Substituted, rule_id));
+
+    event
+        .transaction_info
+        .get_or_insert_with(TransactionInfo::default)
+        .source
+        .set_value(Some(TransactionSource::Sanitized));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::Value;
+
+    fn normalize(name: &str, source: Option<TransactionSource>) -> Event {
+        let rules = vec![TransactionNameRule {
+            pattern: "/orgs/*/**".into(),
+            replacement: "/orgs/{org}/".to_owned(),
+        }];
+
+        let mut event = Event {
+            ty: Annotated::new(EventType::Transaction),
+            transaction: Annotated::new(name.to_owned()),
+            transaction_info: Annotated::new(TransactionInfo {
+                source: Annotated::from(source),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        normalize_transaction_name(&mut event, &rules);
+        event
+    }
+
+    fn get_source(event: &Event) -> Option<TransactionSource> {
+        event.transaction_info.value()?.source.value().copied()
+    }
+
+    #[test]
+    fn test_normalize_identifiers() {
+        let name = "/user/12345/orders/8b4a6bb4-ef02-4a36-9d4e-5ea2bd8d9f1a/items/d41d8cd98f00b204/2020-04-01?page=2";
+        let event = normalize(name, None);
+
+        assert_eq_dbg!(
+            event.transaction.value().map(String::as_str),
+            Some("/user/{id}/orders/{uuid}/items/{hash}/{date}")
+        );
+        assert_eq_dbg!(
+            event.transaction.meta().original_value(),
+            Some(&Value::String(name.to_owned()))
+        );
+        assert_eq_dbg!(get_source(&event), Some(TransactionSource::Sanitized));
+    }
+
+    #[test]
+    fn test_normalize_absolute_url() {
+        let event = normalize(
+            "https://example.org/user/42/profile",
+            Some(TransactionSource::Url),
+        );
+
+        assert_eq_dbg!(
+            event.transaction.value().map(String::as_str),
+            Some("https://example.org/user/{id}/profile")
+        );
+    }
+
+    #[test]
+    fn test_normalize_rule() {
+        let event = normalize("/orgs/sentry/projects/42", None);
+
+        assert_eq_dbg!(
+            event.transaction.value().map(String::as_str),
+            Some("/orgs/{org}/")
+        );
+        assert_eq_dbg!(
+            event.transaction.meta().iter_remarks().next(),
+            Some(&Remark::new(RemarkType::Substituted, "/orgs/*/**"))
+        );
+    }
+
+    #[test]
+    fn test_normalize_unchanged() {
+        let event = normalize("/user/profile", None);
+
+        assert_eq_dbg!(
+            event.transaction.value().map(String::as_str),
+            Some("/user/profile")
+        );
+        assert!(event.transaction.meta().is_empty());
+        assert_eq_dbg!(get_source(&event), None);
+
+        let event = normalize("/user/profile", Some(TransactionSource::Url));
+        assert_eq_dbg!(get_source(&event), Some(TransactionSource::Url));
+    }
+
+    #[test]
+    fn test_rules_only_apply_to_urls() {
+        let rules = vec![TransactionNameRule {
+            pattern: "GET *".into(),
+            replacement: "GET {resource}".to_owned(),
+        }];
+
+        let mut event = Event {
+            ty: Annotated::new(EventType::Transaction),
+            transaction: Annotated::new("GET user".to_owned()),
+            ..Default::default()
+        };
+
+        normalize_transaction_name(&mut event, &rules);
+        assert_eq_dbg!(
+            event.transaction.value().map(String::as_str),
+            Some("GET user")
+        );
+        assert_eq_dbg!(get_source(&event), None);
+    }
+
+    #[test]
+    fn test_skip_other_sources() {
+        let event = normalize("/user/42", Some(TransactionSource::Route));
+        assert_eq_dbg!(
+            event.transaction.value().map(String::as_str),
+            Some("/user/42")
+        );
+        assert_eq_dbg!(get_source(&event), Some(TransactionSource::Route));
+
+        let event = normalize("GET user", None);
+        assert_eq_dbg!(
+            event.transaction.value().map(String::as_str),
+            Some("GET user")
+        );
+        assert_eq_dbg!(get_source(&event), None);
+    }
+}
@@ -1,8 +1,14 @@
+use std::collections::{HashMap, HashSet};
+
 use chrono::{DateTime, Duration, Utc};
 
 use crate::processor::{ProcessValue, ProcessingState, Processor};
-use crate::protocol::{Context, ContextInner, Contexts, Event, EventType, Span};
-use crate::types::{Annotated, Meta, ProcessingAction, ProcessingResult, Timestamp};
+use crate::protocol::{
+    Context, ContextInner, Contexts, Event, EventType, Span, SpanId, TraceContext,
+};
+use crate::types::{
+    Annotated, Error, Meta, ProcessingAction, ProcessingResult, Timestamp, ToValue,
+};
 
 pub struct TransactionsProcessor {
     /// Timestamp when the client thinks it sent the event. None means that we default to
@@ -24,6 +30,206 @@ impl TransactionsProcessor {
     }
 }
 
+/// Returns the trace context of a transaction.
+fn get_trace_context(event: &Event) -> Option<&TraceContext> {
+    match event.contexts.value()?.get("trace")?.value()? {
+        ContextInner(Context::Trace(ref trace_context)) => Some(trace_context),
+        _ => None,
+    }
+}
+
+/// Sets a new value on a field and records the original value along with an error.
+fn repair_value<T>(annotated: &mut Annotated<T>, value: T, reason: &'static str)
+where
+    T: ToValue,
+{
+    let original = std::mem::replace(annotated.value_mut(), Some(value));
+    let meta = annotated.meta_mut();
+    meta.add_error(Error::invalid(reason));
+    meta.set_original_value(original);
+}
+
+/// Moves a timestamp into the time window of the transaction.
+fn clamp_timestamp(timestamp: &mut Annotated<Timestamp>, start: Timestamp, end: Timestamp) {
+    match timestamp.value() {
+        Some(value) if *value < start => {
+            repair_value(timestamp, start, "span exceeds the transaction start")
+        }
+        Some(value) if *value > end => {
+            repair_value(timestamp, end, "span exceeds the transaction end")
+        }
+        _ => (),
+    }
+}
+
+/// Checks whether following the parent chain starting at `parent_id` reaches the root span.
+fn reaches_root(parents: &HashMap<String, Option<String>>, root_id: &str, parent_id: &str) -> bool {
+    let mut current = parent_id;
+
+    // Bound the walk by the number of spans to break out of cycles.
+    for _ in 0..=parents.len() {
+        if current == root_id {
+            return true;
+        }
+
+        match parents.get(current) {
+            Some(Some(parent_id)) => current = parent_id,
+            _ => return false,
+        }
+    }
+
+    false
+}
+
+/// Validates that the spans of a transaction form a tree below its root span and repairs it.
+///
+///  - Spans with an id that has been used before are marked as duplicates.
+///  - Spans belonging to a different trace are marked.
+///  - Spans without a parent, with a parent that does not exist, or that are part of a cycle are
+///    re-parented to the root span.
+///  - Span timestamps outside of the transaction are clamped to the transaction.
+///
+/// All problems are recorded as errors in the `Meta` of the respective field, along with the
+/// original value if it was changed.
+fn repair_span_tree(event: &mut Event) {
+    let (root_id, trace_id) = match get_trace_context(event) {
+        Some(trace_context) => (
+            trace_context.span_id.value().cloned(),
+            trace_context.trace_id.value().cloned(),
+        ),
+        None => return,
+    };
+
+    let root_id = match root_id {
+        Some(SpanId(root_id)) => root_id,
+        None => return,
+    };
+
+    let window = match (event.start_timestamp.value(), event.timestamp.value()) {
+        (Some(start), Some(end)) => Some((*start, *end)),
+        _ => None,
+    };
+
+    let spans = match event.spans.value_mut() {
+        Some(spans) => spans,
+        None => return,
+    };
+
+    // The parent of every span by id. Duplicate span ids are not added since their parent
+    // relationship is ambiguous. Instead, the indexes of duplicate spans are remembered so that
+    // re-parenting them does not change the parent of the first span with the same id.
+    let mut parents = HashMap::new();
+    let mut ids = HashSet::new();
+    let mut duplicates = HashSet::new();
+    ids.insert(root_id.clone());
+
+    for (index, span) in spans
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(index, span)| Some((index, span.value_mut().as_mut()?)))
+    {
+        let span_id = match span.span_id.value() {
+            Some(SpanId(span_id)) => span_id.clone(),
+            None => continue,
+        };
+
+        if ids.insert(span_id.clone()) {
+            let parent_id = span.parent_span_id.value().map(|id| id.0.clone());
+            parents.insert(span_id, parent_id);
+        } else {
+            duplicates.insert(index);
+            span.span_id
+                .meta_mut()
+                .add_error(Error::invalid("duplicate span id"));
+        }
+    }
+
+    // Spans reusing the root span's id have been marked as duplicates above. Re-parenting them
+    // would create a cycle.
+    let is_root = |span: &Span| span.span_id.value().map_or(false, |id| id.0 == root_id);
+
+    // Connect spans without a valid parent to the root first. This also connects all of their
+    // descendants, regardless of the order of spans.
+    for (index, span) in spans
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(index, span)| Some((index, span.value_mut().as_mut()?)))
+    {
+        let reason = match span.parent_span_id.value() {
+            _ if is_root(span) => None,
+            None => Some("span has no parent"),
+            Some(SpanId(parent_id)) if !ids.contains(parent_id) => {
+                Some("parent span does not exist")
+            }
+            Some(_) => None,
+        };
+
+        if let Some(reason) = reason {
+            let parents = if duplicates.contains(&index) {
+                None
+            } else {
+                Some(&mut parents)
+            };
+            reparent_span(span, &root_id, parents, reason);
+        }
+    }
+
+    // All spans that are still not connected to the root are part of a cycle. Re-parenting one
+    // span of a cycle connects all others.
+    for (index, span) in spans
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(index, span)| Some((index, span.value_mut().as_mut()?)))
+    {
+        let connected = match span.parent_span_id.value() {
+            Some(SpanId(parent_id)) => is_root(span) || reaches_root(&parents, &root_id, parent_id),
+            None => true,
+        };
+
+        if !connected {
+            let reason = "span is not connected to the transaction";
+            let parents = if duplicates.contains(&index) {
+                None
+            } else {
+                Some(&mut parents)
+            };
+            reparent_span(span, &root_id, parents, reason);
+        }
+
+        if let (Some(expected), Some(actual)) = (&trace_id, span.trace_id.value()) {
+            if expected != actual {
+                let reason = "span belongs to a different trace";
+                span.trace_id.meta_mut().add_error(Error::invalid(reason));
+            }
+        }
+
+        if let Some((start, end)) = window {
+            clamp_timestamp(&mut span.start_timestamp, start, end);
+            clamp_timestamp(&mut span.timestamp, start, end);
+        }
+    }
+}
+
+/// Sets the parent of a span to the root span and records the original parent.
+///
+/// If `parents` is given, the parent of the span is updated there as well. This must be `None`
+/// for spans with duplicate ids, which do not own their entry in `parents`.
+fn reparent_span(
+    span: &mut Span,
+    root_id: &str,
+    parents: Option<&mut HashMap<String, Option<String>>>,
+    reason: &'static str,
+) {
+    repair_value(&mut span.parent_span_id, SpanId(root_id.to_owned()), reason);
+
+    // Update the parent so that descendants of this span are connected again.
+    if let (Some(SpanId(span_id)), Some(parents)) = (span.span_id.value(), parents) {
+        if let Some(parent_id) = parents.get_mut(span_id) {
+            *parent_id = Some(root_id.to_owned());
+        }
+    }
+}
+
 impl Processor for TransactionsProcessor {
     fn process_event(
         &mut self,
@@ -108,6 +314,8 @@ impl Processor for TransactionsProcessor {
             }
         }
 
+        repair_span_tree(event);
+
         event.process_child_values(self, state)?;
 
         Ok(())
@@ -183,7 +391,7 @@ mod tests {
     use super::*;
     use crate::processor::process_value;
     use crate::protocol::{SpanId, TraceContext, TraceId};
-    use crate::types::Object;
+    use crate::types::{Object, Value};
     use chrono::offset::TimeZone;
     use chrono::Utc;
 
@@ -837,4 +1045,177 @@ mod tests {
             end
         ); // shift by 1 day == end
     }
+
+    fn transaction_with_spans(spans: Vec<Span>) -> Annotated<Event> {
+        Annotated::new(Event {
+            ty: Annotated::new(EventType::Transaction),
+            timestamp: Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, 10)),
+            start_timestamp: Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, 0)),
+            contexts: Annotated::new(Contexts({
+                let mut contexts = Object::new();
+                contexts.insert(
+                    "trace".to_owned(),
+                    Annotated::new(ContextInner(Context::Trace(Box::new(TraceContext {
+                        trace_id: Annotated::new(TraceId(
+                            "4c79f60c11214eb38604f4ae0781bfb2".into(),
+                        )),
+                        span_id: Annotated::new(SpanId("fa90fdead5f74052".into())),
+                        op: Annotated::new("http.server".to_owned()),
+                        ..Default::default()
+                    })))),
+                );
+                contexts
+            })),
+            spans: Annotated::new(spans.into_iter().map(Annotated::new).collect()),
+            ..Default::default()
+        })
+    }
+
+    fn span(span_id: &str, parent_span_id: Option<&str>) -> Span {
+        Span {
+            timestamp: Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, 5)),
+            start_timestamp: Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, 1)),
+            trace_id: Annotated::new(TraceId("4c79f60c11214eb38604f4ae0781bfb2".into())),
+            span_id: Annotated::new(SpanId(span_id.into())),
+            parent_span_id: Annotated::from(parent_span_id.map(|id| SpanId(id.into()))),
+            op: Annotated::new("db.statement".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn process_spans(spans: Vec<Span>) -> Vec<Span> {
+        let mut event = transaction_with_spans(spans);
+        let mut processor = TransactionsProcessor::new(None);
+        processor.now = Utc.ymd(2000, 1, 1).and_hms(0, 0, 10);
+        process_value(&mut event, &mut processor, ProcessingState::root()).unwrap();
+
+        let spans = event.value_mut().as_mut().unwrap().spans.value_mut().take();
+        spans
+            .unwrap()
+            .into_iter()
+            .map(|span| span.0.unwrap())
+            .collect()
+    }
+
+    fn parent_id(span: &Span) -> Option<&str> {
+        span.parent_span_id.value().map(|id| id.0.as_str())
+    }
+
+    fn errors<T>(annotated: &Annotated<T>) -> Vec<Error> {
+        annotated.meta().iter_errors().cloned().collect()
+    }
+
+    #[test]
+    fn test_span_tree_valid() {
+        let spans = process_spans(vec![
+            span("aaaaaaaaaaaaaaaa", Some("fa90fdead5f74052")),
+            span("bbbbbbbbbbbbbbbb", Some("aaaaaaaaaaaaaaaa")),
+        ]);
+
+        assert_eq_dbg!(parent_id(&spans[0]), Some("fa90fdead5f74052"));
+        assert_eq_dbg!(parent_id(&spans[1]), Some("aaaaaaaaaaaaaaaa"));
+        assert!(spans[0].parent_span_id.meta().is_empty());
+        assert!(spans[1].parent_span_id.meta().is_empty());
+    }
+
+    #[test]
+    fn test_span_tree_reparents_orphans() {
+        let spans = process_spans(vec![
+            span("aaaaaaaaaaaaaaaa", None),
+            span("dddddddddddddddd", Some("bbbbbbbbbbbbbbbb")),
+            span("bbbbbbbbbbbbbbbb", Some("cccccccccccccccc")),
+        ]);
+
+        assert_eq_dbg!(parent_id(&spans[0]), Some("fa90fdead5f74052"));
+        assert_eq_dbg!(
+            errors(&spans[0].parent_span_id),
+            vec![Error::invalid("span has no parent")]
+        );
+
+        assert_eq_dbg!(parent_id(&spans[2]), Some("fa90fdead5f74052"));
+        assert_eq_dbg!(
+            spans[2].parent_span_id.meta().original_value(),
+            Some(&Value::String("cccccccccccccccc".into()))
+        );
+
+        // Descendants of re-parented spans are kept as they are, even if they come first.
+        assert_eq_dbg!(parent_id(&spans[1]), Some("bbbbbbbbbbbbbbbb"));
+        assert!(spans[1].parent_span_id.meta().is_empty());
+    }
+
+    #[test]
+    fn test_span_tree_breaks_cycles() {
+        let spans = process_spans(vec![
+            span("aaaaaaaaaaaaaaaa", Some("bbbbbbbbbbbbbbbb")),
+            span("bbbbbbbbbbbbbbbb", Some("aaaaaaaaaaaaaaaa")),
+        ]);
+
+        // Once the first span is connected to the root, the second span is connected as well.
+        assert_eq_dbg!(parent_id(&spans[0]), Some("fa90fdead5f74052"));
+        assert_eq_dbg!(
+            errors(&spans[0].parent_span_id),
+            vec![Error::invalid("span is not connected to the transaction")]
+        );
+        assert_eq_dbg!(parent_id(&spans[1]), Some("aaaaaaaaaaaaaaaa"));
+    }
+
+    #[test]
+    fn test_span_tree_duplicate_ids() {
+        let spans = process_spans(vec![
+            span("aaaaaaaaaaaaaaaa", Some("fa90fdead5f74052")),
+            span("aaaaaaaaaaaaaaaa", Some("fa90fdead5f74052")),
+        ]);
+
+        assert!(spans[0].span_id.meta().is_empty());
+        assert_eq_dbg!(
+            errors(&spans[1].span_id),
+            vec![Error::invalid("duplicate span id")]
+        );
+    }
+
+    #[test]
+    fn test_span_tree_duplicate_ids_in_cycle() {
+        let spans = process_spans(vec![
+            span("aaaaaaaaaaaaaaaa", Some("bbbbbbbbbbbbbbbb")),
+            span("bbbbbbbbbbbbbbbb", Some("aaaaaaaaaaaaaaaa")),
+            span("aaaaaaaaaaaaaaaa", None),
+        ]);
+
+        // Re-parenting the duplicate must not connect the cycle of the first span with this id.
+        assert_eq_dbg!(parent_id(&spans[2]), Some("fa90fdead5f74052"));
+        assert_eq_dbg!(parent_id(&spans[0]), Some("fa90fdead5f74052"));
+        assert_eq_dbg!(
+            errors(&spans[0].parent_span_id),
+            vec![Error::invalid("span is not connected to the transaction")]
+        );
+    }
+
+    #[test]
+    fn test_span_tree_clamps_timestamps() {
+        let mut early = span("aaaaaaaaaaaaaaaa", Some("fa90fdead5f74052"));
+        early.start_timestamp = Annotated::new(Utc.ymd(1999, 12, 31).and_hms(23, 59, 59));
+
+        let mut late = span("bbbbbbbbbbbbbbbb", Some("fa90fdead5f74052"));
+        late.timestamp = Annotated::new(Utc.ymd(2000, 1, 1).and_hms(0, 0, 11));
+
+        let spans = process_spans(vec![early, late]);
+
+        assert_eq_dbg!(
+            spans[0].start_timestamp.value(),
+            Some(&Utc.ymd(2000, 1, 1).and_hms(0, 0, 0))
+        );
+        assert_eq_dbg!(
+            errors(&spans[0].start_timestamp),
+            vec![Error::invalid("span exceeds the transaction start")]
+        );
+
+        assert_eq_dbg!(
+            spans[1].timestamp.value(),
+            Some(&Utc.ymd(2000, 1, 1).and_hms(0, 0, 10))
+        );
+        assert_eq_dbg!(
+            errors(&spans[1].timestamp),
+            vec![Error::invalid("span exceeds the transaction end")]
+        );
+    }
 }
@@ -265,6 +265,16 @@ where
     pub fn from_json_bytes(b: &[u8]) -> Result<Self, serde_json::Error> {
         Self::deserialize_with_meta(&mut serde_json::Deserializer::from_slice(b))
     }
+
+    /// Deserializes an annotated from MessagePack bytes.
+    pub fn from_msgpack(b: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        Self::deserialize_with_meta(&mut rmp_serde::Deserializer::from_read_ref(b))
+    }
+
+    /// Deserializes an annotated from CBOR bytes.
+    pub fn from_cbor(b: &[u8]) -> Result<Self, serde_cbor::Error> {
+        Self::deserialize_with_meta(&mut serde_cbor::Deserializer::from_slice(b))
+    }
 }
 
 impl<T> Annotated<T>
@@ -505,3 +515,56 @@ fn test_annotated_deserialize_with_meta() {
 }"#
     );
 }
+
+#[test]
+fn test_annotated_from_msgpack() {
+    #[derive(Debug, Empty, FromValue, ToValue)]
+    struct Foo {
+        id: Annotated<u64>,
+        #[metastructure(field = "type")]
+        ty: Annotated<String>,
+    }
+
+    let json = serde_json::json!({
+        "id": "blaflasel",
+        "type": "testing",
+    });
+
+    let msgpack = rmp_serde::to_vec_named(&json).unwrap();
+    let annotated_value = Annotated::<Foo>::from_msgpack(&msgpack).unwrap();
+    let foo = annotated_value.value().unwrap();
+
+    assert_eq!(foo.id.value(), None);
+    assert!(foo.id.meta().has_errors());
+    assert_eq!(foo.ty.as_str(), Some("testing"));
+}
+
+#[test]
+fn test_annotated_from_cbor() {
+    #[derive(Debug, Empty, FromValue, ToValue)]
+    struct Foo {
+        id: Annotated<u64>,
+        #[metastructure(field = "type")]
+        ty: Annotated<String>,
+    }
+
+    let json = serde_json::json!({
+        "id": 42,
+        "type": "testing",
+        "_meta": {
+            "type": {
+                "": {
+                    "err": ["invalid_data"]
+                }
+            }
+        }
+    });
+
+    let cbor = serde_cbor::to_vec(&json).unwrap();
+    let annotated_value = Annotated::<Foo>::from_cbor(&cbor).unwrap();
+    let foo = annotated_value.value().unwrap();
+
+    assert_eq!(foo.id.value(), Some(&42));
+    assert_eq!(foo.ty.as_str(), Some("testing"));
+    assert!(foo.ty.meta().has_errors());
+}
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 use std::fmt;
 use std::str;
 
-use serde::de::{Deserialize, MapAccess, SeqAccess, Visitor};
+use serde::de::{Deserialize, MapAccess, SeqAccess, Unexpected, Visitor};
 use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
 
 use crate::types::{Annotated, Meta};
@@ -271,6 +271,17 @@ impl<'de> Deserialize<'de> for Value {
                 Ok(Value::String(value))
             }
 
+            fn visit_bytes<E>(self, value: &[u8]) -> Result<Value, E>
+            where
+                E: serde::de::Error,
+            {
+                // Binary formats such as MessagePack and CBOR may encode strings as raw bytes.
+                match str::from_utf8(value) {
+                    Ok(string) => self.visit_str(string),
+                    Err(_) => Err(E::invalid_value(Unexpected::Bytes(value), &self)),
+                }
+            }
+
             #[inline]
             fn visit_some<D>(self, deserializer: D) -> Result<Value, D::Error>
             where
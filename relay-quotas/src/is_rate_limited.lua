@@ -1,45 +1,111 @@
 -- Check a collection of quota counters to identify if an item should be rate
--- limited. Values provided as ``KEYS`` specify the keys of the counters to
--- check and the keys of counters to subtract, and values provided as ``ARGV``
--- specify the maximum value (quota limit) and expiration time for each key.
+-- limited. The first value in ``ARGV`` is the current Unix timestamp. It is
+-- followed by five values per quota: the quota mode, the maximum value (quota
+-- limit), the expiration time of the quota's keys, a mode-specific parameter
+-- and the quantity of the item (``1`` when counting items, or the payload size
+-- when counting bytes). The number of ``KEYS`` per quota depends on its mode:
 --
--- For example, to check a quota ``foo`` that has a corresponding refund/negative
--- counter "subtract_from_foo", a limit of 10 items and expires at the Unix timestamp
--- ``100``, as well as a quota ``bar`` that has a corresponding refund/negative
--- counter "subtract_from_bar" limit of 20 items and should expire at the Unix
--- timestamp ``100``, the ``KEYS`` and ``ARGV`` values would be as follows:
+--  - ``fixed_window``: The key of the counter and the key of the counter to
+--    subtract (refunds). The parameter is unused.
+--  - ``sliding_window``: The keys of the counters of the current window and its
+--    refunds, followed by the keys of the counters of the previous window and
+--    its refunds. The parameter is the share of the previous window that still
+--    counts towards the limit, between ``0`` and ``1``.
+--  - ``token_bucket``: The key of a hash storing the remaining ``tokens`` and
+--    the time they were last updated at ``ts``. The limit is the capacity of
+--    the bucket, and the parameter is the refill rate in tokens per second.
 --
---   KEYS = {"foo", "subtract_from_foo", "bar", "subtract_from_bar"}
---   ARGV = {10, 100, 20, 100}
+-- For example, to check a fixed window quota ``foo`` that has a corresponding
+-- refund/negative counter "subtract_from_foo", a limit of 10 items and expires
+-- at the Unix timestamp ``100``, as well as a token bucket ``bar`` with a
+-- capacity of 20 tokens that refills one token per second and expires at the
+-- Unix timestamp ``120``, the ``KEYS`` and ``ARGV`` values would be as follows:
+--
+--   KEYS = {"foo", "subtract_from_foo", "bar"}
+--   ARGV = {now, "fixed_window", 10, 100, 0, 1, "token_bucket", 20, 120, 1, 1}
 --
 -- If all checks pass (the item is accepted), the counters for all quotas are
--- incremented. If any checks fail (the item is rejected), the counters for all
--- quotas are unaffected. The result is a Lua table/array (Redis multi bulk
--- reply) that specifies whether or not the item was *rejected* based on the
--- provided limit.
-assert(#KEYS == #ARGV, "incorrect number of keys and arguments provided")
-assert(#KEYS % 2 == 0, "there must be an even number of keys")
+-- incremented and tokens are taken from all buckets by the item's quantity. If any checks fail (the
+-- item is rejected), the counters for all quotas are unaffected. The result is
+-- a Lua table/array (Redis multi bulk reply) that specifies whether or not the
+-- item was *rejected* based on the provided limit.
+assert(#ARGV % 5 == 1, "incorrect number of arguments provided")
+
+local now = tonumber(ARGV[1])
+
+local function get_value(key)
+    return (redis.call('GET', key) or 0) - 0
+end
+
+local function get_tokens(key, capacity, rate)
+    local bucket = redis.call('HMGET', key, 'tokens', 'ts')
+    -- Refunds can create a bucket without timestamp, which is treated like a full bucket.
+    if not bucket[1] or not bucket[2] then
+        return capacity
+    end
+
+    local elapsed = math.max(now - tonumber(bucket[2]), 0)
+    return math.min(capacity, tonumber(bucket[1]) + elapsed * rate)
+end
 
+local quotas = {}
 local results = {}
 local failed = false
-for i=1, #KEYS, 2 do
-    local limit = tonumber(ARGV[i])
+local k = 1
+for i=2, #ARGV, 5 do
+    local quota = {
+        mode = ARGV[i],
+        limit = tonumber(ARGV[i + 1]),
+        expiry = ARGV[i + 2],
+        param = tonumber(ARGV[i + 3]),
+        quantity = tonumber(ARGV[i + 4]),
+        key = k,
+    }
+
     local rejected = false
-    -- limit=-1 means "no limit"
-    if limit >= 0 then
-        rejected = (redis.call('GET', KEYS[i]) or 0) - (redis.call('GET', KEYS[i + 1]) or 0) + 1 > limit
+    if quota.mode == 'token_bucket' then
+        quota.tokens = get_tokens(KEYS[k], quota.limit, quota.param)
+        -- limit=-1 means "no limit"
+        if quota.limit >= 0 then
+            rejected = quota.tokens < quota.quantity
+        end
+        k = k + 1
+    elseif quota.mode == 'sliding_window' then
+        if quota.limit >= 0 then
+            local current = get_value(KEYS[k]) - get_value(KEYS[k + 1])
+            local previous = get_value(KEYS[k + 2]) - get_value(KEYS[k + 3])
+            rejected = current + math.floor(previous * quota.param) + quota.quantity > quota.limit
+        end
+        k = k + 4
+    else
+        if quota.limit >= 0 then
+            rejected = get_value(KEYS[k]) - get_value(KEYS[k + 1]) + quota.quantity > quota.limit
+        end
+        k = k + 2
     end
 
     if rejected then
         failed = true
     end
-    results[(i + 1) / 2] = rejected
+    quotas[#quotas + 1] = quota
+    results[#results + 1] = rejected
 end
 
+assert(k == #KEYS + 1, "incorrect number of keys provided")
+
 if not failed then
-    for i=1, #KEYS, 2 do
-        redis.call('INCR', KEYS[i])
-        redis.call('EXPIREAT', KEYS[i], ARGV[i + 1])
+    for _, quota in ipairs(quotas) do
+        local key = KEYS[quota.key]
+        if quota.mode == 'token_bucket' then
+            -- Unlimited buckets never run out of tokens, so there is nothing to record.
+            if quota.limit >= 0 then
+                redis.call('HMSET', key, 'tokens', tostring(quota.tokens - quota.quantity), 'ts', now)
+                redis.call('EXPIREAT', key, quota.expiry)
+            end
+        else
+            redis.call('INCRBY', key, quota.quantity)
+            redis.call('EXPIREAT', key, quota.expiry)
+        end
     end
 end
 
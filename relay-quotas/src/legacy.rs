@@ -8,7 +8,7 @@
 use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
 use smallvec::smallvec;
 
-use crate::types::{DataCategory, Quota, QuotaScope, ReasonCode};
+use crate::types::{DataCategory, Quota, QuotaMode, QuotaScope, QuotaUnit, ReasonCode};
 
 /// Legacy format of the `Quota` type.
 #[derive(Deserialize, Serialize)]
@@ -60,6 +60,9 @@ impl From<LegacyQuota> for Quota {
             limit: legacy.limit,
             window: legacy.window,
             reason_code: legacy.reason_code,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         }
     }
 }
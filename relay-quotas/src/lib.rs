@@ -5,6 +5,12 @@
 mod types;
 pub use self::types::*;
 
+mod local;
+pub use self::local::*;
+
+mod spike_protection;
+pub use self::spike_protection::*;
+
 #[cfg(feature = "legacy")]
 pub mod legacy;
 
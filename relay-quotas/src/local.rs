@@ -0,0 +1,670 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use relay_common::UnixTimestamp;
+
+use crate::types::{
+    ItemScoping, Quota, QuotaMode, QuotaScope, QuotaUnit, RateLimit, RateLimits, RetryAfter,
+};
+
+/// The default timeout to apply when a scope is fully rejected. This
+/// typically happens for disabled keys, projects, or organizations.
+const REJECT_ALL_SECS: u64 = 60;
+
+/// Identifies the counter of a quota within a single window.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CounterKey {
+    /// The quota id.
+    id: String,
+    /// The organization id, which is always part of the key.
+    organization_id: u64,
+    /// The identifier of the quota's scope, unless the quota is organization-scoped.
+    subscope: Option<String>,
+    /// The window slot. Always `0` for token buckets.
+    slot: u64,
+}
+
+/// The consumed quantity of a quota in a window.
+#[derive(Debug)]
+struct Counter {
+    /// The quantity consumed in this window.
+    value: u64,
+    /// The time at which the counter is no longer needed and can be discarded.
+    expiry: UnixTimestamp,
+}
+
+/// The remaining tokens of a quota in `TokenBucket` mode.
+#[derive(Debug)]
+struct Bucket {
+    /// The number of tokens at the time of the last update.
+    tokens: f64,
+    /// The time of the last update.
+    updated: UnixTimestamp,
+    /// The time at which the bucket is full again and can be discarded.
+    expiry: UnixTimestamp,
+}
+
+/// All counters and buckets of a `LocalRateLimiter`.
+#[derive(Debug, Default)]
+struct Counters {
+    windows: HashMap<CounterKey, Counter>,
+    buckets: HashMap<CounterKey, Bucket>,
+}
+
+impl Counters {
+    /// Discards all counters and buckets that have expired.
+    fn prune(&mut self, timestamp: UnixTimestamp) {
+        self.windows.retain(|_, counter| counter.expiry > timestamp);
+        self.buckets.retain(|_, bucket| bucket.expiry > timestamp);
+    }
+
+    /// Returns the consumed quantity in the given window.
+    fn consumed(&self, key: &CounterKey) -> u64 {
+        self.windows.get(key).map_or(0, |counter| counter.value)
+    }
+
+    /// Returns the tokens currently available in the quota's bucket.
+    fn tokens(&self, quota: &LocalQuota<'_>) -> f64 {
+        let capacity = quota.bucket_capacity().unwrap_or_default() as f64;
+        let rate = quota.refill_rate().unwrap_or_default();
+
+        match self.buckets.get(&quota.bucket_key()) {
+            Some(bucket) => {
+                let elapsed = quota
+                    .timestamp
+                    .as_secs()
+                    .saturating_sub(bucket.updated.as_secs()) as f64;
+                capacity.min(bucket.tokens + elapsed * rate)
+            }
+            None => capacity,
+        }
+    }
+
+    /// Checks whether the quota has capacity for the item.
+    fn is_rejected(&self, quota: &LocalQuota<'_>) -> bool {
+        // Unlimited quotas are still counted, but can never be exceeded.
+        let limit = match quota.limit {
+            Some(limit) => u64::from(limit),
+            None => return false,
+        };
+
+        match quota.mode {
+            QuotaMode::SlidingWindow => {
+                let previous = self.consumed(&quota.previous_key()) as f64;
+                let previous = (previous * quota.previous_weight()).floor() as u64;
+                self.consumed(&quota.key()) + previous + quota.quantity() > limit
+            }
+            QuotaMode::TokenBucket => self.tokens(quota) < quota.quantity() as f64,
+            _ => self.consumed(&quota.key()) + quota.quantity() > limit,
+        }
+    }
+
+    /// Records consumption of the quota.
+    fn consume(&mut self, quota: &LocalQuota<'_>) {
+        if quota.mode == QuotaMode::TokenBucket {
+            // Unlimited buckets never run out of tokens, so there is nothing to record.
+            if quota.limit.is_none() {
+                return;
+            }
+
+            let tokens = self.tokens(quota) - quota.quantity() as f64;
+            self.buckets.insert(
+                quota.bucket_key(),
+                Bucket {
+                    tokens,
+                    updated: quota.timestamp,
+                    expiry: quota.expiry(),
+                },
+            );
+        } else {
+            let counter = self.windows.entry(quota.key()).or_insert_with(|| Counter {
+                value: 0,
+                expiry: quota.expiry(),
+            });
+
+            counter.value += quota.quantity();
+        }
+    }
+
+    /// Reverts consumption of the quota.
+    fn refund(&mut self, quota: &LocalQuota<'_>) {
+        if quota.mode == QuotaMode::TokenBucket {
+            // Missing buckets are full already.
+            let capacity = quota.bucket_capacity().unwrap_or_default() as f64;
+            if let Some(bucket) = self.buckets.get_mut(&quota.bucket_key()) {
+                bucket.tokens = capacity.min(bucket.tokens + quota.quantity() as f64);
+            }
+        } else if let Some(counter) = self.windows.get_mut(&quota.key()) {
+            counter.value = counter.value.saturating_sub(quota.quantity());
+        }
+    }
+}
+
+/// Reference to information required for tracking quotas in memory.
+#[derive(Debug)]
+struct LocalQuota<'a> {
+    /// The original quota.
+    quota: &'a Quota,
+    /// Scopes of the item being tracked.
+    scoping: &'a ItemScoping,
+    /// The counter prefix mapped from the quota id.
+    prefix: &'a str,
+    /// The window in seconds mapped from the quota.
+    window: u64,
+    /// The ingestion timestamp determining the rate limiting bucket.
+    timestamp: UnixTimestamp,
+}
+
+impl<'a> LocalQuota<'a> {
+    fn new(quota: &'a Quota, scoping: &'a ItemScoping, timestamp: UnixTimestamp) -> Option<Self> {
+        // These fields indicate that we *can* track this quota.
+        let prefix = quota.id.as_deref()?;
+        let window = quota.window.filter(|window| *window > 0)?;
+
+        if quota.mode == QuotaMode::Unknown || quota.unit == QuotaUnit::Unknown {
+            return None;
+        }
+
+        Some(Self {
+            quota,
+            scoping,
+            prefix,
+            window,
+            timestamp,
+        })
+    }
+
+    /// Returns the quantity of the item counted against this quota.
+    fn quantity(&self) -> u64 {
+        self.quota.quantity(self.scoping)
+    }
+
+    fn shift(&self) -> u64 {
+        self.scoping.organization_id % self.window
+    }
+
+    fn slot(&self) -> u64 {
+        (self.timestamp.as_secs() - self.shift()) / self.window
+    }
+
+    fn window_start(&self, slot: u64) -> u64 {
+        slot * self.window + self.shift()
+    }
+
+    /// Returns the share of the previous window that overlaps with a sliding window ending now.
+    fn previous_weight(&self) -> f64 {
+        let elapsed = self.timestamp.as_secs() - self.window_start(self.slot());
+        1.0 - elapsed as f64 / self.window as f64
+    }
+
+    /// Returns the time at which the counter or bucket of this quota can be discarded.
+    fn expiry(&self) -> UnixTimestamp {
+        match self.mode {
+            // The current window is needed as previous window until the end of the next window.
+            QuotaMode::SlidingWindow => {
+                UnixTimestamp::from_secs(self.window_start(self.slot() + 2))
+            }
+            // After this time, the bucket is full again. This is the same as a missing bucket.
+            QuotaMode::TokenBucket => {
+                let capacity = self.bucket_capacity().unwrap_or_default() as f64;
+                let rate = self.refill_rate().unwrap_or(1.0);
+                let refill_secs = (capacity / rate).ceil() as u64;
+                UnixTimestamp::from_secs(self.timestamp.as_secs() + refill_secs)
+            }
+            _ => UnixTimestamp::from_secs(self.window_start(self.slot() + 1)),
+        }
+    }
+
+    /// Returns the number of seconds until this quota accepts items again after a rejection.
+    fn retry_after_secs(&self) -> u64 {
+        match self.mode {
+            // Approximately the time it takes to refill a single token.
+            QuotaMode::TokenBucket => match self.refill_rate() {
+                Some(rate) => (1.0 / rate).ceil() as u64,
+                None => self.window,
+            },
+            _ => self.window_start(self.slot() + 1) - self.timestamp.as_secs(),
+        }
+    }
+
+    fn counter_key(&self, slot: u64) -> CounterKey {
+        // Like in Redis, the subscope is only part of the key if the quota is not
+        // organization-scoped.
+        let subscope = match self.quota.scope {
+            QuotaScope::Organization => None,
+            scope => self.scoping.scope_id(scope).map(Cow::into_owned),
+        };
+
+        CounterKey {
+            id: self.prefix.to_owned(),
+            organization_id: self.scoping.organization_id,
+            subscope,
+            slot,
+        }
+    }
+
+    fn key(&self) -> CounterKey {
+        self.counter_key(self.slot())
+    }
+
+    fn previous_key(&self) -> CounterKey {
+        self.counter_key(self.slot().saturating_sub(1))
+    }
+
+    fn bucket_key(&self) -> CounterKey {
+        self.counter_key(0)
+    }
+}
+
+impl std::ops::Deref for LocalQuota<'_> {
+    type Target = Quota;
+
+    fn deref(&self) -> &Self::Target {
+        self.quota
+    }
+}
+
+/// A service that executes quotas and checks for rate limits in memory of this process.
+///
+/// This is the counterpart of `RateLimiter` for Relays without access to Redis. It applies the same
+/// semantics, but quotas are only counted for items ingested by this instance. `LocalRateLimiter`
+/// can be cloned cheaply, and all clones share the same counters.
+#[derive(Clone, Debug, Default)]
+pub struct LocalRateLimiter {
+    counters: Arc<Mutex<Counters>>,
+    max_limit: Option<u64>,
+}
+
+impl LocalRateLimiter {
+    /// Creates a new `LocalRateLimiter` instance without any consumed quotas.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum rate limit in seconds.
+    ///
+    /// By default, this rate limiter will return rate limits based on the quotas' `window` fields.
+    /// If a maximum rate limit is set, this limit is bounded.
+    pub fn max_limit(mut self, max_limit: Option<u64>) -> Self {
+        self.max_limit = max_limit;
+        self
+    }
+
+    /// Checks whether any of the quotas in effect for the given project and project key has been
+    /// exceeded and records consumption of the quota.
+    ///
+    /// By invoking this method, the caller signals that data is being ingested and needs to be
+    /// counted against the quota. This increment happens atomically if none of the quotas have been
+    /// exceeded. Otherwise, a rate limit is returned and data is not counted against the quotas.
+    pub fn is_rate_limited(&self, quotas: &[Quota], scoping: &ItemScoping) -> RateLimits {
+        self.is_rate_limited_at(quotas, scoping, UnixTimestamp::now())
+    }
+
+    fn is_rate_limited_at(
+        &self,
+        quotas: &[Quota],
+        scoping: &ItemScoping,
+        timestamp: UnixTimestamp,
+    ) -> RateLimits {
+        let mut tracked_quotas = Vec::new();
+        let mut rate_limits = RateLimits::new();
+
+        for quota in quotas {
+            if !quota.matches(scoping) {
+                // Silently skip all quotas that do not apply to this item.
+            } else if quota.limit == Some(0) {
+                // A zero-sized quota is strongest. Do not increment any counters, as one quota has
+                // reached capacity (this is how regular quotas behave as well).
+                let retry_after = self.retry_after(REJECT_ALL_SECS);
+                if let Some(rate_limit) = RateLimit::from_quota(quota, scoping, retry_after) {
+                    rate_limits.add(rate_limit);
+                }
+            } else if let Some(quota) = LocalQuota::new(quota, scoping, timestamp) {
+                tracked_quotas.push(quota);
+            }
+
+            // Quotas that can neither be tracked nor reject all items are skipped for
+            // forward-compatibility.
+        }
+
+        if tracked_quotas.is_empty() || rate_limits.is_limited() {
+            return rate_limits;
+        }
+
+        let mut counters = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+        counters.prune(timestamp);
+
+        let mut rejected = false;
+        for quota in &tracked_quotas {
+            if counters.is_rejected(quota) {
+                rejected = true;
+                let retry_after = self.retry_after(quota.retry_after_secs());
+                if let Some(rate_limit) = RateLimit::from_quota(&*quota, scoping, retry_after) {
+                    rate_limits.add(rate_limit);
+                }
+            }
+        }
+
+        if !rejected {
+            for quota in &tracked_quotas {
+                counters.consume(quota);
+            }
+        }
+
+        rate_limits
+    }
+
+    /// Refunds an item that has been counted against the given quotas.
+    ///
+    /// This reverts the effect of a call to `is_rate_limited` that did not return any rate limits,
+    /// in case the item is dropped after it has been counted. The `timestamp` must be the time at
+    /// which the item was counted, so that the refund applies to the same window.
+    pub fn refund(&self, quotas: &[Quota], scoping: &ItemScoping, timestamp: UnixTimestamp) {
+        let mut counters = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for quota in quotas {
+            if !quota.matches(scoping) || quota.limit == Some(0) {
+                continue;
+            }
+
+            if let Some(quota) = LocalQuota::new(quota, scoping, timestamp) {
+                counters.refund(&quota);
+            }
+        }
+    }
+
+    /// Creates a rate limit bounded by `max_limit`.
+    fn retry_after(&self, mut seconds: u64) -> RetryAfter {
+        if let Some(max_limit) = self.max_limit {
+            seconds = std::cmp::min(seconds, max_limit);
+        }
+
+        RetryAfter::from_secs(seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_common::ProjectId;
+
+    use crate::types::{DataCategories, DataCategory, RateLimitScope, ReasonCode};
+
+    use super::*;
+
+    fn reason_codes(rate_limits: &RateLimits) -> Vec<&str> {
+        rate_limits
+            .iter()
+            .filter_map(|limit| limit.reason_code.as_ref())
+            .map(ReasonCode::as_str)
+            .collect()
+    }
+
+    fn scoping() -> ItemScoping {
+        ItemScoping {
+            category: DataCategory::Error,
+            organization_id: 42,
+            project_id: ProjectId::new(43),
+            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
+            key_id: Some(44),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_zero_size_quotas() {
+        let quotas = &[Quota {
+            id: None,
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(0),
+            window: None,
+            reason_code: Some(ReasonCode::new("get_lost")),
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
+        }];
+
+        let rate_limiter = LocalRateLimiter::new();
+        let rate_limits: Vec<RateLimit> = rate_limiter
+            .is_rate_limited(quotas, &scoping())
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            rate_limits,
+            vec![RateLimit {
+                categories: DataCategories::new(),
+                scope: RateLimitScope::Organization(42),
+                reason_code: Some(ReasonCode::new("get_lost")),
+                retry_after: rate_limits[0].retry_after,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_simple_quota() {
+        let quotas = &[Quota {
+            id: Some("foo".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Key,
+            scope_id: None,
+            limit: Some(5),
+            window: Some(60),
+            reason_code: Some(ReasonCode::new("get_lost")),
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
+        }];
+
+        let rate_limiter = LocalRateLimiter::new();
+        let timestamp = UnixTimestamp::from_secs(123_123_123);
+
+        for i in 0..10 {
+            let rate_limits: Vec<RateLimit> = rate_limiter
+                .is_rate_limited_at(quotas, &scoping(), timestamp)
+                .into_iter()
+                .collect();
+
+            if i >= 5 {
+                assert_eq!(
+                    rate_limits,
+                    vec![RateLimit {
+                        categories: DataCategories::new(),
+                        scope: RateLimitScope::Key("a94ae32be2584e0bbd7a4cbb95971fee".to_owned()),
+                        reason_code: Some(ReasonCode::new("get_lost")),
+                        retry_after: rate_limits[0].retry_after,
+                    }]
+                );
+            } else {
+                assert_eq!(rate_limits, vec![]);
+            }
+        }
+
+        // A different key is counted separately.
+        let other_scoping = ItemScoping {
+            key_id: Some(45),
+            ..scoping()
+        };
+        let rate_limits = rate_limiter.is_rate_limited_at(quotas, &other_scoping, timestamp);
+        assert!(rate_limits.is_ok());
+
+        // The next window starts with an empty counter.
+        let next_window = UnixTimestamp::from_secs(123_123_123 + 60);
+        let rate_limits = rate_limiter.is_rate_limited_at(quotas, &scoping(), next_window);
+        assert!(rate_limits.is_ok());
+    }
+
+    #[test]
+    fn test_rejected_items_do_not_count() {
+        let quotas = &[
+            Quota {
+                id: Some("q0".to_owned()),
+                categories: DataCategories::new(),
+                scope: QuotaScope::Organization,
+                scope_id: None,
+                limit: Some(1),
+                window: Some(60),
+                reason_code: Some(ReasonCode::new("project_quota0")),
+                mode: QuotaMode::FixedWindow,
+                burst: None,
+                unit: QuotaUnit::Items,
+            },
+            Quota {
+                id: Some("q1".to_owned()),
+                categories: DataCategories::new(),
+                scope: QuotaScope::Organization,
+                scope_id: None,
+                limit: Some(2),
+                window: Some(60),
+                reason_code: Some(ReasonCode::new("project_quota1")),
+                mode: QuotaMode::FixedWindow,
+                burst: None,
+                unit: QuotaUnit::Items,
+            },
+        ];
+
+        let rate_limiter = LocalRateLimiter::new();
+        let timestamp = UnixTimestamp::from_secs(123_123_123);
+
+        assert!(rate_limiter
+            .is_rate_limited_at(quotas, &scoping(), timestamp)
+            .is_ok());
+
+        for _ in 0..3 {
+            // The first quota rejects, which must not count against the second quota.
+            let rate_limits = rate_limiter.is_rate_limited_at(quotas, &scoping(), timestamp);
+            assert_eq!(reason_codes(&rate_limits), vec!["project_quota0"]);
+        }
+    }
+
+    fn mode_quota(mode: QuotaMode, burst: Option<u32>) -> Quota {
+        Quota {
+            id: Some("m".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(10),
+            window: Some(60),
+            reason_code: Some(ReasonCode::new("mode")),
+            mode,
+            burst,
+            unit: QuotaUnit::Items,
+        }
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let quotas = &[mode_quota(QuotaMode::SlidingWindow, None)];
+        let rate_limiter = LocalRateLimiter::new();
+
+        // The organization id shifts windows by 42 seconds. Consume the entire limit at the end of
+        // a window.
+        let end_of_window = UnixTimestamp::from_secs(60 * 1000 + 42 + 59);
+        for _ in 0..10 {
+            assert!(rate_limiter
+                .is_rate_limited_at(quotas, &scoping(), end_of_window)
+                .is_ok());
+        }
+
+        // Right after the window boundary, a fixed window would accept another 10 items. Since
+        // the previous window still fully overlaps, the sliding window rejects.
+        let start_of_window = UnixTimestamp::from_secs(60 * 1001 + 42);
+        let rate_limits = rate_limiter.is_rate_limited_at(quotas, &scoping(), start_of_window);
+        assert_eq!(reason_codes(&rate_limits), vec!["mode"]);
+
+        // Half-way through the window, half of the previous window's items count.
+        let mid_window = UnixTimestamp::from_secs(60 * 1001 + 42 + 30);
+        for _ in 0..5 {
+            assert!(rate_limiter
+                .is_rate_limited_at(quotas, &scoping(), mid_window)
+                .is_ok());
+        }
+        let rate_limits = rate_limiter.is_rate_limited_at(quotas, &scoping(), mid_window);
+        assert_eq!(reason_codes(&rate_limits), vec!["mode"]);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        // Refills 10 tokens per minute, that is one token every 6 seconds.
+        let quotas = &[mode_quota(QuotaMode::TokenBucket, Some(3))];
+        let rate_limiter = LocalRateLimiter::new();
+        let timestamp = UnixTimestamp::from_secs(123_123_123);
+
+        // The burst limits how many items can be ingested at once.
+        for _ in 0..3 {
+            assert!(rate_limiter
+                .is_rate_limited_at(quotas, &scoping(), timestamp)
+                .is_ok());
+        }
+        let rate_limits = rate_limiter.is_rate_limited_at(quotas, &scoping(), timestamp);
+        assert_eq!(reason_codes(&rate_limits), vec!["mode"]);
+
+        // After six seconds, exactly one token has been refilled.
+        let later = UnixTimestamp::from_secs(123_123_123 + 6);
+        assert!(rate_limiter
+            .is_rate_limited_at(quotas, &scoping(), later)
+            .is_ok());
+        let rate_limits = rate_limiter.is_rate_limited_at(quotas, &scoping(), later);
+        assert_eq!(reason_codes(&rate_limits), vec!["mode"]);
+    }
+
+    #[test]
+    fn test_refund() {
+        let quotas = &[mode_quota(QuotaMode::FixedWindow, None)];
+        let rate_limiter = LocalRateLimiter::new();
+        let timestamp = UnixTimestamp::from_secs(123_123_123);
+
+        for _ in 0..10 {
+            assert!(rate_limiter
+                .is_rate_limited_at(quotas, &scoping(), timestamp)
+                .is_ok());
+        }
+
+        let rate_limits = rate_limiter.is_rate_limited_at(quotas, &scoping(), timestamp);
+        assert_eq!(reason_codes(&rate_limits), vec!["mode"]);
+
+        rate_limiter.refund(quotas, &scoping(), timestamp);
+        assert!(rate_limiter
+            .is_rate_limited_at(quotas, &scoping(), timestamp)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let quotas = &[Quota {
+            limit: Some(1000),
+            unit: QuotaUnit::Bytes,
+            ..mode_quota(QuotaMode::FixedWindow, None)
+        }];
+        let rate_limiter = LocalRateLimiter::new();
+        let timestamp = UnixTimestamp::from_secs(123_123_123);
+
+        let scoping = ItemScoping {
+            bytes: 600,
+            ..scoping()
+        };
+
+        assert!(rate_limiter
+            .is_rate_limited_at(quotas, &scoping, timestamp)
+            .is_ok());
+
+        // Another 600 bytes exceed the limit, even though only a single item has been counted.
+        let rate_limits = rate_limiter.is_rate_limited_at(quotas, &scoping, timestamp);
+        assert_eq!(reason_codes(&rate_limits), vec!["mode"]);
+
+        // Smaller items still fit into the remaining 400 bytes.
+        let small = ItemScoping {
+            bytes: 400,
+            ..scoping
+        };
+        assert!(rate_limiter
+            .is_rate_limited_at(quotas, &small, timestamp)
+            .is_ok());
+    }
+}
@@ -4,10 +4,15 @@ use std::sync::Arc;
 use failure::Fail;
 
 use relay_common::UnixTimestamp;
-use relay_redis::{redis::Script, RedisError, RedisPool};
+use relay_redis::{
+    redis::{self, Script},
+    RedisError, RedisPool,
+};
 use sentry::protocol::value;
 
-use crate::types::{ItemScoping, Quota, QuotaScope, RateLimit, RateLimits, RetryAfter};
+use crate::types::{
+    ItemScoping, Quota, QuotaMode, QuotaScope, QuotaUnit, RateLimit, RateLimits, RetryAfter,
+};
 
 /// The `grace` period allows accomodating for clock drift in TTL
 /// calculation since the clock on the Redis instance used to store quota
@@ -68,7 +73,11 @@ impl<'a> RedisQuota<'a> {
     fn new(quota: &'a Quota, scoping: &'a ItemScoping, timestamp: UnixTimestamp) -> Option<Self> {
         // These fields indicate that we *can* track this quota.
         let prefix = quota.id.as_deref()?;
-        let window = quota.window?;
+        let window = quota.window.filter(|window| *window > 0)?;
+
+        if quota.mode == QuotaMode::Unknown || quota.unit == QuotaUnit::Unknown {
+            return None;
+        }
 
         Some(Self {
             quota,
@@ -92,13 +101,78 @@ impl<'a> RedisQuota<'a> {
         (self.timestamp.as_secs() - self.shift()) / self.window
     }
 
+    fn window_start(&self, slot: u64) -> u64 {
+        slot * self.window + self.shift()
+    }
+
+    /// Returns the mode-specific parameter passed to the Lua script.
+    ///
+    /// For sliding windows, this is the share of the previous window that overlaps with a window
+    /// ending now. For token buckets, this is the refill rate in tokens per second.
+    fn param(&self) -> f64 {
+        match self.mode {
+            QuotaMode::SlidingWindow => {
+                let elapsed = self.timestamp.as_secs() - self.window_start(self.slot());
+                1.0 - elapsed as f64 / self.window as f64
+            }
+            QuotaMode::TokenBucket => self.refill_rate().unwrap_or_default(),
+            _ => 0.0,
+        }
+    }
+
+    /// Returns the limit value for the Lua script.
+    ///
+    /// For token buckets, this is the capacity of the bucket including bursts.
+    fn script_limit(&self) -> i64 {
+        match self.mode {
+            QuotaMode::TokenBucket if self.limit.is_some() => self
+                .bucket_capacity()
+                .map_or(-1, |capacity| capacity as i64),
+            _ => self.limit(),
+        }
+    }
+
     fn expiry(&self) -> UnixTimestamp {
-        let next_slot = self.slot() + 1;
-        let next_start = next_slot * self.window + self.shift();
-        UnixTimestamp::from_secs(next_start + GRACE)
+        let expiry = match self.mode {
+            // The current window is needed as previous window until the end of the next window.
+            QuotaMode::SlidingWindow => self.window_start(self.slot() + 2),
+            // After this time, the bucket is full again. This is the same as a missing bucket.
+            QuotaMode::TokenBucket => {
+                let capacity = self.bucket_capacity().unwrap_or_default() as f64;
+                let rate = self.refill_rate().unwrap_or(1.0);
+                self.timestamp.as_secs() + (capacity / rate).ceil() as u64
+            }
+            _ => self.window_start(self.slot() + 1),
+        };
+
+        UnixTimestamp::from_secs(expiry + GRACE)
+    }
+
+    /// Returns the number of seconds until this quota accepts items again after a rejection.
+    fn retry_after_secs(&self) -> u64 {
+        match self.mode {
+            // Approximately the time it takes to refill a single token.
+            QuotaMode::TokenBucket => match self.refill_rate() {
+                Some(rate) => (1.0 / rate).ceil() as u64,
+                None => self.window,
+            },
+            _ => self.window_start(self.slot() + 1) + GRACE - self.timestamp.as_secs(),
+        }
     }
 
     fn key(&self) -> String {
+        format!("{}:{}", self.key_prefix(), self.slot())
+    }
+
+    fn previous_key(&self) -> String {
+        format!("{}:{}", self.key_prefix(), self.slot().saturating_sub(1))
+    }
+
+    fn bucket_key(&self) -> String {
+        format!("{}:bucket", self.key_prefix())
+    }
+
+    fn key_prefix(&self) -> String {
         // The subscope id is only formatted into the key if the quota is not organization-scoped.
         // The organization id is always included.
         let subscope = match self.quota.scope {
@@ -107,11 +181,10 @@ impl<'a> RedisQuota<'a> {
         };
 
         format!(
-            "quota:{id}{{{org}}}{subscope}:{slot}",
+            "quota:{id}{{{org}}}{subscope}",
             id = self.prefix,
             org = self.scoping.organization_id,
             subscope = OptionalDisplay(subscope),
-            slot = self.slot(),
         )
     }
 }
@@ -177,6 +250,7 @@ impl RateLimiter {
         let timestamp = UnixTimestamp::now();
 
         let mut invocation = self.script.prepare_invoke();
+        invocation.arg(timestamp.as_secs());
         let mut tracked_quotas = Vec::new();
         let mut rate_limits = RateLimits::new();
 
@@ -188,17 +262,38 @@ impl RateLimiter {
                 // increment any keys, as one quota has reached capacity (this is how regular quotas
                 // behave as well).
                 let retry_after = self.retry_after(REJECT_ALL_SECS);
-                rate_limits.add(RateLimit::from_quota(quota, scoping, retry_after));
+                if let Some(rate_limit) = RateLimit::from_quota(quota, scoping, retry_after) {
+                    rate_limits.add(rate_limit);
+                }
             } else if let Some(quota) = RedisQuota::new(quota, scoping, timestamp) {
                 // Remaining quotas are expected to be trackable in Redis.
-                let key = quota.key();
-                let refund_key = get_refunded_quota_key(&key);
-
-                invocation.key(key);
-                invocation.key(refund_key);
-
-                invocation.arg(quota.limit());
+                match quota.mode {
+                    QuotaMode::TokenBucket => {
+                        invocation.key(quota.bucket_key());
+                    }
+                    QuotaMode::SlidingWindow => {
+                        let key = quota.key();
+                        let previous_key = quota.previous_key();
+                        let refund_key = get_refunded_quota_key(&key);
+                        let previous_refund_key = get_refunded_quota_key(&previous_key);
+                        invocation.key(key);
+                        invocation.key(refund_key);
+                        invocation.key(previous_key);
+                        invocation.key(previous_refund_key);
+                    }
+                    _ => {
+                        let key = quota.key();
+                        let refund_key = get_refunded_quota_key(&key);
+                        invocation.key(key);
+                        invocation.key(refund_key);
+                    }
+                }
+
+                invocation.arg(quota.mode.name());
+                invocation.arg(quota.script_limit());
                 invocation.arg(quota.expiry().as_secs());
+                invocation.arg(quota.param());
+                invocation.arg(quota.quantity(scoping));
 
                 tracked_quotas.push(quota);
             } else {
@@ -225,14 +320,83 @@ impl RateLimiter {
 
         for (quota, is_rejected) in tracked_quotas.iter().zip(rejections) {
             if is_rejected {
-                let retry_after = self.retry_after((quota.expiry() - timestamp).as_secs());
-                rate_limits.add(RateLimit::from_quota(&*quota, scoping, retry_after));
+                let retry_after = self.retry_after(quota.retry_after_secs());
+                if let Some(rate_limit) = RateLimit::from_quota(&*quota, scoping, retry_after) {
+                    rate_limits.add(rate_limit);
+                }
             }
         }
 
         Ok(rate_limits)
     }
 
+    /// Refunds an item that has been counted against the given quotas.
+    ///
+    /// This reverts the effect of a call to `is_rate_limited` that did not return any rate limits,
+    /// in case the item is dropped after it has been counted. The `timestamp` must be the time at
+    /// which the item was counted, so that the refund applies to the same window.
+    ///
+    /// Refunds are stored in separate counters which are subtracted when checking quotas. In
+    /// `TokenBucket` mode, the token is put back into the bucket instead.
+    pub fn refund(
+        &self,
+        quotas: &[Quota],
+        scoping: &ItemScoping,
+        timestamp: UnixTimestamp,
+    ) -> Result<(), RateLimitingError> {
+        let mut commands = Vec::new();
+
+        for quota in quotas {
+            // Zero-sized quotas never count items, and quotas that cannot be tracked in Redis have
+            // been skipped by `is_rate_limited`.
+            if !quota.matches(scoping) || quota.limit == Some(0) {
+                continue;
+            }
+
+            let quota = match RedisQuota::new(quota, scoping, timestamp) {
+                Some(quota) => quota,
+                None => continue,
+            };
+
+            let key = match quota.mode {
+                // Unlimited buckets never run out of tokens, so there is nothing to refund.
+                QuotaMode::TokenBucket if quota.limit.is_none() => continue,
+                QuotaMode::TokenBucket => {
+                    let key = quota.bucket_key();
+                    let mut command = redis::cmd("HINCRBYFLOAT");
+                    command.arg(&key).arg("tokens").arg(quota.quantity(scoping));
+                    commands.push(command);
+                    key
+                }
+                _ => {
+                    let key = get_refunded_quota_key(&quota.key());
+                    let mut command = redis::cmd("INCRBY");
+                    command.arg(&key).arg(quota.quantity(scoping));
+                    commands.push(command);
+                    key
+                }
+            };
+
+            let mut command = redis::cmd("EXPIREAT");
+            command.arg(key).arg(quota.expiry().as_secs());
+            commands.push(command);
+        }
+
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        let mut client = self.pool.client().map_err(RateLimitingError::Redis)?;
+        for command in commands {
+            command
+                .query::<()>(&mut client.connection())
+                .map_err(RedisError::Redis)
+                .map_err(RateLimitingError::Redis)?;
+        }
+
+        Ok(())
+    }
+
     /// Creates a rate limit bounded by `max_limit`.
     fn retry_after(&self, mut seconds: u64) -> RetryAfter {
         if let Some(max_limit) = self.max_limit {
@@ -274,6 +438,9 @@ mod tests {
                 limit: Some(0),
                 window: None,
                 reason_code: Some(ReasonCode::new("get_lost")),
+                mode: QuotaMode::FixedWindow,
+                burst: None,
+                unit: QuotaUnit::Items,
             },
             Quota {
                 id: Some("42".to_owned()),
@@ -283,6 +450,9 @@ mod tests {
                 limit: None,
                 window: Some(42),
                 reason_code: Some(ReasonCode::new("unlimited")),
+                mode: QuotaMode::FixedWindow,
+                burst: None,
+                unit: QuotaUnit::Items,
             },
         ];
 
@@ -292,6 +462,11 @@ mod tests {
             project_id: ProjectId::new(43),
             public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(44),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         };
 
         let rate_limits: Vec<RateLimit> = RATE_LIMITER
@@ -321,6 +496,9 @@ mod tests {
             limit: Some(5),
             window: Some(60),
             reason_code: Some(ReasonCode::new("get_lost")),
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         }];
 
         let scoping = ItemScoping {
@@ -329,6 +507,11 @@ mod tests {
             project_id: ProjectId::new(43),
             public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(44),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         };
 
         for i in 0..10 {
@@ -362,6 +545,11 @@ mod tests {
             project_id: ProjectId::new(43),
             public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(44),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         };
 
         let rate_limits: Vec<RateLimit> = RATE_LIMITER
@@ -384,6 +572,9 @@ mod tests {
                 limit: None,
                 window: Some(1),
                 reason_code: Some(ReasonCode::new("project_quota0")),
+                mode: QuotaMode::FixedWindow,
+                burst: None,
+                unit: QuotaUnit::Items,
             },
             Quota {
                 id: Some("q1".to_string()),
@@ -393,6 +584,9 @@ mod tests {
                 limit: Some(1),
                 window: Some(1),
                 reason_code: Some(ReasonCode::new("project_quota1")),
+                mode: QuotaMode::FixedWindow,
+                burst: None,
+                unit: QuotaUnit::Items,
             },
         ];
 
@@ -402,6 +596,11 @@ mod tests {
             project_id: ProjectId::new(43),
             public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(44),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         };
 
         for i in 0..1 {
@@ -437,6 +636,9 @@ mod tests {
             window: Some(2),
             limit: Some(0),
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
         let scoping = ItemScoping {
@@ -445,6 +647,11 @@ mod tests {
             project_id: ProjectId::new(42),
             public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(4711),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         };
 
         let timestamp = UnixTimestamp::from_secs(123_123_123);
@@ -462,6 +669,9 @@ mod tests {
             window: Some(10),
             limit: Some(0),
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
         let scoping = ItemScoping {
@@ -470,6 +680,11 @@ mod tests {
             project_id: ProjectId::new(42),
             public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(4711),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         };
 
         let timestamp = UnixTimestamp::from_secs(234_531);
@@ -505,10 +720,17 @@ mod tests {
             .key(&r_foo)
             .key(&bar)
             .key(&r_bar)
+            .arg(now)
+            .arg("fixed_window")
             .arg(1)
             .arg(now + 60)
+            .arg(0)
+            .arg(1)
+            .arg("fixed_window")
             .arg(2)
-            .arg(now + 120);
+            .arg(now + 120)
+            .arg(0)
+            .arg(1);
 
         // The item should not be rate limited by either key.
         assert_eq!(
@@ -549,7 +771,15 @@ mod tests {
         let () = conn.set(&apple, 5).unwrap();
 
         let mut invocation = script.prepare_invoke();
-        invocation.key(&orange).key(&baz).arg(1).arg(now + 60);
+        invocation
+            .key(&orange)
+            .key(&baz)
+            .arg(now)
+            .arg("fixed_window")
+            .arg(1)
+            .arg(now + 60)
+            .arg(0)
+            .arg(1);
 
         // increment
         assert_eq!(
@@ -564,7 +794,15 @@ mod tests {
         );
 
         let mut invocation = script.prepare_invoke();
-        invocation.key(&orange).key(&apple).arg(1).arg(now + 60);
+        invocation
+            .key(&orange)
+            .key(&apple)
+            .arg(now)
+            .arg("fixed_window")
+            .arg(1)
+            .arg(now + 60)
+            .arg(0)
+            .arg(1);
 
         // test that refund key is used
         assert_eq!(
@@ -572,4 +810,188 @@ mod tests {
             vec![false]
         );
     }
+
+    #[test]
+    fn test_get_redis_key_modes() {
+        let quota = Quota {
+            id: Some("foo".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            window: Some(10),
+            limit: Some(5),
+            reason_code: None,
+            mode: QuotaMode::SlidingWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
+        };
+
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            organization_id: 69420,
+            project_id: ProjectId::new(42),
+            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
+            key_id: Some(4711),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
+        };
+
+        // The organization id shifts windows by 0 seconds, so this is 4 seconds into the window.
+        let timestamp = UnixTimestamp::from_secs(234_534);
+        let redis_quota = RedisQuota::new(&quota, &scoping, timestamp).unwrap();
+        assert_eq!(redis_quota.key(), "quota:foo{69420}:23453");
+        assert_eq!(redis_quota.previous_key(), "quota:foo{69420}:23452");
+        assert_eq!(redis_quota.bucket_key(), "quota:foo{69420}:bucket");
+        assert!((redis_quota.param() - 0.6).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_is_rate_limited_script_token_bucket() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap();
+
+        let mut client = RATE_LIMITER.pool.client().expect("get client");
+        let mut conn = client.connection();
+
+        let bucket = format!("bucket___{}", now);
+        let script = load_lua_script();
+
+        // A bucket with two tokens that refills one token per minute.
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(&bucket)
+            .arg(now)
+            .arg("token_bucket")
+            .arg(2)
+            .arg(now + 120)
+            .arg(1.0 / 60.0)
+            .arg(1);
+
+        for _ in 0..2 {
+            assert_eq!(
+                invocation.invoke::<Vec<bool>>(&mut conn).unwrap(),
+                vec![false]
+            );
+        }
+
+        assert_eq!(
+            invocation.invoke::<Vec<bool>>(&mut conn).unwrap(),
+            vec![true]
+        );
+
+        // One minute later, a single token has been refilled.
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(&bucket)
+            .arg(now + 60)
+            .arg("token_bucket")
+            .arg(2)
+            .arg(now + 180)
+            .arg(1.0 / 60.0)
+            .arg(1);
+
+        assert_eq!(
+            invocation.invoke::<Vec<bool>>(&mut conn).unwrap(),
+            vec![false]
+        );
+        assert_eq!(
+            invocation.invoke::<Vec<bool>>(&mut conn).unwrap(),
+            vec![true]
+        );
+    }
+
+    #[test]
+    fn test_refund() {
+        let quotas = &[Quota {
+            id: Some(format!("test_refund_{:?}", SystemTime::now())),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(1),
+            window: Some(60),
+            reason_code: Some(ReasonCode::new("get_lost")),
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
+        }];
+
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            organization_id: 42,
+            project_id: ProjectId::new(43),
+            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
+            key_id: Some(44),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
+        };
+
+        let timestamp = UnixTimestamp::now();
+        let rate_limits = RATE_LIMITER
+            .is_rate_limited(quotas, &scoping)
+            .expect("rate limiting failed");
+        assert!(rate_limits.is_ok());
+
+        let rate_limits = RATE_LIMITER
+            .is_rate_limited(quotas, &scoping)
+            .expect("rate limiting failed");
+        assert!(rate_limits.is_limited());
+
+        // After the refund, the quota has capacity for another item.
+        RATE_LIMITER
+            .refund(quotas, &scoping, timestamp)
+            .expect("refund failed");
+
+        let rate_limits = RATE_LIMITER
+            .is_rate_limited(quotas, &scoping)
+            .expect("rate limiting failed");
+        assert!(rate_limits.is_ok());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let quotas = &[Quota {
+            id: Some(format!("test_bytes_{:?}", SystemTime::now())),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(1000),
+            window: Some(60),
+            reason_code: Some(ReasonCode::new("get_lost")),
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Bytes,
+        }];
+
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            organization_id: 42,
+            project_id: ProjectId::new(43),
+            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
+            key_id: Some(44),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 600,
+        };
+
+        let rate_limits = RATE_LIMITER
+            .is_rate_limited(quotas, &scoping)
+            .expect("rate limiting failed");
+        assert!(rate_limits.is_ok());
+
+        // The second item exceeds the byte limit.
+        let rate_limits = RATE_LIMITER
+            .is_rate_limited(quotas, &scoping)
+            .expect("rate limiting failed");
+        assert!(rate_limits.is_limited());
+    }
 }
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use smallvec::smallvec;
+
+use crate::types::{DataCategory, Quota, QuotaMode, QuotaScope, QuotaUnit, ReasonCode};
+
+/// The quota identifier used to count events for spike protection.
+const SPIKE_PROTECTION_ID: &str = "issue_spike";
+
+/// The reason code reported when an issue exceeds its spike protection limit.
+pub const SPIKE_PROTECTION_REASON: &str = "issue_spike_protection";
+
+/// Configuration for per-issue spike protection.
+///
+/// Spike protection limits the number of events that are accepted for each issue of a project
+/// within a time window. Issues are identified by the grouping hash of their events, which honors
+/// custom fingerprints. This prevents a single issue, such as a crash loop, from exhausting the
+/// quota of the entire organization.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpikeProtectionConfig {
+    /// The maximum number of events accepted for a single issue within the window.
+    pub limit: u32,
+
+    /// The time window in seconds to enforce the limit in.
+    pub window: u64,
+}
+
+impl SpikeProtectionConfig {
+    /// Returns the quota enforcing this spike protection.
+    ///
+    /// The quota is counted separately for every issue and applies to all data categories that
+    /// create issues.
+    pub fn quota(&self) -> Quota {
+        Quota {
+            id: Some(SPIKE_PROTECTION_ID.to_owned()),
+            categories: smallvec![
+                DataCategory::Default,
+                DataCategory::Error,
+                DataCategory::Security,
+            ],
+            scope: QuotaScope::Issue,
+            scope_id: None,
+            limit: Some(self.limit),
+            window: Some(self.window),
+            reason_code: Some(ReasonCode::new(SPIKE_PROTECTION_REASON)),
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use relay_common::ProjectId;
+
+    use crate::local::LocalRateLimiter;
+    use crate::types::ItemScoping;
+
+    fn scoping() -> ItemScoping {
+        ItemScoping {
+            category: DataCategory::Error,
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
+            key_id: Some(17),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_spike_protection() {
+        let config: SpikeProtectionConfig =
+            serde_json::from_str(r#"{"limit": 100, "window": 60}"#).unwrap();
+
+        let quota = config.quota();
+        assert_eq!(quota.scope, QuotaScope::Issue);
+        assert_eq!(quota.limit, Some(100));
+        assert_eq!(quota.window, Some(60));
+    }
+
+    #[test]
+    fn test_spike_protection_per_issue() {
+        let quotas = &[SpikeProtectionConfig {
+            limit: 1,
+            window: 60,
+        }
+        .quota()];
+
+        let rate_limiter = LocalRateLimiter::new();
+        let first = ItemScoping {
+            issue: Some("c4ca4238a0b923820dcc509a6f75849b".to_owned()),
+            ..scoping()
+        };
+        let second = ItemScoping {
+            issue: Some("eccbc87e4b5ce2fe28308fd9f2a7baf3".to_owned()),
+            ..scoping()
+        };
+
+        assert!(!rate_limiter.is_rate_limited(quotas, &first).is_limited());
+        assert!(!rate_limiter.is_rate_limited(quotas, &second).is_limited());
+
+        let rate_limits = rate_limiter.is_rate_limited(quotas, &first);
+        let rate_limit = rate_limits.iter().next().unwrap();
+        assert_eq!(
+            rate_limit.reason_code,
+            Some(ReasonCode::new(SPIKE_PROTECTION_REASON))
+        );
+
+        // Events without a grouping hash and transactions are never limited.
+        assert!(!rate_limiter
+            .is_rate_limited(quotas, &scoping())
+            .is_limited());
+        assert!(!rate_limiter
+            .is_rate_limited(
+                quotas,
+                &ItemScoping {
+                    category: DataCategory::Transaction,
+                    ..first
+                }
+            )
+            .is_limited());
+    }
+}
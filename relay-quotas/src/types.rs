@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
@@ -26,15 +27,47 @@ pub struct ItemScoping {
 
     /// The public key's internal id.
     pub key_id: Option<u64>,
+
+    /// The release of the item, if known.
+    pub release: Option<String>,
+
+    /// The environment of the item, if known.
+    pub environment: Option<String>,
+
+    /// A hash of the user identifier of the item, if known.
+    pub user: Option<String>,
+
+    /// The grouping hash of the item's issue, if known.
+    pub issue: Option<String>,
+
+    /// The size of the item's payload in bytes.
+    pub bytes: u64,
 }
 
 impl ItemScoping {
+    /// Returns the quantity of this item in the given unit.
+    ///
+    /// In `Items`, this is always `1`.
+    pub fn quantity(&self, unit: QuotaUnit) -> u64 {
+        match unit {
+            QuotaUnit::Bytes => self.bytes,
+            _ => 1,
+        }
+    }
+
     /// Returns the identifier of the given scope.
-    pub fn scope_id(&self, scope: QuotaScope) -> Option<u64> {
+    ///
+    /// Numeric scopes are formatted as decimal strings. Returns `None` if the item does not carry
+    /// information for this scope.
+    pub fn scope_id(&self, scope: QuotaScope) -> Option<Cow<'_, str>> {
         match scope {
-            QuotaScope::Organization => Some(self.organization_id),
-            QuotaScope::Project => Some(self.project_id.value()),
-            QuotaScope::Key => self.key_id,
+            QuotaScope::Organization => Some(self.organization_id.to_string().into()),
+            QuotaScope::Project => Some(self.project_id.value().to_string().into()),
+            QuotaScope::Key => self.key_id.map(|key_id| key_id.to_string().into()),
+            QuotaScope::Release => self.release.as_deref().map(Cow::Borrowed),
+            QuotaScope::Environment => self.environment.as_deref().map(Cow::Borrowed),
+            QuotaScope::User => self.user.as_deref().map(Cow::Borrowed),
+            QuotaScope::Issue => self.issue.as_deref().map(Cow::Borrowed),
             QuotaScope::Unknown => None,
         }
     }
@@ -59,7 +92,7 @@ pub enum DataCategory {
     Error,
     /// Transaction events.
     Transaction,
-    /// Events with an event type of `csp`, `hpkp`, `expectct` and `expectstaple`.
+    /// Events with an event type of `csp`, `hpkp`, `expectct`, `expectstaple` and `nel`.
     Security,
     /// An attachment. Quantity is the size of the attachment in bytes.
     Attachment,
@@ -139,6 +172,26 @@ pub enum QuotaScope {
     /// This is a sub-scope of `Project`.
     Key,
 
+    /// A release of a project.
+    ///
+    /// This is a sub-scope of `Project`.
+    Release,
+
+    /// An environment of a project.
+    ///
+    /// This is a sub-scope of `Project`.
+    Environment,
+
+    /// A user affected by an event, identified by the hash of their user identifier.
+    ///
+    /// This is a sub-scope of `Project`.
+    User,
+
+    /// An issue, identified by the grouping hash of its events.
+    ///
+    /// This is a sub-scope of `Project`.
+    Issue,
+
     /// Any other scope that is not known by this Relay.
     #[serde(other)]
     Unknown,
@@ -151,6 +204,10 @@ impl QuotaScope {
             "organization" => Self::Organization,
             "project" => Self::Project,
             "key" => Self::Key,
+            "release" => Self::Release,
+            "environment" => Self::Environment,
+            "user" => Self::User,
+            "issue" => Self::Issue,
             _ => Self::Unknown,
         }
     }
@@ -161,9 +218,22 @@ impl QuotaScope {
             Self::Key => "key",
             Self::Project => "project",
             Self::Organization => "organization",
+            Self::Release => "release",
+            Self::Environment => "environment",
+            Self::User => "user",
+            Self::Issue => "issue",
             Self::Unknown => "unknown",
         }
     }
+
+    /// Returns `true` if this scope is derived from the contents of an item rather than from the
+    /// project and key it was sent to.
+    pub fn is_item_scope(self) -> bool {
+        match self {
+            Self::Release | Self::Environment | Self::User | Self::Issue => true,
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Display for QuotaScope {
@@ -191,9 +261,8 @@ pub struct ReasonCode(String);
 impl ReasonCode {
     /// Creates a new reason code from a string.
     ///
-    /// This method is only to be used by tests. Reason codes should only be deserialized from
-    /// quotas, but never constructed manually.
-    #[cfg(test)]
+    /// Reason codes should only be deserialized from quotas or parsed from rate limits reported by
+    /// the upstream, but never constructed manually.
     pub fn new<S: Into<String>>(code: S) -> Self {
         Self(code.into())
     }
@@ -204,6 +273,105 @@ impl ReasonCode {
     }
 }
 
+/// The unit in which consumption of a quota is counted.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaUnit {
+    /// Counts the number of items.
+    Items,
+
+    /// Counts the size of item payloads in bytes.
+    Bytes,
+
+    /// Any other unit that is not known by this Relay.
+    #[serde(other)]
+    Unknown,
+}
+
+impl QuotaUnit {
+    /// Returns the canonical name of this unit.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Items => "items",
+            Self::Bytes => "bytes",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Returns `true` if this is the default unit counting items.
+    pub fn is_items(&self) -> bool {
+        *self == Self::Items
+    }
+}
+
+impl Default for QuotaUnit {
+    fn default() -> Self {
+        Self::Items
+    }
+}
+
+impl fmt::Display for QuotaUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The algorithm used to count consumption of a quota.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaMode {
+    /// Counts items in fixed windows.
+    ///
+    /// Windows are aligned to the organization, so that all quotas of an organization reset at the
+    /// same time. This allows up to twice the limit within one window-sized interval that crosses a
+    /// window boundary.
+    FixedWindow,
+
+    /// Counts items in a window that ends at the time of ingestion.
+    ///
+    /// The count is approximated from the current and the previous fixed window, assuming that
+    /// items in the previous window were evenly distributed.
+    SlidingWindow,
+
+    /// Refills a bucket of tokens at a rate of `limit` per `window`, consuming a token per item.
+    ///
+    /// The bucket holds at most `burst` tokens, which defaults to `limit`.
+    TokenBucket,
+
+    /// Any other mode that is not known by this Relay.
+    #[serde(other)]
+    Unknown,
+}
+
+impl QuotaMode {
+    /// Returns the canonical name of this mode.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FixedWindow => "fixed_window",
+            Self::SlidingWindow => "sliding_window",
+            Self::TokenBucket => "token_bucket",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Returns `true` if this is the default fixed window mode.
+    pub fn is_fixed_window(&self) -> bool {
+        *self == Self::FixedWindow
+    }
+}
+
+impl Default for QuotaMode {
+    fn default() -> Self {
+        Self::FixedWindow
+    }
+}
+
+impl fmt::Display for QuotaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 /// Configuration for a data ingestion quota (rate limiting).
 ///
 /// Sentry applies multiple quotas to incoming data before accepting it, some of which can be
@@ -247,6 +415,22 @@ pub struct Quota {
     /// `limit=None`, since unlimited quotas can never be exceeded.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub reason_code: Option<ReasonCode>,
+
+    /// The algorithm used to count consumption of this quota. Defaults to fixed windows.
+    #[serde(default, skip_serializing_if = "QuotaMode::is_fixed_window")]
+    pub mode: QuotaMode,
+
+    /// The maximum number of items that can be accepted at once in `TokenBucket` mode. Defaults to
+    /// `limit`, and is ignored in all other modes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+
+    /// The unit in which `limit` and `burst` are specified. Defaults to counting items.
+    ///
+    /// Quotas in `Bytes` count the payload size of items, which allows to throttle large payloads
+    /// separately from the number of items.
+    #[serde(default, skip_serializing_if = "QuotaUnit::is_items")]
+    pub unit: QuotaUnit,
 }
 
 impl Quota {
@@ -254,32 +438,65 @@ impl Quota {
     ///
     /// This quota matches, if:
     ///  - there is no `scope_id` constraint
-    ///  - the `scope_id` constraint is not numeric
     ///  - the scope identifier matches the one from ascoping and the scope is known
+    ///
+    /// For numeric scopes, a `scope_id` constraint that is not numeric never matches.
     fn matches_scope(&self, scoping: &ItemScoping) -> bool {
         // Check for a scope identifier constraint. If there is no constraint, this means that the
         // quota matches any scope. In case the scope is unknown, it will be coerced to the most
         // specific scope later.
         let scope_id = match self.scope_id {
             Some(ref scope_id) => scope_id,
-            None => return true,
+            // Quotas for release, environment or user are counted separately for every instance
+            // of the scope, so they cannot apply to items that do not carry the scope.
+            None => return !self.scope.is_item_scope() || scoping.scope_id(self.scope).is_some(),
         };
 
-        // Check if the scope identifier in the quota is parseable. If not, this means we cannot
-        // fulfill the constraint, so the quota does not match.
-        let parsed = match scope_id.parse::<u64>() {
-            Ok(parsed) => parsed,
-            Err(_) => return false,
+        // At this stage, require that the scope is known since we have to fulfill the constraint.
+        let item_scope_id = match scoping.scope_id(self.scope) {
+            Some(item_scope_id) => item_scope_id,
+            None => return false,
         };
 
-        // At this stage, require that the scope is known since we have to fulfill the constraint.
-        scoping.scope_id(self.scope) == Some(parsed)
+        match self.scope {
+            // Check if the scope identifier in the quota is parseable. If not, this means we cannot
+            // fulfill the constraint, so the quota does not match.
+            QuotaScope::Organization | QuotaScope::Project | QuotaScope::Key => {
+                match scope_id.parse::<u64>() {
+                    Ok(parsed) => item_scope_id.parse::<u64>() == Ok(parsed),
+                    Err(_) => false,
+                }
+            }
+            _ => item_scope_id == scope_id.as_str(),
+        }
     }
 
     /// Checks whether the quota's constraints match the current item.
     pub fn matches(&self, scoping: &ItemScoping) -> bool {
         self.matches_scope(scoping) && scoping.matches_categories(&self.categories)
     }
+
+    /// Returns the quantity of the given item counted against this quota.
+    pub fn quantity(&self, scoping: &ItemScoping) -> u64 {
+        scoping.quantity(self.unit)
+    }
+
+    /// Returns the capacity of the token bucket in `TokenBucket` mode.
+    ///
+    /// This is `burst` if set, or otherwise the `limit`. Returns `None` for unlimited quotas.
+    pub fn bucket_capacity(&self) -> Option<u64> {
+        let limit = self.limit?;
+        Some(u64::from(self.burst.unwrap_or(limit)))
+    }
+
+    /// Returns the number of tokens added to the bucket per second in `TokenBucket` mode.
+    ///
+    /// Returns `None` for unlimited quotas or quotas without a window.
+    pub fn refill_rate(&self) -> Option<f64> {
+        let limit = self.limit?;
+        let window = self.window.filter(|window| *window > 0)?;
+        Some(f64::from(limit) / window as f64)
+    }
 }
 
 /// A monotonic expiration marker for `RateLimit`s.
@@ -383,18 +600,33 @@ pub enum RateLimitScope {
     Project(ProjectId),
     /// A DSN public key.
     Key(String),
+    /// A release of a project.
+    Release(String),
+    /// An environment of a project.
+    Environment(String),
+    /// A user identified by the hash of their user identifier.
+    User(String),
+    /// An issue identified by the grouping hash of its events.
+    Issue(String),
 }
 
 impl RateLimitScope {
     /// Extracts a rate limiting scope from the given item scoping for a specific quota.
-    pub fn for_quota(scoping: &ItemScoping, scope: QuotaScope) -> Self {
-        match scope {
+    ///
+    /// Returns `None` if the item does not carry a value for the scope, such as a release scope
+    /// for an item without a release. Such rate limits cannot apply to the item.
+    pub fn for_quota(scoping: &ItemScoping, scope: QuotaScope) -> Option<Self> {
+        Some(match scope {
             QuotaScope::Organization => RateLimitScope::Organization(scoping.organization_id),
             QuotaScope::Project => RateLimitScope::Project(scoping.project_id),
             QuotaScope::Key => RateLimitScope::Key(scoping.public_key.clone()),
+            QuotaScope::Release => RateLimitScope::Release(scoping.release.clone()?),
+            QuotaScope::Environment => RateLimitScope::Environment(scoping.environment.clone()?),
+            QuotaScope::User => RateLimitScope::User(scoping.user.clone()?),
+            QuotaScope::Issue => RateLimitScope::Issue(scoping.issue.clone()?),
             // For unknown scopes, assume the most specific scope:
             QuotaScope::Unknown => RateLimitScope::Key(scoping.public_key.clone()),
-        }
+        })
     }
 
     /// Returns the canonical name of this scope.
@@ -403,6 +635,10 @@ impl RateLimitScope {
             Self::Key(_) => QuotaScope::Key.name(),
             Self::Project(_) => QuotaScope::Project.name(),
             Self::Organization(_) => QuotaScope::Organization.name(),
+            Self::Release(_) => QuotaScope::Release.name(),
+            Self::Environment(_) => QuotaScope::Environment.name(),
+            Self::User(_) => QuotaScope::User.name(),
+            Self::Issue(_) => QuotaScope::Issue.name(),
         }
     }
 }
@@ -427,13 +663,20 @@ pub struct RateLimit {
 
 impl RateLimit {
     /// Creates a new rate limit for the given `Quota`.
-    pub fn from_quota(quota: &Quota, scoping: &ItemScoping, retry_after: RetryAfter) -> Self {
-        Self {
+    ///
+    /// Returns `None` if the quota's scope cannot be resolved for the item. See
+    /// `RateLimitScope::for_quota`.
+    pub fn from_quota(
+        quota: &Quota,
+        scoping: &ItemScoping,
+        retry_after: RetryAfter,
+    ) -> Option<Self> {
+        Some(Self {
             categories: quota.categories.clone(),
-            scope: RateLimitScope::for_quota(scoping, quota.scope),
+            scope: RateLimitScope::for_quota(scoping, quota.scope)?,
             reason_code: quota.reason_code.clone(),
             retry_after,
-        }
+        })
     }
 
     /// Checks whether the rate limit applies to the given item.
@@ -447,6 +690,12 @@ impl RateLimit {
             RateLimitScope::Organization(org_id) => scoping.organization_id == org_id,
             RateLimitScope::Project(project_id) => scoping.project_id == project_id,
             RateLimitScope::Key(ref key) => scoping.public_key == *key,
+            RateLimitScope::Release(ref release) => scoping.release.as_ref() == Some(release),
+            RateLimitScope::Environment(ref environment) => {
+                scoping.environment.as_ref() == Some(environment)
+            }
+            RateLimitScope::User(ref user) => scoping.user.as_ref() == Some(user),
+            RateLimitScope::Issue(ref issue) => scoping.issue.as_ref() == Some(issue),
         }
     }
 }
@@ -513,6 +762,22 @@ impl RateLimits {
     /// If no limits match, then the returned `RateLimits` instance evalutes `is_ok`. Otherwise, it
     /// contains rate limits that match the given scoping.
     pub fn check(&mut self, scoping: &ItemScoping) -> Self {
+        self.check_with(|limit| limit.matches(scoping))
+    }
+
+    /// Checks whether any rate limits apply to the scope of the given item, regardless of their
+    /// data categories.
+    ///
+    /// This returns all limits that would apply to items of any category sent with the same scope.
+    pub fn check_scope(&mut self, scoping: &ItemScoping) -> Self {
+        self.check_with(|limit| limit.matches_scope(scoping))
+    }
+
+    /// Removes expired rate limits and returns the active ones matching the given predicate.
+    fn check_with<F>(&mut self, predicate: F) -> Self
+    where
+        F: Fn(&RateLimit) -> bool,
+    {
         let mut applied_limits = Self::new();
 
         self.limits.retain(|limit| {
@@ -520,7 +785,7 @@ impl RateLimits {
                 return false;
             }
 
-            if limit.matches(scoping) {
+            if predicate(limit) {
                 applied_limits.add(limit.clone());
             }
 
@@ -601,6 +866,21 @@ mod tests {
     use super::*;
     use smallvec::smallvec;
 
+    fn scoping() -> ItemScoping {
+        ItemScoping {
+            category: DataCategory::Error,
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
+            key_id: Some(17),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
+        }
+    }
+
     #[test]
     fn test_parse_quota_reject_all() {
         let json = r#"{
@@ -768,6 +1048,83 @@ mod tests {
         "###);
     }
 
+    #[test]
+    fn test_parse_quota_token_bucket() {
+        let json = r#"{
+            "id": "o",
+            "limit": 4711,
+            "window": 42,
+            "mode": "token_bucket",
+            "burst": 100
+        }"#;
+
+        let quota = serde_json::from_str::<Quota>(json).expect("parse quota");
+
+        insta::assert_ron_snapshot!(quota, @r###"
+        Quota(
+          id: Some("o"),
+          categories: [],
+          scope: organization,
+          limit: Some(4711),
+          window: Some(42),
+          mode: token_bucket,
+          burst: Some(100),
+        )
+        "###);
+    }
+
+    #[test]
+    fn test_parse_quota_unknown_mode() {
+        let json = r#"{
+            "id": "o",
+            "limit": 4711,
+            "window": 42,
+            "mode": "future"
+        }"#;
+
+        let quota = serde_json::from_str::<Quota>(json).expect("parse quota");
+        assert_eq!(quota.mode, QuotaMode::Unknown);
+    }
+
+    #[test]
+    fn test_parse_quota_bytes() {
+        let json = r#"{
+            "id": "o",
+            "categories": ["attachment"],
+            "limit": 4711,
+            "window": 42,
+            "unit": "bytes"
+        }"#;
+
+        let quota = serde_json::from_str::<Quota>(json).expect("parse quota");
+
+        insta::assert_ron_snapshot!(quota, @r###"
+        Quota(
+          id: Some("o"),
+          categories: [
+            attachment,
+          ],
+          scope: organization,
+          limit: Some(4711),
+          window: Some(42),
+          unit: bytes,
+        )
+        "###);
+    }
+
+    #[test]
+    fn test_parse_quota_unknown_unit() {
+        let json = r#"{
+            "id": "o",
+            "limit": 4711,
+            "window": 42,
+            "unit": "future"
+        }"#;
+
+        let quota = serde_json::from_str::<Quota>(json).expect("parse quota");
+        assert_eq!(quota.unit, QuotaUnit::Unknown);
+    }
+
     #[test]
     fn test_quota_matches_no_categories() {
         let quota = Quota {
@@ -778,15 +1135,12 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
-        assert!(quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
-        }));
+        assert!(quota.matches(&scoping()));
     }
 
     #[test]
@@ -799,15 +1153,12 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
-        assert!(!quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
-        }));
+        assert!(!quota.matches(&scoping()));
     }
 
     #[test]
@@ -820,22 +1171,16 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
-        assert!(quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
-        }));
+        assert!(quota.matches(&scoping()));
 
         assert!(!quota.matches(&ItemScoping {
             category: DataCategory::Transaction,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
+            ..scoping()
         }));
     }
 
@@ -849,15 +1194,12 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
-        assert!(!quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
-        }));
+        assert!(!quota.matches(&scoping()));
     }
 
     #[test]
@@ -870,22 +1212,16 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
-        assert!(quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
-        }));
+        assert!(quota.matches(&scoping()));
 
         assert!(!quota.matches(&ItemScoping {
-            category: DataCategory::Error,
             organization_id: 0,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
+            ..scoping()
         }));
     }
 
@@ -899,22 +1235,16 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
-        assert!(quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
-        }));
+        assert!(quota.matches(&scoping()));
 
         assert!(!quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
             project_id: ProjectId::new(0),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
+            ..scoping()
         }));
     }
 
@@ -928,33 +1258,99 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
         };
 
-        assert!(quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
-            key_id: Some(17),
-        }));
+        assert!(quota.matches(&scoping()));
 
         assert!(!quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(0),
+            ..scoping()
         }));
 
         assert!(!quota.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
+        }));
+    }
+
+    #[test]
+    fn test_quota_matches_release_scope() {
+        let quota = Quota {
+            id: None,
+            categories: DataCategories::new(),
+            scope: QuotaScope::Release,
+            scope_id: Some("1.0.0".to_owned()),
+            limit: None,
+            window: None,
+            reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
+        };
+
+        assert!(quota.matches(&ItemScoping {
+            release: Some("1.0.0".to_owned()),
+            ..scoping()
+        }));
+
+        assert!(!quota.matches(&ItemScoping {
+            release: Some("2.0.0".to_owned()),
+            ..scoping()
         }));
     }
 
+    #[test]
+    fn test_quota_matches_environment_scope_unset() {
+        let quota = Quota {
+            id: Some("e".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Environment,
+            scope_id: None,
+            limit: Some(10),
+            window: Some(60),
+            reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
+        };
+
+        // Without constraint, the quota applies to every environment separately.
+        assert!(quota.matches(&ItemScoping {
+            environment: Some("production".to_owned()),
+            ..scoping()
+        }));
+
+        // Items without an environment cannot be counted per environment.
+        assert!(!quota.matches(&scoping()));
+    }
+
+    #[test]
+    fn test_quota_matches_issue_scope() {
+        let quota = Quota {
+            id: Some("i".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Issue,
+            scope_id: None,
+            limit: Some(100),
+            window: Some(3600),
+            reason_code: None,
+            mode: QuotaMode::FixedWindow,
+            burst: None,
+            unit: QuotaUnit::Items,
+        };
+
+        assert!(quota.matches(&ItemScoping {
+            issue: Some("c4ca4238a0b923820dcc509a6f75849b".to_owned()),
+            ..scoping()
+        }));
+
+        // Items without a grouping hash cannot be counted per issue.
+        assert!(!quota.matches(&scoping()));
+    }
+
     #[test]
     fn test_parse_retry_after() {
         // positive float
@@ -986,19 +1382,14 @@ mod tests {
         };
 
         assert!(rate_limit.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
         }));
 
         assert!(!rate_limit.matches(&ItemScoping {
             category: DataCategory::Transaction,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
         }));
     }
 
@@ -1012,19 +1403,14 @@ mod tests {
         };
 
         assert!(rate_limit.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
         }));
 
         assert!(!rate_limit.matches(&ItemScoping {
-            category: DataCategory::Error,
             organization_id: 0,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
         }));
     }
 
@@ -1038,19 +1424,14 @@ mod tests {
         };
 
         assert!(rate_limit.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
         }));
 
         assert!(!rate_limit.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
             project_id: ProjectId::new(0),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
         }));
     }
 
@@ -1064,19 +1445,58 @@ mod tests {
         };
 
         assert!(rate_limit.matches(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
         }));
 
         assert!(!rate_limit.matches(&ItemScoping {
-            category: DataCategory::Error,
             organization_id: 0,
-            project_id: ProjectId::new(21),
             public_key: "deadbeefdeadbeefdeadbeefdeadbeef".to_owned(),
             key_id: None,
+            ..scoping()
+        }));
+    }
+
+    #[test]
+    fn test_rate_limit_matches_user() {
+        let rate_limit = RateLimit {
+            categories: DataCategories::new(),
+            scope: RateLimitScope::User("d9b2d63d".to_owned()),
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(1),
+        };
+
+        assert!(rate_limit.matches(&ItemScoping {
+            key_id: None,
+            user: Some("d9b2d63d".to_owned()),
+            ..scoping()
+        }));
+
+        assert!(!rate_limit.matches(&ItemScoping {
+            key_id: None,
+            ..scoping()
+        }));
+    }
+
+    #[test]
+    fn test_rate_limit_matches_issue() {
+        let rate_limit = RateLimit {
+            categories: DataCategories::new(),
+            scope: RateLimitScope::Issue("c4ca4238a0b923820dcc509a6f75849b".to_owned()),
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(1),
+        };
+
+        assert!(rate_limit.matches(&ItemScoping {
+            key_id: None,
+            issue: Some("c4ca4238a0b923820dcc509a6f75849b".to_owned()),
+            ..scoping()
+        }));
+
+        assert!(!rate_limit.matches(&ItemScoping {
+            key_id: None,
+            issue: Some("eccbc87e4b5ce2fe28308fd9f2a7baf3".to_owned()),
+            ..scoping()
         }));
     }
 
@@ -1275,11 +1695,8 @@ mod tests {
         assert_eq!(rate_limits.iter().count(), 3);
 
         let applied_limits = rate_limits.check(&ItemScoping {
-            category: DataCategory::Error,
-            organization_id: 42,
-            project_id: ProjectId::new(21),
-            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: None,
+            ..scoping()
         });
 
         // Check that the error limit is applied
@@ -1324,6 +1741,50 @@ mod tests {
         assert_eq!(rate_limits.iter().count(), 2);
     }
 
+    #[test]
+    fn test_rate_limits_check_scope() {
+        let mut rate_limits = RateLimits::new();
+
+        // Active error limit
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Error],
+            scope: RateLimitScope::Organization(42),
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(1),
+        });
+
+        // Active transaction limit
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Transaction],
+            scope: RateLimitScope::Project(ProjectId::new(21)),
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(1),
+        });
+
+        // Active limit for a different key
+        rate_limits.add(RateLimit {
+            categories: DataCategories::new(),
+            scope: RateLimitScope::Key("deadbeefdeadbeefdeadbeefdeadbeef".to_owned()),
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(1),
+        });
+
+        let applied_limits = rate_limits.check_scope(&ItemScoping {
+            key_id: None,
+            ..scoping()
+        });
+
+        // Both limits for the organization and project apply, regardless of their categories.
+        let scopes: Vec<_> = applied_limits.iter().map(|limit| &limit.scope).collect();
+        assert_eq!(
+            scopes,
+            vec![
+                &RateLimitScope::Organization(42),
+                &RateLimitScope::Project(ProjectId::new(21)),
+            ]
+        );
+    }
+
     #[test]
     fn test_rate_limits_merge() {
         let mut rate_limits1 = RateLimits::new();
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -11,16 +13,19 @@ use futures::prelude::*;
 use parking_lot::RwLock;
 use serde_json::Value as SerdeValue;
 
-use relay_common::{clone, metric, LogError};
+use relay_common::{clone, metric, LogError, UnixTimestamp, Uuid};
 use relay_config::{Config, RelayMode};
+use relay_general::grouping::{self, GroupingVersion};
 use relay_general::pii::PiiProcessor;
 use relay_general::processor::{process_value, ProcessingState};
 use relay_general::protocol::{
-    Breadcrumb, Csp, Event, EventId, ExpectCt, ExpectStaple, Hpkp, LenientString, Metrics,
-    SecurityReportType, Values,
+    apply_browser_report_to_event, Breadcrumb, Csp, Event, EventId, EventType, ExpectCt,
+    ExpectStaple, Hpkp, LenientString, Metrics, Nel, SecurityReportType, Values,
 };
 use relay_general::types::{Annotated, Array, Object, ProcessingAction, Value};
-use relay_quotas::{DataCategory, ItemScoping, RateLimits};
+use relay_quotas::{
+    DataCategory, ItemScoping, LocalRateLimiter, Quota, QuotaScope, QuotaUnit, RateLimits,
+};
 use relay_redis::RedisPool;
 
 use crate::actors::outcome::{DiscardReason, Outcome, OutcomeProducer, TrackOutcome};
@@ -40,11 +45,19 @@ use {
     crate::service::ServerErrorKind,
     failure::ResultExt,
     relay_filter::FilterStatKey,
-    relay_general::protocol::IpAddr,
-    relay_general::store::{GeoIpLookup, StoreConfig, StoreProcessor},
+    relay_general::pii::PseudonymizationConfig,
+    relay_general::protocol::{IpAddr, SessionUpdate, UserReport},
+    relay_general::store::{
+        GeoIpLookup, SourceMapLookup, SourceMapProcessor, StoreConfig, StoreProcessor,
+    },
     relay_quotas::{RateLimiter, RateLimitingError},
 };
 
+lazy_static::lazy_static! {
+    static ref NAMESPACE_USER: Uuid =
+        Uuid::new_v5(&Uuid::NAMESPACE_URL, b"https://sentry.io/#user");
+}
+
 #[derive(Debug, Fail)]
 pub enum QueueEnvelopeError {
     #[fail(display = "Too many events (max_concurrent_events reached)")]
@@ -59,6 +72,9 @@ enum ProcessingError {
     #[fail(display = "invalid message pack event payload")]
     InvalidMsgpack(#[cause] rmp_serde::decode::Error),
 
+    #[fail(display = "invalid CBOR event payload")]
+    InvalidCbor(#[cause] serde_cbor::Error),
+
     #[cfg(feature = "processing")]
     #[fail(display = "invalid unreal crash report")]
     InvalidUnrealReport(#[cause] symbolic::unreal::Unreal4Error),
@@ -123,29 +139,74 @@ type ExtractedEvent = (Annotated<Event>, usize);
 
 struct EventProcessor {
     config: Arc<Config>,
+    local_rate_limiter: Option<LocalRateLimiter>,
     #[cfg(feature = "processing")]
     rate_limiter: Option<RateLimiter>,
     #[cfg(feature = "processing")]
     geoip_lookup: Option<Arc<GeoIpLookup>>,
+    #[cfg(feature = "processing")]
+    sourcemap_lookup: Option<Arc<SourceMapLookup>>,
 }
 
 impl EventProcessor {
     #[cfg(feature = "processing")]
     pub fn new(
         config: Arc<Config>,
+        local_rate_limiter: Option<LocalRateLimiter>,
         rate_limiter: Option<RateLimiter>,
         geoip_lookup: Option<Arc<GeoIpLookup>>,
+        sourcemap_lookup: Option<Arc<SourceMapLookup>>,
     ) -> Self {
         Self {
             config,
+            local_rate_limiter,
             rate_limiter,
             geoip_lookup,
+            sourcemap_lookup,
         }
     }
 
     #[cfg(not(feature = "processing"))]
-    pub fn new(config: Arc<Config>) -> Self {
-        Self { config }
+    pub fn new(config: Arc<Config>, local_rate_limiter: Option<LocalRateLimiter>) -> Self {
+        Self {
+            config,
+            local_rate_limiter,
+        }
+    }
+
+    /// Pseudonymizes user identifiers in sessions and user reports of the envelope.
+    ///
+    /// Items that cannot be parsed are left unchanged. They are rejected when they are stored.
+    #[cfg(feature = "processing")]
+    fn pseudonymize_items(&self, envelope: &mut Envelope, config: &PseudonymizationConfig) {
+        for item in envelope.items_mut() {
+            let payload = match item.ty() {
+                ItemType::Session => {
+                    let mut session = match SessionUpdate::parse(&item.payload()) {
+                        Ok(session) => session,
+                        Err(_) => continue,
+                    };
+
+                    config.pseudonymize_session(&mut session);
+                    session.serialize()
+                }
+                ItemType::UserReport => {
+                    let mut report = match serde_json::from_slice::<UserReport>(&item.payload()) {
+                        Ok(report) => report,
+                        Err(_) => continue,
+                    };
+
+                    config.pseudonymize_user_report(&mut report);
+                    serde_json::to_vec(&report)
+                }
+                _ => continue,
+            };
+
+            match payload {
+                Ok(payload) => item.set_payload(ContentType::Json, payload),
+                Err(error) => log::error!("failed to pseudonymize item: {}", LogError(&error)),
+            }
+        }
     }
 
     /// Writes a placeholder to indicate that this event has an associated minidump or an apple
@@ -205,10 +266,22 @@ impl EventProcessor {
         }));
     }
 
-    fn event_from_json_payload(&self, item: Item) -> Result<ExtractedEvent, ProcessingError> {
-        Annotated::from_json_bytes(&item.payload())
-            .map(|event| (event, item.len()))
-            .map_err(ProcessingError::InvalidJson)
+    /// Parses an event item encoded in JSON, MessagePack or CBOR.
+    ///
+    /// Items without a binary content type are assumed to be JSON.
+    fn event_from_payload(item: Item) -> Result<ExtractedEvent, ProcessingError> {
+        let payload = item.payload();
+        let event = match item.content_type() {
+            Some(ContentType::MsgPack) => {
+                Annotated::from_msgpack(&payload).map_err(ProcessingError::InvalidMsgpack)?
+            }
+            Some(ContentType::Cbor) => {
+                Annotated::from_cbor(&payload).map_err(ProcessingError::InvalidCbor)?
+            }
+            _ => Annotated::from_json_bytes(&payload).map_err(ProcessingError::InvalidJson)?,
+        };
+
+        Ok((event, item.len()))
     }
 
     fn event_from_security_report(&self, item: Item) -> Result<ExtractedEvent, ProcessingError> {
@@ -225,6 +298,10 @@ impl EventProcessor {
             SecurityReportType::ExpectCt => ExpectCt::apply_to_event(data, &mut event),
             SecurityReportType::ExpectStaple => ExpectStaple::apply_to_event(data, &mut event),
             SecurityReportType::Hpkp => Hpkp::apply_to_event(data, &mut event),
+            SecurityReportType::Nel => Nel::apply_to_event(data, &mut event),
+            SecurityReportType::Deprecation | SecurityReportType::Intervention => {
+                apply_browser_report_to_event(data, &mut event)
+            }
         }
         .map_err(ProcessingError::InvalidSecurityReport)?;
 
@@ -281,9 +358,7 @@ impl EventProcessor {
             return Err(ProcessingError::PayloadTooLarge);
         }
 
-        let payload = item.payload();
-        let deserializer = &mut rmp_serde::Deserializer::from_read_ref(payload.as_ref());
-        Annotated::deserialize_with_meta(deserializer).map_err(ProcessingError::InvalidMsgpack)
+        Annotated::from_msgpack(&item.payload()).map_err(ProcessingError::InvalidMsgpack)
     }
 
     fn parse_msgpack_breadcrumbs(
@@ -371,7 +446,8 @@ impl EventProcessor {
     /// Extracts the primary event payload from an envelope.
     ///
     /// The event is obtained from only one source in the following precedence:
-    ///  1. An explicit event item. This is also the case for JSON uploads.
+    ///  1. An explicit event item in JSON, MessagePack or CBOR. This is also the case for JSON
+    ///     uploads.
     ///  2. A security report item.
     ///  3. Attachments `__sentry-event` and `__sentry-breadcrumb1/2`.
     ///  4. A multipart form data body.
@@ -393,9 +469,9 @@ impl EventProcessor {
             .take_item_by(|item| item.attachment_type() == Some(AttachmentType::Breadcrumbs));
 
         if let Some(item) = event_item {
-            log::trace!("processing json event");
+            log::trace!("processing event payload");
             return Ok(metric!(timer(RelayTimers::EventProcessingDeserialize), {
-                self.event_from_json_payload(item)?
+                Self::event_from_payload(item)?
             }));
         }
 
@@ -429,61 +505,200 @@ impl EventProcessor {
         Ok((Annotated::empty(), 0))
     }
 
+    /// Returns the scoping of the envelope's event and the quotas that apply to it.
+    ///
+    /// The `bytes` are the ingested size of the event, which is counted against byte quotas.
+    /// Returns `None` if there are no quotas to enforce. The issue of the event is only computed if
+    /// one of the quotas applies to issues.
+    fn get_quotas<'a>(
+        &self,
+        envelope: &Envelope,
+        event: &Annotated<Event>,
+        scopes: &mut EventScopes,
+        organization_id: u64,
+        bytes: u64,
+        project_state: &'a ProjectState,
+    ) -> Option<(ItemScoping, Cow<'a, [Quota]>)> {
+        // The key configuration may be missing if the event has been queued for extended times and
+        // project was refetched in between. In such a case, access to legacy-qutoas and the key id
+        // are not availabe, but we can gracefully execute all other quotas.
+        let key_config = project_state.get_public_key_config(&envelope.meta().public_key());
+
+        let mut quotas = Cow::Borrowed(if !project_state.config.quotas.is_empty() {
+            project_state.config.quotas.as_slice()
+        } else if let Some(key_config) = key_config {
+            key_config.legacy_quotas.as_slice()
+        } else {
+            &[]
+        });
+
+        // Spike protection is enforced in addition to the organization's quotas, so that a single
+        // issue cannot exhaust them.
+        if let Some(ref spike_protection) = project_state.config.spike_protection {
+            quotas.to_mut().push(spike_protection.quota());
+        }
+
+        if quotas.is_empty() {
+            return None;
+        }
+
+        if quotas.iter().any(|quota| quota.scope == QuotaScope::Issue) {
+            scopes.compute_issue(event);
+        }
+
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            organization_id,
+            project_id: envelope.meta().project_id(),
+            public_key: envelope.meta().public_key().to_owned(),
+            key_id: key_config.as_ref().and_then(|config| config.numeric_id),
+            release: scopes.release.clone(),
+            environment: scopes.environment.clone(),
+            user: scopes.user.clone(),
+            issue: scopes.issue.clone(),
+            bytes,
+        };
+
+        Some((scoping, quotas))
+    }
+
     #[cfg(feature = "processing")]
     fn enforce_quotas(
         &self,
         envelope: &Envelope,
+        event: &Annotated<Event>,
+        scopes: &mut EventScopes,
+        bytes: u64,
         project_state: &ProjectState,
-    ) -> Result<(), ProcessingError> {
+    ) -> Result<Option<ConsumedQuotas>, ProcessingError> {
         // The organization id is effectively always available to Relays in processing mode. Relay
         // uses the same project config as in non-processing mode, which is why it is optional.
         // However, in case it were missing, rather over-accept than drop the event.
         let organization_id = match project_state.organization_id {
             Some(organization_id) => organization_id,
-            None => return Ok(()),
+            None => return Ok(None),
         };
 
         let rate_limiter = match self.rate_limiter.as_ref() {
             Some(rate_limiter) => rate_limiter,
-            None => return Ok(()),
+            None => return Ok(None),
         };
 
-        // The key configuration may be missing if the event has been queued for extended times and
-        // project was refetched in between. In such a case, access to legacy-qutoas and the key id
-        // are not availabe, but we can gracefully execute all other quotas.
-        let key_config = project_state.get_public_key_config(&envelope.meta().public_key());
-
-        let scoping = ItemScoping {
-            category: DataCategory::Error,
+        let quotas = self.get_quotas(
+            envelope,
+            event,
+            scopes,
             organization_id,
-            project_id: envelope.meta().project_id(),
-            public_key: envelope.meta().public_key().to_owned(),
-            key_id: key_config.as_ref().and_then(|config| config.numeric_id),
+            bytes,
+            project_state,
+        );
+        let (scoping, quotas) = match quotas {
+            Some(quotas) => quotas,
+            None => return Ok(None),
         };
 
-        let quotas = if !project_state.config.quotas.is_empty() {
-            project_state.config.quotas.as_slice()
-        } else if let Some(ref key_config) = key_config {
-            key_config.legacy_quotas.as_slice()
-        } else {
-            &[]
-        };
+        let timestamp = UnixTimestamp::now();
+        let rate_limits = metric!(timer(RelayTimers::EventProcessingRateLimiting), {
+            rate_limiter
+                .is_rate_limited(&quotas, &scoping)
+                .map_err(ProcessingError::QuotasFailed)?
+        });
 
-        if quotas.is_empty() {
-            return Ok(());
+        if rate_limits.is_limited() {
+            return Err(ProcessingError::RateLimited(rate_limits));
         }
 
+        Ok(Some(ConsumedQuotas {
+            scoping,
+            quotas: quotas.into_owned(),
+            timestamp,
+        }))
+    }
+
+    /// Enforces quotas in memory for Relays that cannot enforce them in Redis.
+    fn enforce_local_quotas(
+        &self,
+        envelope: &Envelope,
+        event: &Annotated<Event>,
+        scopes: &mut EventScopes,
+        bytes: u64,
+        project_state: &ProjectState,
+    ) -> Result<Option<ConsumedQuotas>, ProcessingError> {
+        let rate_limiter = match self.local_rate_limiter.as_ref() {
+            Some(rate_limiter) => rate_limiter,
+            None => return Ok(None),
+        };
+
+        // The organization id is missing from static and proxied project configs. Like for cached
+        // rate limits, fall back to `0` so that project and key quotas are still enforced. Counters
+        // are local to this Relay, so only organization quotas of such projects are shared.
+        let organization_id = project_state.organization_id.unwrap_or(0);
+
+        let quotas = self.get_quotas(
+            envelope,
+            event,
+            scopes,
+            organization_id,
+            bytes,
+            project_state,
+        );
+        let (scoping, quotas) = match quotas {
+            Some(quotas) => quotas,
+            None => return Ok(None),
+        };
+
+        let timestamp = UnixTimestamp::now();
         let rate_limits = metric!(timer(RelayTimers::EventProcessingRateLimiting), {
-            rate_limiter
-                .is_rate_limited(quotas, &scoping)
-                .map_err(ProcessingError::QuotasFailed)?
+            rate_limiter.is_rate_limited(&quotas, &scoping)
         });
 
         if rate_limits.is_limited() {
             return Err(ProcessingError::RateLimited(rate_limits));
         }
 
-        Ok(())
+        Ok(Some(ConsumedQuotas {
+            scoping,
+            quotas: quotas.into_owned(),
+            timestamp,
+        }))
+    }
+
+    /// Refunds an event to all quotas it has been counted against.
+    ///
+    /// This is called when an event is dropped after quotas have been enforced.
+    fn refund_quotas(&self, consumed: &ConsumedQuotas) {
+        let ConsumedQuotas {
+            ref scoping,
+            ref quotas,
+            timestamp,
+        } = *consumed;
+
+        if let Some(ref rate_limiter) = self.local_rate_limiter {
+            rate_limiter.refund(quotas, scoping, timestamp);
+        }
+
+        #[cfg(feature = "processing")]
+        {
+            if let Some(ref rate_limiter) = self.rate_limiter {
+                if let Err(error) = rate_limiter.refund(quotas, scoping, timestamp) {
+                    log::error!("failed to refund quotas: {}", LogError(&error));
+                    return;
+                }
+            }
+        }
+
+        // Byte quotas are refunded the size of the event, all other quotas a single item.
+        for &unit in &[QuotaUnit::Items, QuotaUnit::Bytes] {
+            if quotas
+                .iter()
+                .any(|quota| quota.unit == unit && quota.matches(scoping))
+            {
+                metric!(
+                    counter(RelayCounters::EventQuotaRefunded) += scoping.quantity(unit) as i64,
+                    unit = unit.name()
+                );
+            }
+        }
     }
 
     #[cfg(feature = "processing")]
@@ -491,8 +706,10 @@ impl EventProcessor {
         &self,
         event: &mut Annotated<Event>,
         envelope: &Envelope,
+        scopes: &mut EventScopes,
+        bytes: u64,
         project_state: &ProjectState,
-    ) -> Result<(), ProcessingError> {
+    ) -> Result<Option<ConsumedQuotas>, ProcessingError> {
         let geoip_lookup = self.geoip_lookup.as_deref();
         let key_id = project_state
             .get_public_key_config(&envelope.meta().public_key())
@@ -517,8 +734,20 @@ impl EventProcessor {
             remove_other: Some(true),
             normalize_user_agent: Some(true),
             sent_at: envelope.sent_at(),
+            transaction_name_rules: project_state.config.transaction_name_rules.clone(),
+            stacktrace_rules: project_state.config.stacktrace_rules.clone(),
+            write_grouping_hashes: Some(false),
+            collapse_breadcrumbs: Some(project_state.config.collapse_breadcrumbs),
         };
 
+        // Resolve minified JavaScript frames before normalization, so that stack trace rules,
+        // trimming and filters apply to the resolved frames.
+        if let Some(ref sourcemap_lookup) = self.sourcemap_lookup {
+            let mut processor = SourceMapProcessor::new(sourcemap_lookup);
+            process_value(event, &mut processor, ProcessingState::root())
+                .map_err(ProcessingError::ProcessingFailed)?;
+        }
+
         let mut store_processor = StoreProcessor::new(store_config, geoip_lookup);
         metric!(timer(RelayTimers::EventProcessingProcess), {
             process_value(event, &mut store_processor, ProcessingState::root())
@@ -544,9 +773,8 @@ impl EventProcessor {
         // dropped or filtered for a different reason before that, it should not count against
         // quotas. Also, this allows to reduce the number of requests to the rate limiter (currently
         // implemented in Redis).
-        self.enforce_quotas(envelope, project_state)?;
-
-        Ok(())
+        *scopes = EventScopes::from_event(event);
+        self.enforce_quotas(envelope, event, scopes, bytes, project_state)
     }
 
     /// Checks for duplicate items in an envelope.
@@ -605,6 +833,14 @@ impl EventProcessor {
             }
         }
 
+        // Pseudonymize user identifiers in items that are not part of the event. This must happen
+        // after expanding Unreal reports, which may contain a user report.
+        if_processing! {
+            if let Some(ref config) = message.project_state.config.pseudonymization {
+                self.pseudonymize_items(&mut envelope, config);
+            }
+        }
+
         // Carry metrics on event sizes through the entire normalization process. Without
         // processing, this value is unused and will be optimized away. Note how we need to extract
         // sizes at different stages of processing and apply them after `store_process_event`.
@@ -638,9 +874,25 @@ impl EventProcessor {
             if let Some(item) = minidump_attachment {
                 _metrics.bytes_ingested_event_minidump = Annotated::new(item.len() as u64);
                 self.write_native_placeholder(&mut event, true);
+
+                // Extract system information, the exception and loaded modules from the minidump
+                // so that inbound filters apply. Stack walking is left to native processing.
+                if let Some(event) = event.value_mut() {
+                    if let Err(error) = utils::merge_minidump(event, &item.payload()) {
+                        log::debug!("failed to parse minidump: {}", LogError(&error));
+                    }
+                }
             } else if let Some(item) =  apple_crash_report_attachment {
                 _metrics.bytes_ingested_event_applecrashreport = Annotated::new(item.len() as u64);
                 self.write_native_placeholder(&mut event, false);
+
+                // Parse the crash report to make the event readable without native processing.
+                // If this fails, the placeholder remains and the pipeline handles the report.
+                if let Some(event) = event.value_mut() {
+                    if let Err(error) = utils::merge_apple_crash_report(event, &item.payload()) {
+                        log::debug!("failed to parse apple crash report: {}", LogError(&error));
+                    }
+                }
             }
 
             let attachment_size = envelope.items()
@@ -667,39 +919,117 @@ impl EventProcessor {
             // envelope only contains attachments or user reports. We should not run filters or
             // apply rate limits.
             log::trace!("no event for envelope, skipping processing");
-            return Ok(ProcessEnvelopeResponse { envelope });
+            return Ok(ProcessEnvelopeResponse {
+                envelope,
+                scopes: EventScopes::default(),
+                consumed: None,
+            });
         }
 
+        // Quotas the event has been counted against. If the event is dropped after this, the
+        // quotas are refunded.
+        let mut consumed = None;
+
+        // Scopes of the event for rate limiting. In processing mode, they are extracted from the
+        // normalized event in `store_process_event`.
+        let mut scopes = EventScopes::default();
+
         if_processing! {
-            self.store_process_event(&mut event, &envelope, &message.project_state)?;
+            consumed = self.store_process_event(
+                &mut event,
+                &envelope,
+                &mut scopes,
+                event_len as u64,
+                &message.project_state,
+            )?;
 
             // Write metrics into the fully processed event. This ensures that whatever happens
             // during processing is overwritten at last.
             if let Some(event) = event.value_mut() {
                 event._metrics = Annotated::new(_metrics);
             }
+
+            // Pseudonymize before PII stripping, so that rules do not destroy user identifiers that
+            // are needed to count affected users.
+            if let Some(ref config) = message.project_state.config.pseudonymization {
+                let user = event
+                    .value_mut()
+                    .as_mut()
+                    .and_then(|event| event.user.value_mut().as_mut());
+
+                if let Some(user) = user {
+                    config.pseudonymize_user(user);
+                }
+            }
         }
 
+        // Relays without processing do not have access to Redis, but can still enforce quotas
+        // configured for the project on their own. Since these Relays do not run filters, this
+        // happens right before PII stripping. The scopes are also needed to apply rate limits
+        // returned by the upstream, so they must be extracted before PII stripping as well.
+        if !self.config.processing_enabled() {
+            scopes = EventScopes::from_event(&event);
+        }
+
+        if let Some(local_consumed) = self.enforce_local_quotas(
+            &envelope,
+            &event,
+            &mut scopes,
+            event_len as u64,
+            &message.project_state,
+        )? {
+            consumed = Some(local_consumed);
+        }
+
+        let data = match self.finalize_event(&mut event, &message.project_state) {
+            Ok(data) => data,
+            Err(error) => {
+                if let Some(ref consumed) = consumed {
+                    self.refund_quotas(consumed);
+                }
+                return Err(error);
+            }
+        };
+
+        // Add the normalized event back to the envelope. All the other items are attachments.
+        let mut event_item = Item::new(ItemType::Event);
+        event_item.set_payload(ContentType::Json, data);
+        if let Some(ty) = event.value().and_then(|e| e.ty.value()) {
+            event_item.set_event_type(*ty);
+        }
+        envelope.add_item(event_item);
+
+        Ok(ProcessEnvelopeResponse {
+            envelope,
+            scopes,
+            consumed,
+        })
+    }
+
+    /// Strips PII from the event and serializes it.
+    fn finalize_event(
+        &self,
+        event: &mut Annotated<Event>,
+        project_state: &ProjectState,
+    ) -> Result<String, ProcessingError> {
         // Run PII stripping last since normalization can add PII (e.g. IP addresses).
         metric!(timer(RelayTimers::EventProcessingPii), {
-            if let Some(ref config) = message.project_state.config.pii_config {
+            if let Some(ref config) = project_state.config.pii_config {
                 let compiled = config.compiled();
-                let mut processor = PiiProcessor::new(&compiled);
-                process_value(&mut event, &mut processor, ProcessingState::root())
+                let pseudonymization = project_state.config.pseudonymization.as_ref();
+                let mut processor =
+                    PiiProcessor::new(&compiled).with_pseudonymization(pseudonymization);
+                process_value(event, &mut processor, ProcessingState::root())
                     .map_err(ProcessingError::ProcessingFailed)?;
             }
 
-            let config = message
-                .project_state
-                .config
-                .datascrubbing_settings
-                .pii_config();
+            let config = project_state.config.datascrubbing_settings.pii_config();
 
             if let Some(ref config) = *config {
                 let compiled = config.compiled();
 
                 let mut processor = PiiProcessor::new(&compiled);
-                process_value(&mut event, &mut processor, ProcessingState::root())
+                process_value(event, &mut processor, ProcessingState::root())
                     .map_err(ProcessingError::ProcessingFailed)?;
             }
         });
@@ -710,15 +1040,7 @@ impl EventProcessor {
             event.to_json().map_err(ProcessingError::SerializeFailed)?
         });
 
-        // Add the normalized event back to the envelope. All the other items are attachments.
-        let mut event_item = Item::new(ItemType::Event);
-        event_item.set_payload(ContentType::Json, data);
-        if let Some(ty) = event.value().and_then(|e| e.ty.value()) {
-            event_item.set_event_type(*ty);
-        }
-        envelope.add_item(event_item);
-
-        Ok(ProcessEnvelopeResponse { envelope })
+        Ok(data)
     }
 }
 
@@ -726,6 +1048,130 @@ impl Actor for EventProcessor {
     type Context = SyncContext<Self>;
 }
 
+/// Scopes of an event that quotas can apply to in addition to its organization, project and key.
+#[derive(Clone, Debug, Default)]
+struct EventScopes {
+    release: Option<String>,
+    environment: Option<String>,
+    user: Option<String>,
+    issue: Option<String>,
+}
+
+impl EventScopes {
+    /// Extracts the release, environment and a hash of the user identifier from the event.
+    ///
+    /// The user is identified by the first available of id, email, username and IP address. The
+    /// issue is computed separately by `compute_issue`.
+    fn from_event(event: &Annotated<Event>) -> Self {
+        let event = match event.value() {
+            Some(event) => event,
+            None => return Self::default(),
+        };
+
+        let user = event.user.value().and_then(|user| {
+            let identifier = user
+                .id
+                .as_str()
+                .or_else(|| user.email.as_str())
+                .or_else(|| user.username.as_str())
+                .or_else(|| user.ip_address.value().map(|ip| ip.as_str()))?;
+
+            let hash = Uuid::new_v5(&NAMESPACE_USER, identifier.as_bytes());
+            Some(hash.to_simple().to_string())
+        });
+
+        Self {
+            release: event.release.as_str().map(str::to_owned),
+            environment: event.environment.as_str().map(str::to_owned),
+            user,
+            issue: None,
+        }
+    }
+
+    /// Identifies the issue of the event by its primary grouping hash, which honors custom
+    /// fingerprints.
+    ///
+    /// Grouping is expensive, so this is only called if a quota applies to issues. The hash is
+    /// computed at most once per event. Transactions are not grouped into issues.
+    fn compute_issue(&mut self, event: &Annotated<Event>) {
+        if self.issue.is_some() {
+            return;
+        }
+
+        let event = match event.value() {
+            Some(event) if event.ty.value() != Some(&EventType::Transaction) => event,
+            _ => return,
+        };
+
+        self.issue = grouping::compute_hashes(event, GroupingVersion::V1)
+            .hashes
+            .into_iter()
+            .next();
+    }
+}
+
+/// Quotas that an event has been counted against.
+#[derive(Debug)]
+struct ConsumedQuotas {
+    /// The scoping of the event.
+    scoping: ItemScoping,
+    /// All quotas of the project, including ones that did not apply to the event.
+    quotas: Vec<Quota>,
+    /// The time at which the event was counted.
+    timestamp: UnixTimestamp,
+}
+
+/// Refunds an event that has been dropped after it was counted against quotas.
+struct RefundQuotas(ConsumedQuotas);
+
+impl Message for RefundQuotas {
+    type Result = ();
+}
+
+impl Handler<RefundQuotas> for EventProcessor {
+    type Result = ();
+
+    fn handle(&mut self, message: RefundQuotas, _context: &mut Self::Context) -> Self::Result {
+        self.refund_quotas(&message.0);
+    }
+}
+
+/// Refunds consumed quotas when dropped, unless the event has been accepted.
+///
+/// This covers all paths on which an event is dropped after processing, including errors while
+/// forwarding, timeouts and cancellation of the event's future.
+struct QuotaRefund {
+    processor: Addr<EventProcessor>,
+    consumed: RefCell<Option<ConsumedQuotas>>,
+}
+
+impl QuotaRefund {
+    fn new(processor: Addr<EventProcessor>) -> Self {
+        Self {
+            processor,
+            consumed: RefCell::new(None),
+        }
+    }
+
+    /// Records the quotas that the event has been counted against.
+    fn set(&self, consumed: Option<ConsumedQuotas>) {
+        *self.consumed.borrow_mut() = consumed;
+    }
+
+    /// Marks the event as accepted, which keeps it counted against quotas.
+    fn accept(&self) {
+        self.consumed.borrow_mut().take();
+    }
+}
+
+impl Drop for QuotaRefund {
+    fn drop(&mut self) {
+        if let Some(consumed) = self.consumed.get_mut().take() {
+            self.processor.do_send(RefundQuotas(consumed));
+        }
+    }
+}
+
 struct ProcessEnvelope {
     pub envelope: Envelope,
     pub project_state: Arc<ProjectState>,
@@ -735,6 +1181,8 @@ struct ProcessEnvelope {
 #[cfg_attr(not(feature = "processing"), allow(dead_code))]
 struct ProcessEnvelopeResponse {
     envelope: Envelope,
+    scopes: EventScopes,
+    consumed: Option<ConsumedQuotas>,
 }
 
 impl Message for ProcessEnvelope {
@@ -780,6 +1228,14 @@ impl EventManager {
         #[cfg(not(feature = "processing"))]
         let _ = redis_pool;
 
+        // Processing Relays enforce quotas in Redis. All other Relays can opt into counting quotas
+        // in memory, which is shared between all processor threads.
+        let local_rate_limiter = if config.local_quotas() && !config.processing_enabled() {
+            Some(LocalRateLimiter::new().max_limit(config.max_rate_limit()))
+        } else {
+            None
+        };
+
         #[cfg(feature = "processing")]
         let processor = {
             let geoip_lookup = match config.geoip_path() {
@@ -789,15 +1245,24 @@ impl EventManager {
                 None => None,
             };
 
+            let sourcemap_lookup = match config.sourcemaps_path() {
+                Some(p) => Some(Arc::new(
+                    SourceMapLookup::open(p).context(ServerErrorKind::SourceMapError)?,
+                )),
+                None => None,
+            };
+
             let rate_limiter =
                 redis_pool.map(|pool| RateLimiter::new(pool).max_limit(config.max_rate_limit()));
 
             SyncArbiter::start(
                 thread_count,
-                clone!(config, || EventProcessor::new(
+                clone!(config, local_rate_limiter, || EventProcessor::new(
                     config.clone(),
+                    local_rate_limiter.clone(),
                     rate_limiter.clone(),
                     geoip_lookup.clone(),
+                    sourcemap_lookup.clone(),
                 )),
             )
         };
@@ -805,7 +1270,10 @@ impl EventManager {
         #[cfg(not(feature = "processing"))]
         let processor = SyncArbiter::start(
             thread_count,
-            clone!(config, || EventProcessor::new(config.clone())),
+            clone!(config, local_rate_limiter, || EventProcessor::new(
+                config.clone(),
+                local_rate_limiter.clone(),
+            )),
         );
 
         #[cfg(feature = "processing")]
@@ -953,6 +1421,12 @@ impl Handler<HandleEnvelope> for EventManager {
         // loaded at this time.
         let organization_id = Rc::new(AtomicU64::new(0));
 
+        // The event is counted against quotas during processing. If it is dropped afterwards, for
+        // instance because of an error or a timeout, the quotas are refunded when the last
+        // reference to this guard goes away.
+        let refund = Rc::new(QuotaRefund::new(processor.clone()));
+        let guard = refund.clone();
+
         metric!(set(RelaySets::UniqueProjects) = project_id.value() as i64);
 
         let future = project
@@ -969,7 +1443,7 @@ impl Handler<HandleEnvelope> for EventManager {
                     .map_err(ProcessingError::ScheduleFailed)
                     .and_then(|result| result.map_err(ProcessingError::ProjectFailed))
             }))
-            .and_then(clone!(organization_id, |project_state| {
+            .and_then(clone!(organization_id, processor, |project_state| {
                 if let Some(id) = project_state.organization_id {
                     organization_id.store(id, Ordering::Relaxed);
                 }
@@ -984,7 +1458,14 @@ impl Handler<HandleEnvelope> for EventManager {
                     .flatten()
             }))
             .and_then(clone!(captured_events, organization_id, |processed| {
-                let envelope = processed.envelope;
+                let ProcessEnvelopeResponse {
+                    envelope,
+                    scopes,
+                    consumed,
+                } = processed;
+
+                // The event has been counted against quotas, but may still fail to be forwarded.
+                refund.set(consumed);
 
                 #[cfg(feature = "processing")]
                 {
@@ -1026,6 +1507,11 @@ impl Handler<HandleEnvelope> for EventManager {
                     project_id: envelope.meta().project_id(),
                     public_key: envelope.meta().public_key().to_owned(),
                     key_id: None,
+                    release: scopes.release,
+                    environment: scopes.environment,
+                    user: scopes.user,
+                    issue: scopes.issue,
+                    bytes: 0,
                 };
 
                 log::trace!("sending event to sentry endpoint");
@@ -1065,7 +1551,10 @@ impl Handler<HandleEnvelope> for EventManager {
             }))
             .into_actor(self)
             .timeout(self.config.event_buffer_expiry(), ProcessingError::Timeout)
-            .map(|_, _, _| metric!(counter(RelayCounters::EventAccepted) += 1))
+            .map(move |_, _, _| {
+                guard.accept();
+                metric!(counter(RelayCounters::EventAccepted) += 1);
+            })
             .map_err(clone!(project, captured_events, |error, _, _| {
                 // Rate limits need special handling: Cache them on the project to avoid
                 // expensive processing while the limit is active.
@@ -1106,6 +1595,9 @@ impl Handler<HandleEnvelope> for EventManager {
                     ProcessingError::InvalidMsgpack(_) => {
                         Some(Outcome::Invalid(DiscardReason::InvalidMsgpack))
                     }
+                    ProcessingError::InvalidCbor(_) => {
+                        Some(Outcome::Invalid(DiscardReason::InvalidCbor))
+                    }
                     ProcessingError::EventRejected(outcome_reason) => {
                         Some(Outcome::Invalid(outcome_reason))
                     }
@@ -1244,6 +1736,49 @@ mod tests {
             .unwrap()
     }
 
+    fn create_event_item(content_type: ContentType, data: Vec<u8>) -> Item {
+        let mut item = Item::new(ItemType::Event);
+        item.set_payload(content_type, data);
+        item
+    }
+
+    #[test]
+    fn test_event_from_msgpack_payload() {
+        let json = serde_json::json!({"message": "hello", "level": "warning"});
+        let data = rmp_serde::to_vec_named(&json).unwrap();
+        let item = create_event_item(ContentType::MsgPack, data);
+
+        let (event, _) = EventProcessor::event_from_payload(item).unwrap();
+        let event = event.value().unwrap();
+        assert_eq!(
+            event.logentry.value().unwrap().formatted.as_str(),
+            Some("hello")
+        );
+    }
+
+    #[test]
+    fn test_event_from_cbor_payload() {
+        let json = serde_json::json!({"message": "hello", "level": "warning"});
+        let data = serde_cbor::to_vec(&json).unwrap();
+        let item = create_event_item(ContentType::Cbor, data);
+
+        let (event, _) = EventProcessor::event_from_payload(item).unwrap();
+        let event = event.value().unwrap();
+        assert_eq!(
+            event.logentry.value().unwrap().formatted.as_str(),
+            Some("hello")
+        );
+    }
+
+    #[test]
+    fn test_event_from_invalid_cbor_payload() {
+        let item = create_event_item(ContentType::Cbor, b"{\"message\": \"hello\"}".to_vec());
+        match EventProcessor::event_from_payload(item) {
+            Err(ProcessingError::InvalidCbor(_)) => (),
+            other => panic!("expected CBOR error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_breadcrumbs_file1() {
         let item = create_breadcrumbs_item(&[(None, "item1")]);
@@ -146,9 +146,15 @@ pub enum DiscardReason {
     /// [Relay] Parsing the event msgpack payload failed due to a syntax error.
     InvalidMsgpack,
 
+    /// [Relay] Parsing the event CBOR payload failed due to a syntax error.
+    InvalidCbor,
+
     /// [Relay] Parsing a multipart form-data request failed.
     InvalidMultipart,
 
+    /// [Relay] Parsing an OpenTelemetry trace export failed.
+    InvalidOtlp,
+
     /// [Relay] The event is parseable but semantically invalid. This should only happen with
     /// transaction events.
     InvalidTransaction,
@@ -249,6 +255,8 @@ mod kafka {
                 DiscardReason::InvalidJson => "invalid_json",
                 DiscardReason::InvalidMultipart => "invalid_multipart",
                 DiscardReason::InvalidMsgpack => "invalid_msgpack",
+                DiscardReason::InvalidCbor => "invalid_cbor",
+                DiscardReason::InvalidOtlp => "invalid_otlp",
                 DiscardReason::InvalidTransaction => "invalid_transaction",
                 DiscardReason::InvalidEnvelope => "invalid_envelope",
                 DiscardReason::ProjectState => "project_state",
@@ -8,11 +8,12 @@ use serde_json::Value;
 use url::Url;
 
 use relay_auth::PublicKey;
-use relay_common::{metric, ProjectId, Uuid};
+use relay_common::{is_false, metric, ProjectId, Uuid};
 use relay_config::{Config, RelayMode};
 use relay_filter::{matches_any_origin, FiltersConfig};
-use relay_general::pii::{DataScrubbingConfig, PiiConfig};
-use relay_quotas::{DataCategory, ItemScoping, Quota, RateLimits};
+use relay_general::pii::{DataScrubbingConfig, PiiConfig, PseudonymizationConfig};
+use relay_general::store::{StacktraceRule, TransactionNameRule};
+use relay_quotas::{DataCategory, ItemScoping, Quota, RateLimits, SpikeProtectionConfig};
 
 use crate::actors::outcome::DiscardReason;
 use crate::actors::project_cache::{FetchProjectState, ProjectCache, ProjectError};
@@ -70,11 +71,26 @@ pub struct ProjectConfig {
     /// Configuration for data scrubbers.
     #[serde(skip_serializing_if = "DataScrubbingConfig::is_disabled")]
     pub datascrubbing_settings: DataScrubbingConfig,
+    /// Configuration for pseudonymizing user identifiers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pseudonymization: Option<PseudonymizationConfig>,
     /// Maximum event retention for the organization.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub event_retention: Option<u16>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub quotas: Vec<Quota>,
+    /// Limits the number of events accepted for each issue of the project.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spike_protection: Option<SpikeProtectionConfig>,
+    /// Rules for normalizing the names of URL transactions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transaction_name_rules: Vec<TransactionNameRule>,
+    /// Rules for setting or overriding `in_app` of stack frames.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stacktrace_rules: Vec<StacktraceRule>,
+    /// Collapse consecutive repeated breadcrumbs into one.
+    #[serde(skip_serializing_if = "is_false")]
+    pub collapse_breadcrumbs: bool,
 }
 
 impl Default for ProjectConfig {
@@ -86,8 +102,13 @@ impl Default for ProjectConfig {
             grouping_config: None,
             filter_settings: FiltersConfig::default(),
             datascrubbing_settings: DataScrubbingConfig::default(),
+            pseudonymization: None,
             event_retention: None,
             quotas: Vec::new(),
+            spike_protection: None,
+            transaction_name_rules: Vec::new(),
+            stacktrace_rules: Vec::new(),
+            collapse_breadcrumbs: false,
         }
     }
 }
@@ -544,6 +565,11 @@ impl Handler<GetEventAction> for Project {
             // only require the public_key. We omit it since there's no guarantee that the key_id is
             // available at any time.
             key_id: None,
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         });
 
         if rate_limits.is_limited() {
@@ -566,6 +592,47 @@ impl Handler<GetEventAction> for Project {
     }
 }
 
+/// Returns the active rate limits for the project key of a request.
+///
+/// This includes rate limits for all data categories. It is used to inform clients about rate
+/// limits before their events are rejected.
+pub struct GetRateLimits {
+    meta: Arc<RequestMeta>,
+}
+
+impl GetRateLimits {
+    pub fn new(meta: Arc<RequestMeta>) -> Self {
+        GetRateLimits { meta }
+    }
+}
+
+impl Message for GetRateLimits {
+    type Result = RateLimits;
+}
+
+impl Handler<GetRateLimits> for Project {
+    type Result = MessageResult<GetRateLimits>;
+
+    fn handle(&mut self, message: GetRateLimits, _context: &mut Self::Context) -> Self::Result {
+        // See `GetEventAction` for the choice of the organization id and key id. The category is
+        // irrelevant since rate limits of all categories are returned.
+        let rate_limits = self.rate_limits.check_scope(&ItemScoping {
+            category: DataCategory::Default,
+            organization_id: self.state().and_then(|s| s.organization_id).unwrap_or(0),
+            project_id: self.id,
+            public_key: message.meta.public_key().to_owned(),
+            key_id: None,
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
+        });
+
+        MessageResult(rate_limits)
+    }
+}
+
 pub struct UpdateRateLimits(pub RateLimits);
 
 impl Message for UpdateRateLimits {
@@ -18,7 +18,7 @@ use relay_auth::{RegisterChallenge, RegisterRequest, RegisterResponse, Registrat
 use relay_common::{tryf, LogError, RetryBackoff};
 use relay_config::{Config, RelayMode};
 use relay_quotas::{
-    DataCategories, ItemScoping, QuotaScope, RateLimit, RateLimitScope, RateLimits, RetryAfter,
+    DataCategories, ItemScoping, RateLimit, RateLimitScope, RateLimits, RetryAfter,
 };
 
 use crate::utils;
@@ -113,7 +113,7 @@ impl UpstreamRateLimits {
 
             rate_limits.add(RateLimit {
                 categories,
-                scope: RateLimitScope::for_quota(scoping, QuotaScope::Key),
+                scope: RateLimitScope::Key(scoping.public_key.clone()),
                 reason_code: None,
                 retry_after: self.retry_after,
             });
@@ -8,6 +8,7 @@ use actix_web::http::{header, StatusCode};
 use actix_web::middleware::cors::{Cors, CorsBuilder};
 use actix_web::{HttpRequest, HttpResponse, ResponseError};
 use failure::Fail;
+use futures::future;
 use futures::prelude::*;
 use parking_lot::Mutex;
 use sentry::Hub;
@@ -15,16 +16,16 @@ use sentry_actix::ActixWebHubExt;
 use serde::Deserialize;
 
 use relay_common::{clone, metric, tryf, LogError};
-use relay_general::protocol::EventId;
+use relay_general::protocol::{EventId, OtlpError};
 use relay_quotas::{DataCategory, RateLimits};
 
 use crate::actors::events::{QueueEnvelope, QueueEnvelopeError};
 use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
-use crate::actors::project::{EventAction, GetEventAction};
+use crate::actors::project::{EventAction, GetEventAction, GetRateLimits};
 use crate::actors::project_cache::{GetProject, ProjectError};
 use crate::body::StorePayloadError;
 use crate::constants::ITEM_NAME_EVENT;
-use crate::envelope::{Envelope, EnvelopeError, ItemType, Items};
+use crate::envelope::{ContentType, Envelope, EnvelopeError, ItemType, Items};
 use crate::extractors::{RequestMeta, StartTime};
 use crate::metrics::RelayCounters;
 use crate::service::{ServiceApp, ServiceState};
@@ -50,9 +51,15 @@ pub enum BadStoreRequest {
     #[fail(display = "invalid messagepack data")]
     InvalidMsgpack(#[cause] rmp_serde::decode::Error),
 
+    #[fail(display = "invalid CBOR data")]
+    InvalidCbor(#[cause] serde_cbor::Error),
+
     #[fail(display = "invalid event envelope")]
     InvalidEnvelope(#[cause] EnvelopeError),
 
+    #[fail(display = "invalid OpenTelemetry traces")]
+    InvalidOtlp(#[cause] OtlpError),
+
     #[fail(display = "invalid multipart data")]
     InvalidMultipart(#[cause] MultipartError),
 
@@ -95,6 +102,7 @@ impl BadStoreRequest {
             BadStoreRequest::EmptyBody => Outcome::Invalid(DiscardReason::NoData),
             BadStoreRequest::InvalidJson(_) => Outcome::Invalid(DiscardReason::InvalidJson),
             BadStoreRequest::InvalidMsgpack(_) => Outcome::Invalid(DiscardReason::InvalidMsgpack),
+            BadStoreRequest::InvalidCbor(_) => Outcome::Invalid(DiscardReason::InvalidCbor),
             BadStoreRequest::InvalidMultipart(_) => {
                 Outcome::Invalid(DiscardReason::InvalidMultipart)
             }
@@ -103,6 +111,7 @@ impl BadStoreRequest {
                 Outcome::Invalid(DiscardReason::MissingMinidumpUpload)
             }
             BadStoreRequest::InvalidEnvelope(_) => Outcome::Invalid(DiscardReason::InvalidEnvelope),
+            BadStoreRequest::InvalidOtlp(_) => Outcome::Invalid(DiscardReason::InvalidOtlp),
 
             BadStoreRequest::QueueFailed(event_error) => match event_error {
                 QueueEnvelopeError::TooManyEvents => Outcome::Invalid(DiscardReason::Internal),
@@ -146,15 +155,15 @@ impl ResponseError for BadStoreRequest {
                     .map(|limit| limit.retry_after.remaining_seconds().to_string())
                     .unwrap_or_default();
 
-                let rate_limits_header = utils::format_rate_limits(rate_limits);
-
                 // For rate limits, we return a special status code and indicate the client to hold
                 // off until the rate limit period has expired. Currently, we only support the
                 // delay-seconds variant of the Rate-Limit header.
-                HttpResponse::build(StatusCode::TOO_MANY_REQUESTS)
+                let mut response = HttpResponse::build(StatusCode::TOO_MANY_REQUESTS)
                     .header(header::RETRY_AFTER, retry_after_header)
-                    .header(utils::RATE_LIMITS_HEADER, rate_limits_header)
-                    .json(&body)
+                    .json(&body);
+
+                add_rate_limits_header(&mut response, rate_limits);
+                response
             }
             BadStoreRequest::ProjectFailed(project_error) => match project_error {
                 ProjectError::FetchFailed => {
@@ -186,6 +195,23 @@ impl ResponseError for BadStoreRequest {
     }
 }
 
+/// Adds the `X-Sentry-Rate-Limits` header to the response if there are active rate limits.
+///
+/// Clients use this header to back off from sending data in specific categories before they are
+/// rejected by Relay.
+fn add_rate_limits_header(response: &mut HttpResponse, rate_limits: &RateLimits) {
+    if !rate_limits.is_limited() {
+        return;
+    }
+
+    let name = header::HeaderName::from_bytes(utils::RATE_LIMITS_HEADER.as_bytes());
+    let value = header::HeaderValue::from_str(&utils::format_rate_limits(rate_limits));
+
+    if let (Ok(name), Ok(value)) = (name, value) {
+        response.headers_mut().insert(name, value);
+    }
+}
+
 #[derive(Deserialize)]
 struct EventIdHelper {
     #[serde(default, rename = "event_id")]
@@ -214,6 +240,31 @@ pub fn event_id_from_msgpack(data: &[u8]) -> Result<Option<EventId>, BadStoreReq
         .map_err(BadStoreRequest::InvalidMsgpack)
 }
 
+/// Extracts the event id from a CBOR payload.
+///
+/// If the payload contains no event id, `Ok(None)` is returned. This function also validates that
+/// the provided is valid and returns an `Err` on parse errors. If the event id itself is malformed,
+/// an `Err` is returned.
+pub fn event_id_from_cbor(data: &[u8]) -> Result<Option<EventId>, BadStoreRequest> {
+    serde_cbor::from_slice(data)
+        .map(|helper: EventIdHelper| helper.id)
+        .map_err(BadStoreRequest::InvalidCbor)
+}
+
+/// Extracts the event id from an event payload of the given content type.
+///
+/// Event payloads can be encoded in MessagePack or CBOR. All other payloads are assumed to be JSON.
+pub fn event_id_from_payload(
+    content_type: Option<&ContentType>,
+    data: &[u8],
+) -> Result<Option<EventId>, BadStoreRequest> {
+    match content_type {
+        Some(ContentType::MsgPack) => event_id_from_msgpack(data),
+        Some(ContentType::Cbor) => event_id_from_cbor(data),
+        _ => event_id_from_json(data),
+    }
+}
+
 /// Extracts the event id from `sentry` JSON payload or the `sentry[event_id]` formdata key.
 ///
 /// If the event id itself is malformed, an `Err` is returned. If there is a `sentry` key containing
@@ -239,13 +290,13 @@ pub fn event_id_from_formdata(data: &[u8]) -> Result<Option<EventId>, BadStoreRe
 /// Submitting multiple event payloads is undefined behavior. This function will check for an event
 /// id in the following precedence:
 ///
-///  1. The `Event` item.
+///  1. The `Event` item, encoded in JSON, MessagePack or CBOR.
 ///  2. The `__sentry-event` event attachment.
 ///  3. The `sentry` JSON payload.
 ///  4. The `sentry[event_id]` formdata key.
 pub fn event_id_from_items(items: &Items) -> Result<Option<EventId>, BadStoreRequest> {
     if let Some(item) = items.iter().find(|item| item.ty() == ItemType::Event) {
-        if let Some(event_id) = event_id_from_json(&item.payload())? {
+        if let Some(event_id) = event_id_from_payload(item.content_type(), &item.payload())? {
             return Ok(Some(event_id));
         }
     }
@@ -321,6 +372,40 @@ where
     F: FnOnce(&HttpRequest<ServiceState>, RequestMeta) -> I + 'static,
     I: IntoFuture<Item = Envelope, Error = BadStoreRequest> + 'static,
     R: FnOnce(Option<EventId>) -> HttpResponse + 'static,
+{
+    handle_store_like_batch(
+        meta,
+        is_event,
+        start_time,
+        request,
+        move |request, meta| {
+            extract_envelope(request, meta)
+                .into_future()
+                .map(|envelope| vec![envelope])
+        },
+        move |event_ids| create_response(event_ids.into_iter().next().and_then(|id| id)),
+    )
+}
+
+/// Handles requests that contain multiple events.
+///
+/// This works like `handle_store_like_request`, except that all envelopes extracted from the
+/// request are queued individually. Rate limits are checked once for the entire request. If the
+/// request is rejected, an outcome is emitted for every extracted envelope. If only some envelopes
+/// cannot be queued, the request still succeeds and outcomes are emitted for just these envelopes,
+/// so that clients do not retry envelopes that have been accepted.
+pub fn handle_store_like_batch<F, R, I>(
+    meta: RequestMeta,
+    is_event: bool,
+    start_time: StartTime,
+    request: HttpRequest<ServiceState>,
+    extract_envelopes: F,
+    create_response: R,
+) -> ResponseFuture<HttpResponse, BadStoreRequest>
+where
+    F: FnOnce(&HttpRequest<ServiceState>, RequestMeta) -> I + 'static,
+    I: IntoFuture<Item = Vec<Envelope>, Error = BadStoreRequest> + 'static,
+    R: FnOnce(Vec<Option<EventId>>) -> HttpResponse + 'static,
 {
     let start_time = start_time.into_inner();
 
@@ -348,26 +433,28 @@ where
     let event_manager = request.state().event_manager();
     let project_manager = request.state().project_cache();
     let outcome_producer = request.state().outcome_producer();
+    let failed_outcome_producer = outcome_producer.clone();
     let remote_addr = meta.client_addr();
 
     let cloned_meta = Arc::new(meta.clone());
-    let event_id = Rc::new(Mutex::new(None));
+    let rate_limits_meta = cloned_meta.clone();
+    let event_ids = Rc::new(Mutex::new(Vec::new()));
 
     let future = project_manager
         .send(GetProject { id: project_id })
         .map_err(BadStoreRequest::ScheduleFailed)
-        .and_then(clone!(event_id, |project| {
-            extract_envelope(&request, meta)
+        .and_then(clone!(event_ids, |project| {
+            extract_envelopes(&request, meta)
                 .into_future()
-                .and_then(clone!(project, |envelope| {
-                    *event_id.lock() = envelope.event_id();
+                .and_then(clone!(project, |envelopes| {
+                    *event_ids.lock() = envelopes.iter().map(Envelope::event_id).collect();
 
                     project
                         .send(GetEventAction::cached(cloned_meta, DataCategory::Error))
                         .map_err(BadStoreRequest::ScheduleFailed)
                         .and_then(move |action| {
                             match action.map_err(BadStoreRequest::ProjectFailed)? {
-                                EventAction::Accept => Ok(envelope),
+                                EventAction::Accept => Ok(envelopes),
                                 EventAction::RateLimit(rate_limits) => {
                                     Err(BadStoreRequest::RateLimited(rate_limits))
                                 }
@@ -377,31 +464,97 @@ where
                             }
                         })
                 }))
-                .and_then(move |envelope| {
-                    event_manager
-                        .send(QueueEnvelope {
-                            envelope,
-                            project,
-                            start_time,
-                        })
+                .and_then(clone!(project, |envelopes| {
+                    let futures = envelopes.into_iter().map(|envelope| {
+                        let event_id = envelope.event_id();
+                        event_manager
+                            .send(QueueEnvelope {
+                                envelope,
+                                project: project.clone(),
+                                start_time,
+                            })
+                            .map_err(BadStoreRequest::ScheduleFailed)
+                            .and_then(|result| result.map_err(BadStoreRequest::QueueFailed))
+                            .then(move |result| Ok::<_, BadStoreRequest>((event_id, result)))
+                    });
+
+                    future::join_all(futures).and_then(move |results| {
+                        let mut queued = Vec::with_capacity(results.len());
+                        let mut failed = Vec::new();
+
+                        for (event_id, result) in results {
+                            match result {
+                                Ok(queued_id) => queued.push(queued_id),
+                                Err(error) => failed.push((event_id, error)),
+                            }
+                        }
+
+                        // If no envelope could be queued, the entire request fails.
+                        if queued.is_empty() {
+                            if let Some((_, error)) = failed.pop() {
+                                return Err(error);
+                            }
+                        }
+
+                        // Otherwise, only envelopes that could not be queued are rejected. All
+                        // others are already being processed and create their own outcomes.
+                        for (event_id, error) in failed {
+                            metric!(counter(RelayCounters::EventRejected) += 1);
+                            log::debug!("failed to queue envelope: {}", LogError(&error));
+
+                            if is_event {
+                                failed_outcome_producer.do_send(TrackOutcome {
+                                    timestamp: start_time,
+                                    project_id,
+                                    org_id: None,
+                                    key_id: None,
+                                    outcome: error.to_outcome(),
+                                    event_id,
+                                    remote_addr,
+                                });
+                            }
+                        }
+
+                        Ok(queued)
+                    })
+                }))
+                .and_then(move |event_ids| {
+                    // Inform the client about active rate limits of its key, even though this
+                    // request has been accepted. This allows clients to back off per category.
+                    project
+                        .send(GetRateLimits::new(rate_limits_meta))
                         .map_err(BadStoreRequest::ScheduleFailed)
-                        .and_then(|result| result.map_err(BadStoreRequest::QueueFailed))
-                        .map(create_response)
+                        .map(move |rate_limits| {
+                            let mut response = create_response(event_ids);
+                            add_rate_limits_header(&mut response, &rate_limits);
+                            response
+                        })
                 })
         }))
         .or_else(move |error: BadStoreRequest| {
             metric!(counter(RelayCounters::EventRejected) += 1);
 
             if is_event {
-                outcome_producer.do_send(TrackOutcome {
-                    timestamp: start_time,
-                    project_id,
-                    org_id: None,
-                    key_id: None,
-                    outcome: error.to_outcome(),
-                    event_id: *event_id.lock(),
-                    remote_addr,
-                });
+                let event_ids = event_ids.lock();
+
+                // If no envelopes have been extracted, there is a single outcome for the request.
+                let event_ids = match event_ids.len() {
+                    0 => &[None][..],
+                    _ => &event_ids[..],
+                };
+
+                let outcome = error.to_outcome();
+                for event_id in event_ids {
+                    outcome_producer.do_send(TrackOutcome {
+                        timestamp: start_time,
+                        project_id,
+                        org_id: None,
+                        key_id: None,
+                        outcome: outcome.clone(),
+                        event_id: *event_id,
+                        remote_addr,
+                    });
+                }
             }
 
             let response = error.error_response();
@@ -13,6 +13,7 @@ mod events;
 mod forward;
 mod healthcheck;
 mod minidump;
+mod otlp;
 mod project_configs;
 mod public_keys;
 mod security_report;
@@ -34,6 +35,7 @@ pub fn configure_app(app: ServiceApp) -> ServiceApp {
     .configure(minidump::configure_app)
     .configure(attachments::configure_app)
     .configure(unreal::configure_app)
+    .configure(otlp::configure_app)
     // `forward` must be last as it creates a wildcard proxy
     .configure(forward::configure_app)
 }
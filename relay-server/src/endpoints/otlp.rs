@@ -0,0 +1,125 @@
+//! Endpoint for OpenTelemetry traces sent via OTLP/HTTP.
+//!
+//! Spans are converted into transaction events, which are then queued individually.
+
+use actix_web::actix::ResponseFuture;
+use actix_web::{pred, HttpMessage, HttpRequest, HttpResponse, Request};
+use futures::Future;
+
+use relay_general::protocol::{EventId, OtlpTraces};
+use relay_general::types::Annotated;
+
+use crate::body::StoreBody;
+use crate::endpoints::common::{self, BadStoreRequest};
+use crate::envelope::{ContentType, Envelope, Item, ItemType};
+use crate::extractors::{RequestMeta, StartTime};
+use crate::service::{ServiceApp, ServiceState};
+
+/// The content type of protobuf-encoded trace exports.
+const CONTENT_TYPE_PROTOBUF: &str = "application/x-protobuf";
+
+fn extract_envelopes(
+    request: &HttpRequest<ServiceState>,
+    meta: RequestMeta,
+    max_event_payload_size: usize,
+    content_type: String,
+) -> ResponseFuture<Vec<Envelope>, BadStoreRequest> {
+    let future = StoreBody::new(&request, max_event_payload_size)
+        .map_err(BadStoreRequest::PayloadError)
+        .and_then(move |data| {
+            if data.is_empty() {
+                return Err(BadStoreRequest::EmptyBody);
+            }
+
+            let traces = match content_type.as_str() {
+                CONTENT_TYPE_PROTOBUF => OtlpTraces::from_protobuf(&data),
+                _ => OtlpTraces::from_json(&data),
+            };
+
+            let traces = traces.map_err(BadStoreRequest::InvalidOtlp)?;
+
+            traces
+                .into_events()
+                .into_iter()
+                .map(|event| {
+                    let event_id = event.id.value().copied().unwrap_or_else(EventId::new);
+                    let json = Annotated::new(event)
+                        .to_json()
+                        .map_err(BadStoreRequest::InvalidJson)?;
+
+                    let mut event_item = Item::new(ItemType::Event);
+                    event_item.set_payload(ContentType::Json, json);
+
+                    let mut envelope = Envelope::from_request(Some(event_id), meta.clone());
+                    envelope.add_item(event_item);
+
+                    Ok(envelope)
+                })
+                .collect::<Result<Vec<_>, _>>()
+        });
+
+    Box::new(future)
+}
+
+/// Creates an empty export response in the encoding of the request.
+fn create_response(content_type: &str) -> HttpResponse {
+    match content_type {
+        CONTENT_TYPE_PROTOBUF => HttpResponse::Ok()
+            .content_type(CONTENT_TYPE_PROTOBUF)
+            .finish(),
+        _ => HttpResponse::Ok()
+            .content_type("application/json")
+            .body("{}"),
+    }
+}
+
+/// Handles OTLP trace exports.
+///
+/// Every local root span is converted into a transaction, which is processed like any other event.
+fn store_traces(
+    meta: RequestMeta,
+    start_time: StartTime,
+    request: HttpRequest<ServiceState>,
+) -> ResponseFuture<HttpResponse, BadStoreRequest> {
+    let event_size = request.state().config().max_event_payload_size();
+    let content_type = request.content_type().to_owned();
+    let response_type = content_type.clone();
+
+    common::handle_store_like_batch(
+        meta,
+        true,
+        start_time,
+        request,
+        move |data, meta| extract_envelopes(data, meta, event_size, content_type),
+        move |_| create_response(&response_type),
+    )
+}
+
+#[derive(Debug)]
+struct OtlpFilter;
+
+impl pred::Predicate<ServiceState> for OtlpFilter {
+    fn check(&self, request: &Request, _: &ServiceState) -> bool {
+        let content_type = request
+            .headers()
+            .get("content-type")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|ct| ct.split(';').next())
+            .unwrap_or("")
+            .trim();
+
+        match content_type {
+            CONTENT_TYPE_PROTOBUF | "application/json" => true,
+            _ => false,
+        }
+    }
+}
+
+pub fn configure_app(app: ServiceApp) -> ServiceApp {
+    common::cors(app)
+        .resource(r"/api/{project:\d+}/otlp/v1/traces{t:/*}", |r| {
+            r.name("store-otlp-traces");
+            r.post().filter(OtlpFilter).with(store_traces);
+        })
+        .register()
+}
@@ -1,10 +1,14 @@
 //! Endpoints for security reports.
+//!
+//! Besides individual security reports, this also accepts batches of reports sent through the
+//! Reporting API. Every report in such a batch is ingested as a separate event.
 
 use actix_web::actix::ResponseFuture;
 use actix_web::{pred, HttpRequest, HttpResponse, Query, Request};
 use futures::Future;
 use serde::Deserialize;
 
+use relay_common::Uuid;
 use relay_general::protocol::EventId;
 
 use crate::body::StoreBody;
@@ -13,6 +17,14 @@ use crate::envelope::{ContentType, Envelope, Item, ItemType};
 use crate::extractors::{RequestMeta, StartTime};
 use crate::service::{ServiceApp, ServiceState};
 
+/// The content type of report batches sent through the Reporting API.
+const CONTENT_TYPE_REPORTS: &str = "application/reports+json";
+
+lazy_static::lazy_static! {
+    static ref NAMESPACE_REPORT: Uuid =
+        Uuid::new_v5(&Uuid::NAMESPACE_URL, b"https://sentry.io/#report");
+}
+
 #[derive(Debug, Deserialize)]
 struct SecurityReportParams {
     sentry_release: Option<String>,
@@ -51,6 +63,65 @@ fn extract_envelope(
     Box::new(future)
 }
 
+/// Derives the event id of a report in a Reporting API batch.
+///
+/// Clients retry failed batches, so the same report must always receive the same id. The `age` of
+/// a report changes with every delivery attempt and is therefore not part of the id.
+fn report_event_id(report: &serde_json::Value) -> Result<EventId, BadStoreRequest> {
+    let mut report = report.clone();
+    if let Some(object) = report.as_object_mut() {
+        object.remove("age");
+    }
+
+    let data = serde_json::to_vec(&report).map_err(BadStoreRequest::InvalidJson)?;
+    Ok(EventId(Uuid::new_v5(&NAMESPACE_REPORT, &data)))
+}
+
+fn extract_envelopes(
+    request: &HttpRequest<ServiceState>,
+    meta: RequestMeta,
+    max_event_payload_size: usize,
+    params: SecurityReportParams,
+) -> ResponseFuture<Vec<Envelope>, BadStoreRequest> {
+    let future = StoreBody::new(&request, max_event_payload_size)
+        .map_err(BadStoreRequest::PayloadError)
+        .and_then(move |data| {
+            if data.is_empty() {
+                return Err(BadStoreRequest::EmptyBody);
+            }
+
+            let reports: Vec<serde_json::Value> =
+                serde_json::from_slice(&data).map_err(BadStoreRequest::InvalidJson)?;
+
+            reports
+                .into_iter()
+                .map(|report| {
+                    let payload =
+                        serde_json::to_vec(&report).map_err(BadStoreRequest::InvalidJson)?;
+
+                    let mut report_item = Item::new(ItemType::SecurityReport);
+                    report_item.set_payload(ContentType::Json, payload);
+
+                    if let Some(ref sentry_release) = params.sentry_release {
+                        report_item.set_header("sentry_release", sentry_release.clone());
+                    }
+
+                    if let Some(ref sentry_environment) = params.sentry_environment {
+                        report_item.set_header("sentry_environment", sentry_environment.clone());
+                    }
+
+                    let event_id = report_event_id(&report)?;
+                    let mut envelope = Envelope::from_request(Some(event_id), meta.clone());
+                    envelope.add_item(report_item);
+
+                    Ok(envelope)
+                })
+                .collect::<Result<Vec<_>, _>>()
+        });
+
+    Box::new(future)
+}
+
 fn create_response() -> HttpResponse {
     HttpResponse::Created()
         .content_type("application/javascript")
@@ -77,6 +148,26 @@ fn store_security_report(
     )
 }
 
+/// This handles batches of reports sent through the Reporting API.
+///
+/// Every report is queued as an individual security report.
+fn store_reports(
+    meta: RequestMeta,
+    start_time: StartTime,
+    request: HttpRequest<ServiceState>,
+    params: Query<SecurityReportParams>,
+) -> ResponseFuture<HttpResponse, BadStoreRequest> {
+    let event_size = request.state().config().max_event_payload_size();
+    common::handle_store_like_batch(
+        meta,
+        true,
+        start_time,
+        request,
+        move |data, meta| extract_envelopes(data, meta, event_size, params.into_inner()),
+        |_| create_response(),
+    )
+}
+
 #[derive(Debug)]
 struct SecurityReportFilter;
 
@@ -99,6 +190,23 @@ impl pred::Predicate<ServiceState> for SecurityReportFilter {
     }
 }
 
+#[derive(Debug)]
+struct ReportsFilter;
+
+impl pred::Predicate<ServiceState> for ReportsFilter {
+    fn check(&self, request: &Request, _: &ServiceState) -> bool {
+        let content_type = request
+            .headers()
+            .get("content-type")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|ct| ct.split(';').next())
+            .unwrap_or("")
+            .trim();
+
+        content_type == CONTENT_TYPE_REPORTS
+    }
+}
+
 pub fn configure_app(app: ServiceApp) -> ServiceApp {
     common::cors(app)
         // Default security endpoint
@@ -107,6 +215,7 @@ pub fn configure_app(app: ServiceApp) -> ServiceApp {
             r.post()
                 .filter(SecurityReportFilter)
                 .with(store_security_report);
+            r.post().filter(ReportsFilter).with(store_reports);
         })
         // Legacy security endpoint
         .resource(r"/api/{project:\d+}/csp-report/", |r| {
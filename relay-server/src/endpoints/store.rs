@@ -56,18 +56,19 @@ fn extract_envelope(
                 data = data_mut.freeze();
             }
 
-            // Ensure that the event has a UUID. It will be returned from this message and from the
-            // incoming store request. To uncouple it from the workload on the processing workers, this
-            // requires to synchronously parse a minimal part of the JSON payload. If the JSON payload
-            // is invalid, processing can be skipped altogether.
-            let event_id = common::event_id_from_json(&data)?.unwrap_or_else(EventId::new);
-
             // Use the request's content type. If the content type is missing, assume "application/json".
             let content_type = match &content_type {
                 ct if ct.is_empty() => ContentType::Json,
                 _ct => ContentType::from(content_type),
             };
 
+            // Ensure that the event has a UUID. It will be returned from this message and from the
+            // incoming store request. To uncouple it from the workload on the processing workers, this
+            // requires to synchronously parse a minimal part of the payload. If the payload is
+            // invalid, processing can be skipped altogether.
+            let event_id = common::event_id_from_payload(Some(&content_type), &data)?
+                .unwrap_or_else(EventId::new);
+
             let mut event_item = Item::new(ItemType::Event);
             event_item.set_payload(content_type, data);
 
@@ -114,6 +114,8 @@ pub enum ContentType {
     Json,
     /// application/x-msgpack
     MsgPack,
+    /// application/cbor
+    Cbor,
     /// application/octet-stream
     OctetStream,
     /// Any arbitrary content type not listed explicitly.
@@ -127,6 +129,7 @@ impl ContentType {
             Self::Text => "text/plain",
             Self::Json => "application/json",
             Self::MsgPack => "application/x-msgpack",
+            Self::Cbor => "application/cbor",
             Self::OctetStream => "application/octet-stream",
             Self::Other(ref other) => &other,
         }
@@ -137,6 +140,7 @@ impl ContentType {
             "text/plain" => Some(Self::Text),
             "application/json" => Some(Self::Json),
             "application/x-msgpack" => Some(Self::MsgPack),
+            "application/cbor" => Some(Self::Cbor),
             "application/octet-stream" => Some(Self::OctetStream),
             _ => None,
         }
@@ -164,6 +168,7 @@ impl Serialize for ContentType {
             Self::Text => "text/plain",
             Self::Json => "application/json",
             Self::MsgPack => "application/x-msgpack",
+            Self::Cbor => "application/cbor",
             Self::OctetStream => "application/octet-stream",
             Self::Other(ref other) => other,
         };
@@ -435,6 +440,7 @@ impl Item {
 
 pub type Items = SmallVec<[Item; 3]>;
 pub type ItemIter<'a> = std::slice::Iter<'a, Item>;
+pub type ItemIterMut<'a> = std::slice::IterMut<'a, Item>;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct EnvelopeHeaders<M = RequestMeta> {
@@ -627,6 +633,13 @@ impl Envelope {
         self.items.iter()
     }
 
+    /// Returns a mutable iterator over items in this envelope.
+    ///
+    /// Note that iteration order may change when using `take_item`.
+    pub fn items_mut(&mut self) -> ItemIterMut<'_> {
+        self.items.iter_mut()
+    }
+
     /// Returns the an option with a reference to the first item that matches
     /// the predicate, or None if the predicate is not matched by any item.
     pub fn get_item_by<F>(&self, mut pred: F) -> Option<&Item>
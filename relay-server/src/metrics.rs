@@ -193,6 +193,15 @@ pub enum RelayCounters {
     /// project id is extracted from the url path. Only projects with the id not already fetched
     /// are counted. Once the ProjectId is successfully cached it will be retained indefinitely.
     ProjectIdRequest,
+    /// Counts the quantity refunded to quotas.
+    ///
+    /// Events are refunded if they are dropped after being counted against quotas, for instance
+    /// because of an error during PII stripping, while forwarding the event or because of a
+    /// timeout. This metric is tagged with:
+    ///
+    ///  - `unit`: The quota unit, either `items` or `bytes`. For bytes, the size of the event is
+    ///    counted.
+    EventQuotaRefunded,
     /// Counts the number of times Relay started.
     /// This can be used to track unwanted restarts due to crashes or termination.
     ServerStarting,
@@ -233,6 +242,7 @@ impl CounterMetric for RelayCounters {
             RelayCounters::ProjectCacheHit => "project_cache.hit",
             RelayCounters::ProjectCacheMiss => "project_cache.miss",
             RelayCounters::ProjectIdRequest => "project_id.request",
+            RelayCounters::EventQuotaRefunded => "event.quota_refunded",
             RelayCounters::ServerStarting => "server.starting",
             #[cfg(feature = "processing")]
             RelayCounters::ProcessingEventProduced => "processing.event.produced",
@@ -53,6 +53,10 @@ pub enum ServerErrorKind {
     #[fail(display = "could not load the Geoip Db")]
     GeoIpError,
 
+    /// The source map directory could not be opened.
+    #[fail(display = "could not open the source map directory")]
+    SourceMapError,
+
     /// Configuration failed.
     #[fail(display = "configuration error")]
     ConfigError,
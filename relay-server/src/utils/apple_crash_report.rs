@@ -0,0 +1,540 @@
+//! Parsing of Apple crash reports.
+//!
+//! Apple crash reports are text reports written by the crash reporter on macOS and iOS. Relay
+//! extracts the exception, threads, registers and binary images from these reports, so that
+//! events are readable even if they are not symbolicated later on.
+
+use failure::Fail;
+use regex::Regex;
+use uuid::Uuid;
+
+use relay_general::protocol::{
+    Addr, AppleDebugImage, DebugImage, DebugMeta, Event, Exception, Frame, JsonLenientString,
+    Mechanism, RawStacktrace, RegVal, Stacktrace, Thread, ThreadId, Values,
+};
+use relay_general::types::{Annotated, Object};
+
+lazy_static::lazy_static! {
+    /// Start of a thread backtrace, such as `Thread 0 Crashed:: Dispatch queue: main`.
+    static ref THREAD_RE: Regex = Regex::new(r"^Thread (\d+)( Crashed)?:(?::\s+(.*))?$").unwrap();
+    /// Name of a thread, such as `Thread 0 name:  Dispatch queue: main`.
+    static ref THREAD_NAME_RE: Regex = Regex::new(r"^Thread (\d+) name:\s+(.*)$").unwrap();
+    /// Start of the register dump, such as `Thread 0 crashed with ARM Thread State (64-bit):`.
+    static ref THREAD_STATE_RE: Regex =
+        Regex::new(r"^Thread (\d+) crashed with .*Thread State").unwrap();
+    /// A stack frame, such as `0   libsystem_kernel.dylib  0x00007fff61bc6c2a mach_msg_trap + 10`.
+    static ref FRAME_RE: Regex =
+        Regex::new(r"^\d+\s+(.+?)\s+0x([0-9a-fA-F]+)\s+(.*)$").unwrap();
+    /// An unsymbolicated frame location, such as `0x10ab6e000 + 5716184`.
+    static ref IMAGE_OFFSET_RE: Regex = Regex::new(r"^0x([0-9a-fA-F]+) \+ \d+$").unwrap();
+    /// A symbolicated frame location, such as `mach_msg_trap + 10`.
+    static ref SYMBOL_OFFSET_RE: Regex = Regex::new(r"^(.+?) \+ \d+").unwrap();
+    /// A single register value, such as `rax: 0x0000000000000000`.
+    static ref REGISTER_RE: Regex = Regex::new(r"([a-z0-9]+):\s+0x([0-9a-fA-F]+)").unwrap();
+    /// A binary image, such as `0x10ab6e000 - 0x10b1fcfff +App x86_64 <uuid> /path/to/App`.
+    static ref BINARY_IMAGE_RE: Regex = Regex::new(
+        r"^\s*0x([0-9a-fA-F]+)\s*-\s*0x([0-9a-fA-F]+)\s+\+?(.+?)\s+(?:([a-z][a-z0-9_]*)\s+)?(?:\([^)]*\)\s+)?<([0-9a-fA-F-]+)>\s+(.+)$"
+    )
+    .unwrap();
+    /// A key-value pair in the header of the report, such as `Exception Type:  EXC_CRASH`.
+    static ref HEADER_RE: Regex = Regex::new(r"^([A-Za-z][A-Za-z ]*):\s*(.*)$").unwrap();
+}
+
+/// The mechanism type of exceptions created from Apple crash reports.
+///
+/// This must match the mechanism of the native placeholder, which tells the ingestion pipeline
+/// that this event needs to be processed.
+const MECHANISM_TYPE: &str = "applecrashreport";
+
+/// An error returned when an Apple crash report cannot be parsed.
+#[derive(Debug, Fail)]
+pub enum AppleCrashReportError {
+    /// The report does not contain any thread backtraces.
+    #[fail(display = "no threads in apple crash report")]
+    NoThreads,
+}
+
+/// The section of the crash report that is currently being parsed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Section {
+    Header,
+    ApplicationSpecificInformation,
+    Thread,
+    ThreadState,
+    BinaryImages,
+}
+
+/// A thread backtrace parsed from the crash report.
+#[derive(Debug, Default)]
+struct ParsedThread {
+    id: u64,
+    name: Option<String>,
+    crashed: bool,
+    frames: Vec<Annotated<Frame>>,
+}
+
+/// Information extracted from a text Apple crash report.
+#[derive(Debug, Default)]
+struct AppleCrashReport {
+    exception_type: Option<String>,
+    exception_subtype: Option<String>,
+    exception_codes: Option<String>,
+    application_specific_information: Vec<String>,
+    crashed_thread: Option<u64>,
+    threads: Vec<ParsedThread>,
+    registers: Object<RegVal>,
+    images: Vec<AppleDebugImage>,
+}
+
+impl AppleCrashReport {
+    /// Parses the text of an Apple crash report.
+    fn parse(text: &str) -> Result<Self, AppleCrashReportError> {
+        let mut report = Self::default();
+        let mut section = Section::Header;
+        let mut thread_name = None;
+
+        for line in text.lines() {
+            let line = line.trim_end();
+
+            if line.is_empty() {
+                if section != Section::BinaryImages {
+                    section = Section::Header;
+                }
+                continue;
+            }
+
+            if line == "Binary Images:" {
+                section = Section::BinaryImages;
+            } else if line == "Application Specific Information:" {
+                section = Section::ApplicationSpecificInformation;
+            } else if let Some(captures) = THREAD_NAME_RE.captures(line) {
+                thread_name = Some((captures[1].parse().unwrap_or(0), captures[2].to_owned()));
+            } else if let Some(captures) = THREAD_STATE_RE.captures(line) {
+                report.crashed_thread = captures[1].parse().ok().or(report.crashed_thread);
+                section = Section::ThreadState;
+            } else if let Some(captures) = THREAD_RE.captures(line) {
+                let id = captures[1].parse().unwrap_or(0);
+                let name = match thread_name.take() {
+                    Some((thread_id, name)) if thread_id == id => Some(name),
+                    _ => captures.get(3).map(|m| m.as_str().to_owned()),
+                };
+
+                report.threads.push(ParsedThread {
+                    id,
+                    name,
+                    crashed: captures.get(2).is_some(),
+                    frames: Vec::new(),
+                });
+
+                section = Section::Thread;
+            } else {
+                match section {
+                    Section::Header => report.parse_header(line),
+                    Section::ApplicationSpecificInformation => report
+                        .application_specific_information
+                        .push(line.trim().to_owned()),
+                    Section::Thread => report.parse_frame(line),
+                    Section::ThreadState => report.parse_registers(line),
+                    Section::BinaryImages => report.parse_image(line),
+                }
+            }
+        }
+
+        if report.threads.is_empty() {
+            return Err(AppleCrashReportError::NoThreads);
+        }
+
+        Ok(report)
+    }
+
+    fn parse_header(&mut self, line: &str) {
+        let captures = match HEADER_RE.captures(line) {
+            Some(captures) => captures,
+            None => return,
+        };
+
+        let value = captures[2].trim().to_owned();
+        match &captures[1] {
+            "Exception Type" => self.exception_type = Some(value),
+            "Exception Subtype" => self.exception_subtype = Some(value),
+            "Exception Codes" => self.exception_codes = Some(value),
+            "Crashed Thread" => {
+                let id = value
+                    .split_whitespace()
+                    .next()
+                    .and_then(|id| id.parse().ok());
+                self.crashed_thread = id.or(self.crashed_thread);
+            }
+            _ => (),
+        }
+    }
+
+    fn parse_frame(&mut self, line: &str) {
+        let captures = match FRAME_RE.captures(line) {
+            Some(captures) => captures,
+            None => return,
+        };
+
+        let instruction_addr = match u64::from_str_radix(&captures[2], 16) {
+            Ok(addr) => addr,
+            Err(_) => return,
+        };
+
+        let location = &captures[3];
+        let mut frame = Frame {
+            package: Annotated::new(captures[1].to_owned()),
+            instruction_addr: Annotated::new(Addr(instruction_addr)),
+            ..Frame::default()
+        };
+
+        if let Some(image) = IMAGE_OFFSET_RE.captures(location) {
+            let image_addr = u64::from_str_radix(&image[1], 16).ok().map(Addr);
+            frame.image_addr = Annotated::from(image_addr);
+        } else if let Some(symbol) = SYMBOL_OFFSET_RE.captures(location) {
+            frame.function = Annotated::new(symbol[1].to_owned());
+        } else {
+            frame.function = Annotated::new(location.to_owned());
+        }
+
+        if let Some(thread) = self.threads.last_mut() {
+            thread.frames.push(Annotated::new(frame));
+        }
+    }
+
+    fn parse_registers(&mut self, line: &str) {
+        for captures in REGISTER_RE.captures_iter(line) {
+            if let Ok(value) = u64::from_str_radix(&captures[2], 16) {
+                let name = captures[1].to_owned();
+                self.registers.insert(name, Annotated::new(RegVal(value)));
+            }
+        }
+    }
+
+    fn parse_image(&mut self, line: &str) {
+        let captures = match BINARY_IMAGE_RE.captures(line) {
+            Some(captures) => captures,
+            None => return,
+        };
+
+        let start = u64::from_str_radix(&captures[1], 16);
+        let end = u64::from_str_radix(&captures[2], 16);
+        let uuid = Uuid::parse_str(&captures[5]);
+
+        let (start, end, uuid) = match (start, end, uuid) {
+            (Ok(start), Ok(end), Ok(uuid)) if end >= start => (start, end, uuid),
+            _ => return,
+        };
+
+        // The end address is inclusive, so an image spanning the entire address space overflows.
+        let size = match (end - start).checked_add(1) {
+            Some(size) => size,
+            None => return,
+        };
+
+        self.images.push(AppleDebugImage {
+            name: Annotated::new(captures[6].to_owned()),
+            arch: Annotated::from(captures.get(4).map(|m| m.as_str().to_owned())),
+            image_addr: Annotated::new(Addr(start)),
+            image_size: Annotated::new(size),
+            uuid: Annotated::new(uuid),
+            ..AppleDebugImage::default()
+        });
+    }
+
+    /// Returns the identifier of the thread that crashed, if any.
+    fn crashed_thread(&self) -> Option<u64> {
+        self.crashed_thread.or_else(|| {
+            self.threads
+                .iter()
+                .find(|thread| thread.crashed)
+                .map(|thread| thread.id)
+        })
+    }
+
+    /// Creates the exception from the exception information of the report.
+    fn exception(&self) -> Exception {
+        let ty = match self.exception_type {
+            Some(ref ty) => ty.split_whitespace().next().unwrap_or(ty).to_owned(),
+            None => "AppleCrashReport".to_owned(),
+        };
+
+        let value = self
+            .exception_subtype
+            .clone()
+            .or_else(|| self.exception_codes.clone())
+            .or_else(|| {
+                Some(self.application_specific_information.join("\n"))
+                    .filter(|info| !info.is_empty())
+            });
+
+        Exception {
+            ty: Annotated::new(ty),
+            value: Annotated::from(value.map(JsonLenientString)),
+            mechanism: Annotated::new(Mechanism {
+                ty: Annotated::new(MECHANISM_TYPE.to_owned()),
+                handled: Annotated::new(false),
+                ..Mechanism::default()
+            }),
+            ..Exception::default()
+        }
+    }
+
+    /// Writes the exception, threads and debug images of this report into the event.
+    ///
+    /// The stack trace of the crashed thread is moved into the exception, along with the
+    /// registers of the crashed thread. Previous exceptions and threads are replaced.
+    fn apply_to_event(self, event: &mut Event) {
+        let crashed_thread = self.crashed_thread();
+        let mut exception = self.exception();
+        let mut registers = self.registers;
+        let mut threads = Vec::with_capacity(self.threads.len());
+
+        for parsed in self.threads {
+            let crashed = Some(parsed.id) == crashed_thread;
+
+            let mut frames = parsed.frames;
+            frames.reverse();
+
+            let stacktrace = if frames.is_empty() {
+                Annotated::empty()
+            } else {
+                let registers = if crashed && !registers.is_empty() {
+                    Annotated::new(std::mem::take(&mut registers))
+                } else {
+                    Annotated::empty()
+                };
+
+                Annotated::new(Stacktrace(RawStacktrace {
+                    frames: Annotated::new(frames),
+                    registers,
+                    ..RawStacktrace::default()
+                }))
+            };
+
+            let mut thread = Thread {
+                id: Annotated::new(ThreadId::Int(parsed.id)),
+                name: Annotated::from(parsed.name),
+                crashed: Annotated::new(crashed),
+                ..Thread::default()
+            };
+
+            if crashed {
+                exception.thread_id = Annotated::new(ThreadId::Int(parsed.id));
+                exception.stacktrace = stacktrace;
+            } else {
+                thread.stacktrace = stacktrace;
+            }
+
+            threads.push(Annotated::new(thread));
+        }
+
+        let exceptions = event
+            .exceptions
+            .value_mut()
+            .get_or_insert_with(Values::default)
+            .values
+            .value_mut()
+            .get_or_insert_with(Vec::new);
+
+        exceptions.clear();
+        exceptions.push(Annotated::new(exception));
+
+        event.threads = Annotated::new(Values::new(threads));
+
+        if !self.images.is_empty() {
+            let images = event
+                .debug_meta
+                .value_mut()
+                .get_or_insert_with(DebugMeta::default)
+                .images
+                .value_mut()
+                .get_or_insert_with(Vec::new);
+
+            images.extend(
+                self.images
+                    .into_iter()
+                    .map(|image| Annotated::new(DebugImage::Apple(Box::new(image)))),
+            );
+        }
+    }
+}
+
+/// Parses an Apple crash report and writes its contents into the event.
+///
+/// This replaces exceptions and threads of the event with the ones from the crash report and adds
+/// the binary images of the crash report to the debug meta. Frames are not symbolicated.
+pub fn merge_apple_crash_report(
+    event: &mut Event,
+    payload: &[u8],
+) -> Result<(), AppleCrashReportError> {
+    let text = String::from_utf8_lossy(payload);
+    let report = AppleCrashReport::parse(&text)?;
+    report.apply_to_event(event);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRASH_REPORT: &str = r#"Incident Identifier: 5C32DF84-31A0-43E7-87D0-239F7F594940
+CrashReporter Key:   TODO
+Hardware Model:      MacBookPro14,3
+Process:             YetAnotherMac [49028]
+Path:                /Users/USER/Desktop/YetAnotherMac.app/Contents/MacOS/YetAnotherMac
+Identifier:          com.YourCompany.GenericShooter
+Version:             4.21.1 (4.21.1)
+Code Type:           X86-64
+Parent Process:      launchd [1]
+
+Date/Time:           2019-01-09 17:42:22 +0000
+OS Version:          Mac OS X 10.14.0 (18A391)
+Report Version:      104
+
+Exception Type:  EXC_BAD_ACCESS (SIGSEGV)
+Exception Codes: KERN_INVALID_ADDRESS at 0x0000000000000000
+Crashed Thread:  0
+
+Application Specific Information:
+objc_msgSend() selector name: respondsToSelector:
+
+Thread 0 Crashed:: Dispatch queue: com.apple.main-thread
+0   YetAnotherMac                       0x000000010b0e1cd8 0x10ab6e000 + 5716184
+1   libsystem_kernel.dylib              0x00007fff61bc6c2a mach_msg_trap + 10
+
+Thread 1 name:  Background Worker
+Thread 1:
+0   libsystem_kernel.dylib              0x00007fff61bc6c2a mach_msg_trap + 10
+
+Thread 0 crashed with X86 Thread State (64-bit):
+  rax: 0x0000000000000000  rbx: 0x000000011b8bb6c8  rcx: 0x0000000000000000
+  rip: 0x000000010b0e1cd8  rfl: 0x0000000000010246  cr2: 0x0000000000000000
+
+Binary Images:
+       0x10ab6e000 -        0x10b1fcfff +YetAnotherMac x86_64  <7b1d5ef08d4d3e5da4d71ea2b1d6d58d> /Users/USER/Desktop/YetAnotherMac.app/Contents/MacOS/YetAnotherMac
+    0x7fff61bc6000 -     0x7fff61bf5fff  libsystem_kernel.dylib (4903.201.2) <E1C1D2A5-2BA2-3DC6-8B14-5C6E6E5C5C6D> /usr/lib/system/libsystem_kernel.dylib
+"#;
+
+    fn get_event() -> Event {
+        let mut event = Event::default();
+        merge_apple_crash_report(&mut event, CRASH_REPORT.as_bytes()).unwrap();
+        event
+    }
+
+    #[test]
+    fn test_merge_exception() {
+        let event = get_event();
+
+        let exceptions = event.exceptions.value().unwrap().values.value().unwrap();
+        assert_eq!(exceptions.len(), 1);
+
+        let exception = exceptions[0].value().unwrap();
+        assert_eq!(exception.ty.as_str(), Some("EXC_BAD_ACCESS"));
+        assert_eq!(
+            exception.value.value().map(|value| value.as_str()),
+            Some("KERN_INVALID_ADDRESS at 0x0000000000000000")
+        );
+        assert_eq!(exception.thread_id.value(), Some(&ThreadId::Int(0)));
+
+        let mechanism = exception.mechanism.value().unwrap();
+        assert_eq!(mechanism.ty.as_str(), Some(MECHANISM_TYPE));
+
+        // The crashed thread's stack trace is moved to the exception, with the oldest frame first.
+        let stacktrace = exception.stacktrace.value().unwrap();
+        let frames = stacktrace.frames.value().unwrap();
+        assert_eq!(frames.len(), 2);
+
+        let first = frames[0].value().unwrap();
+        assert_eq!(first.function.as_str(), Some("mach_msg_trap"));
+        assert_eq!(first.package.as_str(), Some("libsystem_kernel.dylib"));
+
+        let last = frames[1].value().unwrap();
+        assert_eq!(last.function.value(), None);
+        assert_eq!(last.instruction_addr.value(), Some(&Addr(0x10b0e_1cd8)));
+        assert_eq!(last.image_addr.value(), Some(&Addr(0x10ab_6e000)));
+
+        let registers = stacktrace.registers.value().unwrap();
+        assert_eq!(registers.len(), 6);
+        assert_eq!(
+            registers.get("rip").and_then(Annotated::value),
+            Some(&RegVal(0x10b0e_1cd8))
+        );
+    }
+
+    #[test]
+    fn test_merge_threads() {
+        let event = get_event();
+
+        let threads = event.threads.value().unwrap().values.value().unwrap();
+        assert_eq!(threads.len(), 2);
+
+        let crashed = threads[0].value().unwrap();
+        assert_eq!(crashed.crashed.value(), Some(&true));
+        assert_eq!(
+            crashed.name.as_str(),
+            Some("Dispatch queue: com.apple.main-thread")
+        );
+        assert!(crashed.stacktrace.value().is_none());
+
+        let background = threads[1].value().unwrap();
+        assert_eq!(background.id.value(), Some(&ThreadId::Int(1)));
+        assert_eq!(background.name.as_str(), Some("Background Worker"));
+        assert_eq!(background.crashed.value(), Some(&false));
+        assert!(background.stacktrace.value().is_some());
+    }
+
+    #[test]
+    fn test_merge_debug_images() {
+        let event = get_event();
+
+        let images = event.debug_meta.value().unwrap().images.value().unwrap();
+        assert_eq!(images.len(), 2);
+
+        let image = match images[0].value() {
+            Some(DebugImage::Apple(image)) => image,
+            _ => panic!("expected apple debug image"),
+        };
+
+        assert_eq!(image.arch.as_str(), Some("x86_64"));
+        assert_eq!(image.image_addr.value(), Some(&Addr(0x10ab_6e000)));
+        assert_eq!(image.image_size.value(), Some(&0x68_f000));
+        assert_eq!(
+            image.uuid.value(),
+            Some(&"7b1d5ef0-8d4d-3e5d-a4d7-1ea2b1d6d58d".parse().unwrap())
+        );
+
+        let image = match images[1].value() {
+            Some(DebugImage::Apple(image)) => image,
+            _ => panic!("expected apple debug image"),
+        };
+
+        assert_eq!(image.arch.value(), None);
+        assert_eq!(
+            image.name.as_str(),
+            Some("/usr/lib/system/libsystem_kernel.dylib")
+        );
+    }
+
+    #[test]
+    fn test_skip_overflowing_image() {
+        let report = format!(
+            "{}{}\n",
+            CRASH_REPORT,
+            "0x0 - 0xffffffffffffffff  overflow x86_64  <7b1d5ef08d4d3e5da4d71ea2b1d6d58d> /overflow"
+        );
+
+        let mut event = Event::default();
+        merge_apple_crash_report(&mut event, report.as_bytes()).unwrap();
+
+        let images = event.debug_meta.value().unwrap().images.value().unwrap();
+        assert_eq!(images.len(), 2);
+    }
+
+    #[test]
+    fn test_no_threads() {
+        let mut event = Event::default();
+        let result = merge_apple_crash_report(&mut event, b"Exception Type: EXC_CRASH\n");
+        assert!(result.is_err());
+        assert!(event.exceptions.value().is_none());
+    }
+}
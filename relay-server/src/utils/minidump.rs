@@ -0,0 +1,801 @@
+//! Extraction of basic information from minidumps.
+//!
+//! Relay does not walk stacks. It only reads the system info, exception, thread list and module
+//! list streams of a minidump, so that events carry structured information before they are
+//! symbolicated. See the Microsoft documentation of `MINIDUMP_HEADER` for the file format.
+
+use debugid::{CodeId, DebugId};
+use failure::Fail;
+
+use relay_general::protocol::{
+    Addr, Context, Contexts, DebugImage, DebugMeta, DeviceContext, Event, Exception,
+    JsonLenientString, LenientString, MachException, Mechanism, MechanismMeta, NativeDebugImage,
+    OsContext, PosixSignal, Thread, ThreadId, Values,
+};
+use relay_general::types::Annotated;
+
+/// The signature at the start of every minidump (`MDMP`).
+const MINIDUMP_SIGNATURE: u32 = 0x504d_444d;
+/// The version of the minidump format in the lower 16 bits of the header version.
+const MINIDUMP_VERSION: u32 = 0xa793;
+
+const STREAM_THREAD_LIST: u32 = 3;
+const STREAM_MODULE_LIST: u32 = 4;
+const STREAM_EXCEPTION: u32 = 6;
+const STREAM_SYSTEM_INFO: u32 = 7;
+
+/// Size of a `MINIDUMP_THREAD` entry in the thread list.
+const THREAD_SIZE: usize = 48;
+/// Size of a `MINIDUMP_MODULE` entry in the module list.
+const MODULE_SIZE: usize = 108;
+
+/// CodeView signature of PDB 7.0 records (`RSDS`), also used by Breakpad on macOS.
+const CV_SIGNATURE_PDB70: u32 = 0x5344_5352;
+/// CodeView signature of Breakpad ELF build id records (`BpEL`).
+const CV_SIGNATURE_ELF: u32 = 0x4270_454c;
+
+/// The mechanism type of exceptions created from minidumps.
+///
+/// This must match the mechanism of the native placeholder, which tells the ingestion pipeline
+/// that this event needs to be processed.
+const MECHANISM_TYPE: &str = "minidump";
+
+/// An error returned when a minidump cannot be parsed.
+#[derive(Debug, Fail)]
+pub enum MinidumpError {
+    /// The minidump header or stream directory is missing or malformed.
+    #[fail(display = "invalid minidump header")]
+    InvalidHeader,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let mut buf = [0; 2];
+    buf.copy_from_slice(data.get(offset..offset.checked_add(2)?)?);
+    Some(u16::from_le_bytes(buf))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(data.get(offset..offset.checked_add(4)?)?);
+    Some(u32::from_le_bytes(buf))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(data.get(offset..offset.checked_add(8)?)?);
+    Some(u64::from_le_bytes(buf))
+}
+
+/// The operating system family that wrote the minidump.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Os {
+    Windows,
+    MacOs,
+    Ios,
+    Linux,
+    Android,
+    Other(u32),
+}
+
+impl Os {
+    fn from_platform_id(platform_id: u32) -> Self {
+        match platform_id {
+            1 | 2 => Os::Windows,
+            0x8101 => Os::MacOs,
+            0x8102 => Os::Ios,
+            0x8201 => Os::Linux,
+            0x8203 => Os::Android,
+            other => Os::Other(other),
+        }
+    }
+
+    fn name(self) -> Option<&'static str> {
+        match self {
+            Os::Windows => Some("Windows"),
+            Os::MacOs => Some("macOS"),
+            Os::Ios => Some("iOS"),
+            Os::Linux => Some("Linux"),
+            Os::Android => Some("Android"),
+            Os::Other(_) => None,
+        }
+    }
+}
+
+/// Returns the name of a CPU architecture in a minidump's system info.
+fn cpu_arch(processor_architecture: u16) -> Option<&'static str> {
+    Some(match processor_architecture {
+        0 => "x86",
+        1 => "mips",
+        3 => "ppc",
+        5 => "arm",
+        9 => "x86_64",
+        12 | 0x8003 => "arm64",
+        0x8001 => "sparc",
+        0x8002 => "ppc64",
+        0x8004 => "mips64",
+        _ => return None,
+    })
+}
+
+/// Information from the system info stream.
+#[derive(Debug)]
+struct SystemInfo {
+    os: Os,
+    arch: Option<&'static str>,
+    version: (u32, u32, u32),
+    csd_version: Option<String>,
+}
+
+/// Information from the exception stream.
+#[derive(Debug)]
+struct ExceptionInfo {
+    thread_id: u32,
+    code: u32,
+    flags: u32,
+    address: u64,
+    parameters: Vec<u64>,
+}
+
+/// A module from the module list stream.
+#[derive(Debug)]
+struct Module {
+    base: u64,
+    size: u32,
+    timestamp: u32,
+    name: String,
+    code_id: Option<CodeId>,
+    debug_id: DebugId,
+    debug_file: Option<String>,
+}
+
+/// A parsed minidump header with access to its streams.
+struct Minidump<'a> {
+    data: &'a [u8],
+    streams: Vec<(u32, &'a [u8])>,
+}
+
+impl<'a> Minidump<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, MinidumpError> {
+        let signature = read_u32(data, 0).ok_or(MinidumpError::InvalidHeader)?;
+        let version = read_u32(data, 4).ok_or(MinidumpError::InvalidHeader)?;
+
+        if signature != MINIDUMP_SIGNATURE || version & 0xffff != MINIDUMP_VERSION {
+            return Err(MinidumpError::InvalidHeader);
+        }
+
+        let stream_count = read_u32(data, 8).ok_or(MinidumpError::InvalidHeader)? as usize;
+        let directory = read_u32(data, 12).ok_or(MinidumpError::InvalidHeader)? as usize;
+
+        let mut streams = Vec::new();
+        for index in 0..stream_count {
+            let entry = directory + index * 12;
+            let ty = read_u32(data, entry).ok_or(MinidumpError::InvalidHeader)?;
+            let size = read_u32(data, entry + 4).ok_or(MinidumpError::InvalidHeader)? as usize;
+            let rva = read_u32(data, entry + 8).ok_or(MinidumpError::InvalidHeader)? as usize;
+
+            // Skip streams that point outside of the file, such as in truncated minidumps.
+            if let Some(stream) = data.get(rva..rva.saturating_add(size)) {
+                streams.push((ty, stream));
+            }
+        }
+
+        Ok(Minidump { data, streams })
+    }
+
+    fn stream(&self, stream_type: u32) -> Option<&'a [u8]> {
+        self.streams
+            .iter()
+            .find(|(ty, _)| *ty == stream_type)
+            .map(|(_, stream)| *stream)
+    }
+
+    /// Reads a `MINIDUMP_STRING` at the given offset, which is encoded in UTF-16.
+    fn string(&self, rva: u32) -> Option<String> {
+        let rva = rva as usize;
+        let len = read_u32(self.data, rva)? as usize;
+        let bytes = self.data.get(rva + 4..(rva + 4).checked_add(len)?)?;
+
+        let chars: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+
+        Some(String::from_utf16_lossy(&chars))
+    }
+
+    fn system_info(&self) -> Option<SystemInfo> {
+        let stream = self.stream(STREAM_SYSTEM_INFO)?;
+        let csd_version = self
+            .string(read_u32(stream, 24)?)
+            .filter(|csd| !csd.is_empty());
+
+        Some(SystemInfo {
+            os: Os::from_platform_id(read_u32(stream, 20)?),
+            arch: cpu_arch(read_u16(stream, 0)?),
+            version: (
+                read_u32(stream, 8)?,
+                read_u32(stream, 12)?,
+                read_u32(stream, 16)?,
+            ),
+            csd_version,
+        })
+    }
+
+    fn exception(&self) -> Option<ExceptionInfo> {
+        let stream = self.stream(STREAM_EXCEPTION)?;
+        let parameter_count = read_u32(stream, 32)?.min(15) as usize;
+
+        Some(ExceptionInfo {
+            thread_id: read_u32(stream, 0)?,
+            code: read_u32(stream, 8)?,
+            flags: read_u32(stream, 12)?,
+            address: read_u64(stream, 24)?,
+            parameters: (0..parameter_count)
+                .filter_map(|index| read_u64(stream, 40 + index * 8))
+                .collect(),
+        })
+    }
+
+    fn thread_ids(&self) -> Vec<u32> {
+        let stream = match self.stream(STREAM_THREAD_LIST) {
+            Some(stream) => stream,
+            None => return Vec::new(),
+        };
+
+        // Cap the count by the stream size, since it is read from untrusted input.
+        let count = read_u32(stream, 0).unwrap_or(0) as usize;
+        let count = count.min(stream.len().saturating_sub(4) / THREAD_SIZE);
+        (0..count)
+            .filter_map(|index| read_u32(stream, 4 + index * THREAD_SIZE))
+            .collect()
+    }
+
+    fn modules(&self) -> Vec<Module> {
+        let stream = match self.stream(STREAM_MODULE_LIST) {
+            Some(stream) => stream,
+            None => return Vec::new(),
+        };
+
+        let count = read_u32(stream, 0).unwrap_or(0) as usize;
+        let count = count.min(stream.len().saturating_sub(4) / MODULE_SIZE);
+        (0..count)
+            .filter_map(|index| self.module(stream.get(4 + index * MODULE_SIZE..)?))
+            .collect()
+    }
+
+    fn module(&self, entry: &[u8]) -> Option<Module> {
+        let base = read_u64(entry, 0)?;
+        let size = read_u32(entry, 8)?;
+        let timestamp = read_u32(entry, 16)?;
+        let name = self.string(read_u32(entry, 20)?)?;
+
+        let cv_size = read_u32(entry, 76)? as usize;
+        let cv_rva = read_u32(entry, 80)? as usize;
+        let cv_record = self.data.get(cv_rva..cv_rva.checked_add(cv_size)?)?;
+
+        let (debug_id, code_id, debug_file) = match read_u32(cv_record, 0)? {
+            CV_SIGNATURE_PDB70 => {
+                let age = read_u32(cv_record, 20)?;
+                let debug_id = DebugId::from_guid_age(cv_record.get(4..20)?, age).ok()?;
+                let file = cv_record.get(24..)?;
+                let file = file.split(|b| *b == 0).next().unwrap_or_default();
+                let file = String::from_utf8_lossy(file).into_owned();
+                (debug_id, None, Some(file).filter(|file| !file.is_empty()))
+            }
+            CV_SIGNATURE_ELF => {
+                let build_id = cv_record.get(4..)?;
+                let mut guid = [0; 16];
+                let len = build_id.len().min(16);
+                guid[..len].copy_from_slice(&build_id[..len]);
+
+                let debug_id = DebugId::from_guid_age(&guid, 0).ok()?;
+                let code_id: String = build_id.iter().map(|b| format!("{:02x}", b)).collect();
+                (debug_id, Some(CodeId::new(code_id)), None)
+            }
+            _ => return None,
+        };
+
+        // Without a debug identifier, the module cannot be symbolicated.
+        if debug_id.is_nil() {
+            return None;
+        }
+
+        Some(Module {
+            base,
+            size,
+            timestamp,
+            name,
+            code_id,
+            debug_id,
+            debug_file,
+        })
+    }
+}
+
+/// Returns the name of an exception code on Windows.
+fn windows_exception_name(code: u32, parameters: &[u64]) -> Option<&'static str> {
+    Some(match code {
+        0xc000_0005 => match parameters.first() {
+            Some(0) => "EXCEPTION_ACCESS_VIOLATION_READ",
+            Some(1) => "EXCEPTION_ACCESS_VIOLATION_WRITE",
+            Some(8) => "EXCEPTION_ACCESS_VIOLATION_EXEC",
+            _ => "EXCEPTION_ACCESS_VIOLATION",
+        },
+        0x8000_0003 => "EXCEPTION_BREAKPOINT",
+        0xc000_001d => "EXCEPTION_ILLEGAL_INSTRUCTION",
+        0xc000_0094 => "EXCEPTION_INT_DIVIDE_BY_ZERO",
+        0xc000_00fd => "EXCEPTION_STACK_OVERFLOW",
+        0xc000_0409 => "EXCEPTION_STACK_BUFFER_OVERRUN",
+        0xc000_0374 => "EXCEPTION_HEAP_CORRUPTION",
+        _ => return None,
+    })
+}
+
+/// Returns the name of a POSIX signal.
+fn signal_name(signal: u32) -> Option<&'static str> {
+    Some(match signal {
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        _ => return None,
+    })
+}
+
+/// Returns the name of a Mach exception type.
+fn mach_exception_name(exception: u32) -> Option<&'static str> {
+    Some(match exception {
+        1 => "EXC_BAD_ACCESS",
+        2 => "EXC_BAD_INSTRUCTION",
+        3 => "EXC_ARITHMETIC",
+        4 => "EXC_EMULATION",
+        5 => "EXC_SOFTWARE",
+        6 => "EXC_BREAKPOINT",
+        10 => "EXC_CRASH",
+        11 => "EXC_RESOURCE",
+        12 => "EXC_GUARD",
+        _ => return None,
+    })
+}
+
+/// Creates the exception from the exception stream.
+///
+/// Depending on the operating system, the exception code is a Windows exception code, a POSIX
+/// signal or a Mach exception type. The latter two are also recorded in the mechanism.
+fn create_exception(info: &ExceptionInfo, os: Option<Os>) -> Exception {
+    let mut meta = MechanismMeta::default();
+
+    let name = match os {
+        Some(Os::Windows) => windows_exception_name(info.code, &info.parameters),
+        Some(Os::MacOs) | Some(Os::Ios) => {
+            let name = mach_exception_name(info.code);
+            meta.mach_exception = Annotated::new(MachException {
+                ty: Annotated::new(i64::from(info.code)),
+                code: Annotated::new(u64::from(info.flags)),
+                subcode: Annotated::new(info.address),
+                name: Annotated::from(name.map(str::to_owned)),
+            });
+            name
+        }
+        Some(Os::Linux) | Some(Os::Android) => {
+            let name = signal_name(info.code);
+            meta.signal = Annotated::new(PosixSignal {
+                number: Annotated::new(i64::from(info.code)),
+                code: Annotated::new(i64::from(info.flags)),
+                name: Annotated::from(name.map(str::to_owned)),
+                ..PosixSignal::default()
+            });
+            name
+        }
+        _ => None,
+    };
+
+    let ty = match name {
+        Some(name) => name.to_owned(),
+        None => format!("0x{:x}", info.code),
+    };
+
+    Exception {
+        value: Annotated::new(JsonLenientString(format!("Fatal Error: {}", ty))),
+        ty: Annotated::new(ty),
+        thread_id: Annotated::new(ThreadId::Int(info.thread_id.into())),
+        mechanism: Annotated::new(Mechanism {
+            ty: Annotated::new(MECHANISM_TYPE.to_owned()),
+            handled: Annotated::new(false),
+            meta: Annotated::new(meta),
+            ..Mechanism::default()
+        }),
+        ..Exception::default()
+    }
+}
+
+/// Creates a debug image for a module.
+fn create_image(module: Module, os: Option<Os>, arch: Option<&str>) -> DebugImage {
+    let code_id = match (module.code_id, os) {
+        (Some(code_id), _) => Some(code_id),
+        (None, Some(Os::Windows)) => Some(CodeId::new(format!(
+            "{:08X}{:x}",
+            module.timestamp, module.size
+        ))),
+        (None, _) => None,
+    };
+
+    let image = Box::new(NativeDebugImage {
+        code_id: Annotated::from(code_id),
+        code_file: Annotated::new(module.name.into()),
+        debug_id: Annotated::new(module.debug_id),
+        debug_file: Annotated::from(module.debug_file.map(Into::into)),
+        arch: Annotated::from(arch.map(str::to_owned)),
+        image_addr: Annotated::new(Addr(module.base)),
+        image_size: Annotated::new(module.size.into()),
+        ..NativeDebugImage::default()
+    });
+
+    match os {
+        Some(Os::Windows) => DebugImage::Pe(image),
+        Some(Os::MacOs) | Some(Os::Ios) => DebugImage::MachO(image),
+        _ => DebugImage::Elf(image),
+    }
+}
+
+/// Writes the operating system and CPU architecture into the contexts of the event.
+///
+/// Values sent by the client take precedence over the ones in the minidump.
+fn merge_system_info(event: &mut Event, system_info: &SystemInfo) {
+    let contexts = event.contexts.get_or_insert_with(Contexts::default);
+
+    let os_context = contexts.get_or_insert_with(OsContext::default_key(), || {
+        Context::Os(Box::new(OsContext::default()))
+    });
+
+    if let Context::Os(os_context) = os_context {
+        if let Some(name) = system_info.os.name() {
+            os_context.name.get_or_insert_with(|| name.to_owned());
+        }
+
+        let is_linux = match system_info.os {
+            Os::Linux | Os::Android => true,
+            _ => false,
+        };
+
+        // Linux does not write a version into the system info. Instead, the CSD version contains
+        // the kernel information, such as "Linux 4.15.0-60-generic #67-Ubuntu SMP ...".
+        let version = match (system_info.version, &system_info.csd_version) {
+            ((0, 0, 0), Some(csd)) if is_linux => csd.split_whitespace().nth(1).map(str::to_owned),
+            ((0, 0, 0), _) => None,
+            ((major, minor, build), _) => Some(format!("{}.{}.{}", major, minor, build)),
+        };
+
+        if let Some(version) = version {
+            os_context.version.get_or_insert_with(|| version);
+        }
+
+        if let Some(ref csd) = system_info.csd_version {
+            if is_linux {
+                os_context.kernel_version.get_or_insert_with(|| csd.clone());
+            } else {
+                os_context
+                    .build
+                    .get_or_insert_with(|| LenientString(csd.clone()));
+            }
+        }
+    }
+
+    if let Some(arch) = system_info.arch {
+        let device_context = contexts.get_or_insert_with(DeviceContext::default_key(), || {
+            Context::Device(Box::new(DeviceContext::default()))
+        });
+
+        if let Context::Device(device_context) = device_context {
+            device_context.arch.get_or_insert_with(|| arch.to_owned());
+        }
+    }
+}
+
+/// Parses a minidump and writes its basic information into the event.
+///
+/// This adds the operating system and CPU architecture to the contexts, replaces the exceptions
+/// and threads of the event, and adds the loaded modules to the debug meta. Stack traces are not
+/// extracted, which is left to symbolication.
+pub fn merge_minidump(event: &mut Event, payload: &[u8]) -> Result<(), MinidumpError> {
+    let minidump = Minidump::parse(payload)?;
+    let system_info = minidump.system_info();
+    let os = system_info.as_ref().map(|info| info.os);
+    let arch = system_info.as_ref().and_then(|info| info.arch);
+
+    if let Some(ref system_info) = system_info {
+        merge_system_info(event, system_info);
+    }
+
+    let exception = minidump.exception();
+    if let Some(ref exception) = exception {
+        let exceptions = event
+            .exceptions
+            .value_mut()
+            .get_or_insert_with(Values::default)
+            .values
+            .value_mut()
+            .get_or_insert_with(Vec::new);
+
+        exceptions.clear();
+        exceptions.push(Annotated::new(create_exception(exception, os)));
+    }
+
+    let thread_ids = minidump.thread_ids();
+    if !thread_ids.is_empty() {
+        let crashed_thread = exception.as_ref().map(|exception| exception.thread_id);
+        let threads = thread_ids
+            .into_iter()
+            .map(|id| {
+                Annotated::new(Thread {
+                    id: Annotated::new(ThreadId::Int(id.into())),
+                    crashed: Annotated::new(Some(id) == crashed_thread),
+                    ..Thread::default()
+                })
+            })
+            .collect();
+
+        event.threads = Annotated::new(Values::new(threads));
+    }
+
+    let modules = minidump.modules();
+    if !modules.is_empty() {
+        let images = event
+            .debug_meta
+            .value_mut()
+            .get_or_insert_with(DebugMeta::default)
+            .images
+            .value_mut()
+            .get_or_insert_with(Vec::new);
+
+        images.extend(
+            modules
+                .into_iter()
+                .map(|module| Annotated::new(create_image(module, os, arch))),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a minidump of a Windows process that crashed writing to `0x1000`.
+    fn windows_minidump() -> Vec<u8> {
+        fn utf16(string: &str) -> Vec<u8> {
+            let mut bytes = (string.len() as u32 * 2).to_le_bytes().to_vec();
+            bytes.extend(string.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()));
+            bytes
+        }
+
+        // Offsets of the streams and data following the header and stream directory.
+        let system_info = 80u32;
+        let exception = system_info + 56;
+        let thread_list = exception + 168;
+        let module_list = thread_list + 4 + 2 * 48;
+        let module_name = module_list + 4 + 108;
+        let cv_record = module_name + 24;
+        let csd_version = cv_record + 32;
+
+        let mut data = Vec::new();
+
+        // Header and stream directory
+        data.extend(&MINIDUMP_SIGNATURE.to_le_bytes());
+        data.extend(&MINIDUMP_VERSION.to_le_bytes());
+        data.extend(&4u32.to_le_bytes());
+        data.extend(&32u32.to_le_bytes());
+        data.extend(&[0; 16]);
+        for (ty, size, rva) in &[
+            (STREAM_SYSTEM_INFO, 56, system_info),
+            (STREAM_EXCEPTION, 168, exception),
+            (STREAM_THREAD_LIST, 100, thread_list),
+            (STREAM_MODULE_LIST, 112, module_list),
+        ] {
+            data.extend(&ty.to_le_bytes());
+            data.extend(&(*size as u32).to_le_bytes());
+            data.extend(&rva.to_le_bytes());
+        }
+
+        // System info: x86_64, Windows 10.0.19041
+        data.extend(&9u16.to_le_bytes());
+        data.extend(&[0; 6]);
+        data.extend(&10u32.to_le_bytes());
+        data.extend(&0u32.to_le_bytes());
+        data.extend(&19041u32.to_le_bytes());
+        data.extend(&2u32.to_le_bytes());
+        data.extend(&csd_version.to_le_bytes());
+        data.extend(&[0; 28]);
+
+        // Exception: access violation writing to 0x1000 on thread 1234
+        data.extend(&1234u32.to_le_bytes());
+        data.extend(&[0; 4]);
+        data.extend(&0xc000_0005u32.to_le_bytes());
+        data.extend(&[0; 12]);
+        data.extend(&0x1000u64.to_le_bytes());
+        data.extend(&2u32.to_le_bytes());
+        data.extend(&[0; 4]);
+        data.extend(&1u64.to_le_bytes());
+        data.extend(&0x1000u64.to_le_bytes());
+        data.extend(&[0; 13 * 8 + 8]);
+
+        // Thread list
+        data.extend(&2u32.to_le_bytes());
+        for thread_id in &[1234u32, 5678] {
+            data.extend(&thread_id.to_le_bytes());
+            data.extend(&[0; 44]);
+        }
+
+        // Module list
+        data.extend(&1u32.to_le_bytes());
+        data.extend(&0x1_4000_0000u64.to_le_bytes());
+        data.extend(&0x10000u32.to_le_bytes());
+        data.extend(&0u32.to_le_bytes());
+        data.extend(&0x5f00_0000u32.to_le_bytes());
+        data.extend(&module_name.to_le_bytes());
+        data.extend(&[0; 52]);
+        data.extend(&32u32.to_le_bytes());
+        data.extend(&cv_record.to_le_bytes());
+        data.extend(&[0; 24]);
+
+        // Module name, CodeView record and CSD version
+        data.extend(utf16("C:\\app.exe"));
+        data.extend(&CV_SIGNATURE_PDB70.to_le_bytes());
+        data.extend((0..16).collect::<Vec<u8>>());
+        data.extend(&1u32.to_le_bytes());
+        data.extend(b"app.pdb\0");
+        data.extend(utf16("Service Pack 1"));
+
+        data
+    }
+
+    fn get_event() -> Event {
+        let mut event = Event::default();
+        merge_minidump(&mut event, &windows_minidump()).unwrap();
+        event
+    }
+
+    fn get_context<'a>(event: &'a Event, key: &str) -> &'a Context {
+        let contexts = event.contexts.value().expect("missing contexts");
+        match contexts.get(key).and_then(Annotated::value) {
+            Some(context) => &context.0,
+            None => panic!("missing {} context", key),
+        }
+    }
+
+    #[test]
+    fn test_merge_system_info() {
+        let event = get_event();
+        let os = match get_context(&event, OsContext::default_key()) {
+            Context::Os(os) => os,
+            _ => panic!("expected os context"),
+        };
+
+        assert_eq!(os.name.as_str(), Some("Windows"));
+        assert_eq!(os.version.as_str(), Some("10.0.19041"));
+        assert_eq!(os.build.as_str(), Some("Service Pack 1"));
+
+        let device = match get_context(&event, DeviceContext::default_key()) {
+            Context::Device(device) => device,
+            _ => panic!("expected device context"),
+        };
+
+        assert_eq!(device.arch.as_str(), Some("x86_64"));
+    }
+
+    #[test]
+    fn test_keep_client_contexts() {
+        let mut event = Event::default();
+        let mut os = OsContext::default();
+        os.version = Annotated::new("10.0.19042".to_owned());
+
+        let mut contexts = Contexts::new();
+        contexts.add(Context::Os(Box::new(os)));
+        event.contexts = Annotated::new(contexts);
+
+        merge_minidump(&mut event, &windows_minidump()).unwrap();
+
+        let os = match get_context(&event, OsContext::default_key()) {
+            Context::Os(os) => os,
+            _ => panic!("expected os context"),
+        };
+
+        assert_eq!(os.name.as_str(), Some("Windows"));
+        assert_eq!(os.version.as_str(), Some("10.0.19042"));
+    }
+
+    #[test]
+    fn test_merge_exception_and_threads() {
+        let event = get_event();
+
+        let exceptions = event.exceptions.value().unwrap().values.value().unwrap();
+        assert_eq!(exceptions.len(), 1);
+
+        let exception = exceptions[0].value().unwrap();
+        assert_eq!(
+            exception.ty.as_str(),
+            Some("EXCEPTION_ACCESS_VIOLATION_WRITE")
+        );
+        assert_eq!(
+            exception.value.value().map(JsonLenientString::as_str),
+            Some("Fatal Error: EXCEPTION_ACCESS_VIOLATION_WRITE")
+        );
+        assert_eq!(exception.thread_id.value(), Some(&ThreadId::Int(1234)));
+
+        let mechanism = exception.mechanism.value().unwrap();
+        assert_eq!(mechanism.ty.as_str(), Some(MECHANISM_TYPE));
+        assert_eq!(mechanism.handled.value(), Some(&false));
+
+        let threads = event.threads.value().unwrap().values.value().unwrap();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].value().unwrap().crashed.value(), Some(&true));
+        assert_eq!(
+            threads[1].value().unwrap().id.value(),
+            Some(&ThreadId::Int(5678))
+        );
+        assert_eq!(threads[1].value().unwrap().crashed.value(), Some(&false));
+    }
+
+    #[test]
+    fn test_merge_modules() {
+        let event = get_event();
+
+        let images = event.debug_meta.value().unwrap().images.value().unwrap();
+        assert_eq!(images.len(), 1);
+
+        let image = match images[0].value() {
+            Some(DebugImage::Pe(image)) => image,
+            _ => panic!("expected pe debug image"),
+        };
+
+        assert_eq!(
+            image.code_file.value().map(|path| path.as_str()),
+            Some("C:\\app.exe")
+        );
+        assert_eq!(
+            image.code_id.value(),
+            Some(&CodeId::new("5F00000010000".to_owned()))
+        );
+        assert_eq!(
+            image.debug_id.value(),
+            Some(&"03020100-0504-0706-0809-0a0b0c0d0e0f-1".parse().unwrap())
+        );
+        assert_eq!(
+            image.debug_file.value().map(|path| path.as_str()),
+            Some("app.pdb")
+        );
+        assert_eq!(image.arch.as_str(), Some("x86_64"));
+        assert_eq!(image.image_addr.value(), Some(&Addr(0x1_4000_0000)));
+        assert_eq!(image.image_size.value(), Some(&0x10000));
+    }
+
+    #[test]
+    fn test_excessive_counts() {
+        // Overwrite the thread and module counts with values exceeding their streams.
+        let mut data = windows_minidump();
+        data[304..308].copy_from_slice(&u32::MAX.to_le_bytes());
+        data[404..408].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut event = Event::default();
+        merge_minidump(&mut event, &data).unwrap();
+
+        let threads = event.threads.value().unwrap().values.value().unwrap();
+        assert_eq!(threads.len(), 2);
+
+        let images = event.debug_meta.value().unwrap().images.value().unwrap();
+        assert_eq!(images.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut event = Event::default();
+        assert!(merge_minidump(&mut event, b"MDMP").is_err());
+        assert!(merge_minidump(&mut event, b"not a minidump at all").is_err());
+        assert!(event.exceptions.value().is_none());
+    }
+}
@@ -8,6 +8,10 @@ mod request;
 mod shutdown;
 mod timer;
 
+#[cfg(feature = "processing")]
+mod apple_crash_report;
+#[cfg(feature = "processing")]
+mod minidump;
 #[cfg(feature = "processing")]
 mod unreal;
 
@@ -21,5 +25,9 @@ pub use self::request::*;
 pub use self::shutdown::*;
 pub use self::timer::*;
 
+#[cfg(feature = "processing")]
+pub use self::apple_crash_report::*;
+#[cfg(feature = "processing")]
+pub use self::minidump::*;
 #[cfg(feature = "processing")]
 pub use self::unreal::*;
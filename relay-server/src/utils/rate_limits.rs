@@ -2,12 +2,16 @@ use std::fmt::Write;
 
 use relay_quotas::{
     DataCategories, DataCategory, ItemScoping, QuotaScope, RateLimit, RateLimitScope, RateLimits,
+    ReasonCode,
 };
 
 /// Name of the rate limits header.
 pub const RATE_LIMITS_HEADER: &str = "X-Sentry-Rate-Limits";
 
 /// Formats the `X-Sentry-Rate-Limits` header.
+///
+/// Each rate limit is formatted as `retry_after:categories:scope:reason_code`, where categories are
+/// separated by semicolons. The reason code is omitted if the rate limit does not have one.
 pub fn format_rate_limits(rate_limits: &RateLimits) -> String {
     let mut header = String::new();
 
@@ -26,6 +30,10 @@ pub fn format_rate_limits(rate_limits: &RateLimits) -> String {
         }
 
         write!(header, ":{}", rate_limit.scope.name()).ok();
+
+        if let Some(ref reason_code) = rate_limit.reason_code {
+            write!(header, ":{}", reason_code.as_str()).ok();
+        }
     }
 
     header
@@ -56,13 +64,23 @@ pub fn parse_rate_limits(scoping: &ItemScoping, string: &str) -> RateLimits {
             }
         }
 
+        // Skip rate limits for scopes that do not apply to this item, such as a release scope if
+        // the item does not have a release.
         let quota_scope = QuotaScope::from_name(components.next().unwrap_or(""));
-        let scope = RateLimitScope::for_quota(scoping, quota_scope);
+        let scope = match RateLimitScope::for_quota(scoping, quota_scope) {
+            Some(scope) => scope,
+            None => continue,
+        };
+
+        let reason_code = components
+            .next()
+            .filter(|code| !code.is_empty())
+            .map(ReasonCode::new);
 
         rate_limits.add(RateLimit {
             categories,
             scope,
-            reason_code: None,
+            reason_code,
             retry_after,
         });
     }
@@ -109,6 +127,22 @@ mod tests {
         assert_eq!(formatted, expected);
     }
 
+    #[test]
+    fn test_format_rate_limits_reason_code() {
+        let mut rate_limits = RateLimits::new();
+
+        rate_limits.add(RateLimit {
+            categories: get_test_categories(),
+            scope: RateLimitScope::Key("a94ae32be2584e0bbd7a4cbb95971fee".to_owned()),
+            reason_code: Some(ReasonCode::new("my_quota")),
+            retry_after: RetryAfter::from_secs(42),
+        });
+
+        let formatted = format_rate_limits(&rate_limits);
+        let expected = "42:transaction;security:key:my_quota";
+        assert_eq!(formatted, expected);
+    }
+
     #[test]
     fn test_parse_invalid_rate_limits() {
         let scoping = ItemScoping {
@@ -117,6 +151,11 @@ mod tests {
             project_id: ProjectId::new(21),
             public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(17),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         };
 
         assert!(parse_rate_limits(&scoping, "").is_ok());
@@ -132,6 +171,11 @@ mod tests {
             project_id: ProjectId::new(21),
             public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
             key_id: Some(17),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
         };
 
         // contains "foobar", an unknown scope that should be mapped to Unknown
@@ -160,4 +204,60 @@ mod tests {
         assert_eq!(42, rate_limits[0].retry_after.remaining_seconds());
         assert_eq!(4711, rate_limits[1].retry_after.remaining_seconds());
     }
+
+    #[test]
+    fn test_parse_rate_limits_reason_code() {
+        let scoping = ItemScoping {
+            category: DataCategory::Unknown, // irrelevant for this test
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
+            key_id: Some(17),
+            release: None,
+            environment: None,
+            user: None,
+            issue: None,
+            bytes: 0,
+        };
+
+        let formatted = "42::organization:my_quota, 42::project:";
+        let rate_limits: Vec<RateLimit> =
+            parse_rate_limits(&scoping, formatted).into_iter().collect();
+
+        assert_eq!(
+            rate_limits[0].reason_code,
+            Some(ReasonCode::new("my_quota"))
+        );
+        assert_eq!(rate_limits[1].reason_code, None);
+    }
+
+    #[test]
+    fn test_parse_rate_limits_item_scopes() {
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            public_key: "a94ae32be2584e0bbd7a4cbb95971fee".to_owned(),
+            key_id: Some(17),
+            release: Some("1.0.0".to_owned()),
+            environment: Some("loadtest".to_owned()),
+            user: None,
+            issue: None,
+            bytes: 0,
+        };
+
+        // The user is not known, so the user rate limit is skipped.
+        let formatted = "60:error:release, 60:error:environment, 60:error:user";
+        let rate_limits: Vec<RateLimit> =
+            parse_rate_limits(&scoping, formatted).into_iter().collect();
+
+        let scopes: Vec<_> = rate_limits.iter().map(|limit| &limit.scope).collect();
+        assert_eq!(
+            scopes,
+            vec![
+                &RateLimitScope::Release("1.0.0".to_owned()),
+                &RateLimitScope::Environment("loadtest".to_owned()),
+            ]
+        );
+    }
 }
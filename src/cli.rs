@@ -9,7 +9,7 @@ use failure::{err_msg, Error};
 
 use relay_common::{LogError, Uuid};
 use relay_config::{Config, Credentials, MinimalConfig, RelayMode};
-use relay_general::pii::{PiiConfig, PiiProcessor};
+use relay_general::pii::{explain_pii, PiiConfig, PiiProcessor};
 use relay_general::processor::{process_value, ProcessingState};
 use relay_general::protocol::Event;
 use relay_general::store::{StoreConfig, StoreProcessor};
@@ -316,6 +316,23 @@ pub fn process_event<'a>(matches: &ArgMatches<'a>) -> Result<(), Error> {
     let stdin = io::stdin();
     stdin.lock().read_to_end(&mut event_json)?;
     let mut event = EventV8::from_json_bytes(&event_json[..])?;
+
+    if matches.is_present("explain") {
+        // `--explain` requires `--pii-config`, which is enforced by clap.
+        let compiled = pii_config.as_ref().unwrap().compiled();
+        let explanations = explain_pii(&compiled, &event)?;
+
+        if matches.is_present("debug") {
+            println!("{:#?}", explanations);
+        } else if matches.is_present("pretty") {
+            println!("{}", serde_json::to_string_pretty(&explanations)?);
+        } else {
+            println!("{}", serde_json::to_string(&explanations)?);
+        }
+
+        return Ok(());
+    }
+
     if let Some(ref pii_config) = pii_config {
         let compiled = pii_config.compiled();
         let mut processor = PiiProcessor::new(&compiled);
@@ -182,6 +182,16 @@ pub fn make_app() -> App<'static, 'static> {
                     Arg::with_name("store")
                         .long("store")
                         .help("Run through store normalization"),
+                )
+                .arg(
+                    Arg::with_name("explain")
+                        .long("explain")
+                        .requires("pii_config")
+                        .conflicts_with("store")
+                        .help(
+                            "Print which PII rules apply to which fields instead of \
+                             the processed event",
+                        ),
                 ),
         )
         .subcommand(
@@ -92,3 +92,66 @@ def test_security_report(mini_sentry, relay, test_case, json_fixture_provider):
     expected_evt = fixture_provider.load(test_name, ext)
 
     assert event == expected_evt
+
+
+def test_reporting_api_batch(mini_sentry, relay):
+    proj_id = 42
+    relay = relay(mini_sentry)
+    relay.wait_relay_healthcheck()
+    mini_sentry.project_configs[proj_id] = mini_sentry.full_project_config()
+
+    reports = [
+        {
+            "type": "network-error",
+            "age": 29,
+            "url": "https://example.com/about/",
+            "user_agent": "Mozilla/5.0",
+            "body": {
+                "sampling_fraction": 1.0,
+                "server_ip": "192.0.2.1",
+                "protocol": "h2",
+                "method": "GET",
+                "status_code": 0,
+                "elapsed_time": 823,
+                "phase": "connection",
+                "type": "tcp.timed_out",
+            },
+        },
+        {
+            "type": "deprecation",
+            "age": 27,
+            "url": "https://example.com/",
+            "user_agent": "Mozilla/5.0",
+            "body": {
+                "id": "websql",
+                "message": "WebSQL is deprecated",
+                "sourceFile": "https://example.com/index.js",
+                "lineNumber": 1234,
+                "columnNumber": 42,
+            },
+        },
+    ]
+
+    relay.send_security_report(
+        project_id=proj_id,
+        content_type="application/reports+json",
+        payload=reports,
+        release="01d5c3165d9fbc5c8bdcf9550a1d6793a80fc02b",
+        environment="production",
+    )
+
+    events = [
+        mini_sentry.captured_events.get(timeout=1).get_event()
+        for _ in range(len(reports))
+    ]
+    events.sort(key=lambda event: event["type"])
+
+    deprecation, nel = events
+    assert deprecation["type"] == "default"
+    assert deprecation["logentry"]["formatted"] == "WebSQL is deprecated"
+    assert deprecation["culprit"] == "https://example.com/index.js:1234:42"
+
+    assert nel["type"] == "nel"
+    assert nel["nel"]["type"] == "tcp.timed_out"
+    assert nel["release"] == "01d5c3165d9fbc5c8bdcf9550a1d6793a80fc02b"
+    assert mini_sentry.captured_events.empty()